@@ -0,0 +1,863 @@
+use crate::canvas::*;
+use crate::config::ServerConfig;
+use crate::matrix::DomMatrix;
+use crate::result::*;
+use crate::style::Filter;
+use crate::text_layout::*;
+use actix_rt::System;
+use std::future::Future;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{mpsc, Arc};
+use std::thread;
+use std::time::Duration;
+use tokio::runtime::Handle;
+use tokio::time::{delay_for, timeout};
+use uuid::Uuid;
+
+// the server's runtime, run on its own thread until every generator and canvas using it is dropped
+struct Runtime {
+    handle: Handle,
+    system: System,
+    thread: Option<thread::JoinHandle<()>>,
+    // canvases dropped but not yet destroyed in the browser
+    closing: Arc<AtomicUsize>,
+}
+
+impl Drop for Runtime {
+    // gives dropped canvases a moment to be destroyed, their messages would be lost once the system stops
+    fn drop(&mut self) {
+        let closing = self.closing.clone();
+        self.handle.block_on(async move {
+            let closed = async {
+                while closing.load(Ordering::SeqCst) > 0 {
+                    delay_for(Duration::from_millis(10)).await;
+                }
+            };
+            timeout(Duration::from_secs(5), closed).await.unwrap_or(())
+        });
+        self.system.stop();
+        if let Some(thread) = self.thread.take() {
+            thread.join().unwrap_or(());
+        }
+    }
+}
+
+// starts the server on a runtime thread of its own, so it can be used without a tokio runtime or actix system.
+// none of the blocking calls may be made from inside an async runtime
+pub fn start_server(web_addr: &str, websocket_addr: &str) -> Result<BlockingCanvasGenerator> {
+    start_server_with_config(ServerConfig::new(web_addr, websocket_addr))
+}
+
+pub fn start_server_with_config(config: ServerConfig) -> Result<BlockingCanvasGenerator> {
+    let (sender, receiver) = mpsc::channel();
+    let thread = thread::Builder::new()
+        .name("remote-canvas".to_string())
+        .spawn(move || {
+            let mut system = System::new("remote-canvas");
+            let started = system.block_on(async move {
+                crate::start_server_with_config(config)
+                    .map(|generator| (generator, Handle::current(), System::current()))
+            });
+            let failed = started.is_err();
+            sender.send(started).unwrap_or(());
+            if !failed {
+                system.run().unwrap_or(());
+            }
+        })?;
+    let (generator, handle, system) = receiver
+        .recv()
+        .map_err(|_| canvas_error!("server thread exited before starting") as Error)??;
+    Ok(BlockingCanvasGenerator {
+        generator,
+        runtime: Arc::new(Runtime {
+            handle,
+            system,
+            thread: Some(thread),
+            closing: Arc::new(AtomicUsize::new(0)),
+        }),
+    })
+}
+
+#[derive(Clone)]
+pub struct BlockingCanvasGenerator {
+    generator: CanvasGenerator,
+    runtime: Arc<Runtime>,
+}
+
+impl BlockingCanvasGenerator {
+    pub fn with_text_metrics_cache(mut self, capacity: usize) -> BlockingCanvasGenerator {
+        self.generator = self.generator.with_text_metrics_cache(capacity);
+        self
+    }
+
+    pub fn with_creation_timeout(mut self, timeout: Duration) -> BlockingCanvasGenerator {
+        self.generator = self.generator.with_creation_timeout(timeout);
+        self
+    }
+
+    pub fn with_max_pending_creations(mut self, max: usize) -> BlockingCanvasGenerator {
+        self.generator = self.generator.with_max_pending_creations(max);
+        self
+    }
+
+    pub fn get_pending_creations(&self) -> usize {
+        self.generator.get_pending_creations()
+    }
+
+    pub fn wait_for_browsers(&self, count: usize, timeout: Duration) -> Result<()> {
+        self.runtime
+            .handle
+            .block_on(self.generator.wait_for_browsers(count, timeout))
+    }
+
+    fn wrap(&self, canvas: Canvas2D) -> BlockingCanvas2D {
+        BlockingCanvas2D {
+            canvas: Some(canvas),
+            runtime: self.runtime.clone(),
+        }
+    }
+
+    pub fn make_2d_canvas(
+        &self,
+        data: ContextData2D,
+        width: u32,
+        height: u32,
+    ) -> Result<BlockingCanvas2D> {
+        let canvas = self
+            .runtime
+            .handle
+            .block_on(self.generator.make_2d_canvas(data, width, height))?;
+        Ok(self.wrap(canvas))
+    }
+
+    pub fn register_font<S: Into<FontSource>>(
+        &self,
+        family: &str,
+        source: S,
+        descriptors: FontDescriptors,
+    ) -> Result<()> {
+        self.runtime
+            .handle
+            .block_on(self.generator.register_font(family, source, descriptors))
+    }
+
+    pub fn make_2d_canvas_near(
+        &self,
+        data: ContextData2D,
+        width: u32,
+        height: u32,
+        near: &BlockingCanvas2D,
+    ) -> Result<BlockingCanvas2D> {
+        let canvas = self
+            .runtime
+            .handle
+            .block_on(
+                self.generator
+                    .make_2d_canvas_near(data, width, height, near.canvas()),
+            )?;
+        Ok(self.wrap(canvas))
+    }
+}
+
+// a Canvas2D whose methods block until the browser has answered
+pub struct BlockingCanvas2D {
+    // only None once closed
+    canvas: Option<Canvas2D>,
+    runtime: Arc<Runtime>,
+}
+
+#[allow(clippy::too_many_arguments)]
+impl BlockingCanvas2D {
+    fn canvas(&self) -> &Canvas2D {
+        self.canvas.as_ref().unwrap()
+    }
+
+    fn block_on<'a, T, F, Fut>(&'a mut self, f: F) -> T
+    where
+        F: FnOnce(&'a mut Canvas2D) -> Fut,
+        Fut: Future<Output = T>,
+    {
+        let canvas = self.canvas.as_mut().unwrap();
+        self.runtime.handle.block_on(f(canvas))
+    }
+
+    // the async canvas, for use from inside the server's runtime
+    pub fn as_async(&mut self) -> &mut Canvas2D {
+        self.canvas.as_mut().unwrap()
+    }
+
+    pub fn measure_text(&mut self, text: String) -> Result<TextMetrics> {
+        self.block_on(|canvas| canvas.measure_text(text))
+    }
+
+    pub fn measure_texts(&mut self, texts: &[&str]) -> Result<Vec<TextMetrics>> {
+        self.block_on(|canvas| canvas.measure_texts(texts))
+    }
+
+    pub fn get_browser_id(&self) -> Uuid {
+        self.canvas().get_browser_id()
+    }
+
+    pub fn is_strict_mode(&self) -> bool {
+        self.canvas().is_strict_mode()
+    }
+
+    pub fn set_strict_mode(&mut self, strict: bool) -> Result<()> {
+        self.block_on(|canvas| canvas.set_strict_mode(strict))
+    }
+
+    pub fn get_line_width(&self) -> f64 {
+        self.canvas().get_line_width()
+    }
+
+    pub fn set_line_width(&mut self, line_width: f64) -> Result<()> {
+        self.block_on(|canvas| canvas.set_line_width(line_width))
+    }
+
+    pub fn get_line_cap(&self) -> LineCap {
+        self.canvas().get_line_cap()
+    }
+
+    pub fn set_line_cap(&mut self, line_cap: LineCap) -> Result<()> {
+        self.block_on(|canvas| canvas.set_line_cap(line_cap))
+    }
+
+    pub fn get_line_join(&self) -> LineJoin {
+        self.canvas().get_line_join()
+    }
+
+    pub fn set_line_join(&mut self, line_join: LineJoin) -> Result<()> {
+        self.block_on(|canvas| canvas.set_line_join(line_join))
+    }
+
+    pub fn get_miter_limit(&self) -> f64 {
+        self.canvas().get_miter_limit()
+    }
+
+    pub fn set_miter_limit(&mut self, miter_limit: f64) -> Result<()> {
+        self.block_on(|canvas| canvas.set_miter_limit(miter_limit))
+    }
+
+    pub fn get_line_dash(&mut self) -> Result<Vec<f64>> {
+        self.block_on(|canvas| canvas.get_line_dash())
+    }
+
+    pub fn set_line_dash(&mut self, line_dash: Vec<f64>) -> Result<Vec<f64>> {
+        self.block_on(|canvas| canvas.set_line_dash(line_dash))
+    }
+
+    pub fn get_line_dash_offset(&self) -> f64 {
+        self.canvas().get_line_dash_offset()
+    }
+
+    pub fn set_line_dash_offset(&mut self, line_dash_offset: f64) -> Result<()> {
+        self.block_on(|canvas| canvas.set_line_dash_offset(line_dash_offset))
+    }
+
+    pub fn get_font(&self) -> &str {
+        self.canvas().get_font()
+    }
+
+    pub fn set_font<S: Into<String>>(&mut self, font: S) -> Result<()> {
+        self.block_on(|canvas| canvas.set_font(font))
+    }
+
+    pub fn get_text_align(&self) -> TextAlign {
+        self.canvas().get_text_align()
+    }
+
+    pub fn set_text_align(&mut self, text_align: TextAlign) -> Result<()> {
+        self.block_on(|canvas| canvas.set_text_align(text_align))
+    }
+
+    pub fn get_text_baseline(&self) -> TextBaseline {
+        self.canvas().get_text_baseline()
+    }
+
+    pub fn set_text_baseline(&mut self, text_baseline: TextBaseline) -> Result<()> {
+        self.block_on(|canvas| canvas.set_text_baseline(text_baseline))
+    }
+
+    pub fn get_text_direction(&self) -> TextDirection {
+        self.canvas().get_text_direction()
+    }
+
+    pub fn set_text_direction(&mut self, text_direction: TextDirection) -> Result<()> {
+        self.block_on(|canvas| canvas.set_text_direction(text_direction))
+    }
+
+    pub fn get_letter_spacing(&self) -> &str {
+        self.canvas().get_letter_spacing()
+    }
+
+    pub fn set_letter_spacing(&mut self, letter_spacing: &str) -> Result<()> {
+        self.block_on(|canvas| canvas.set_letter_spacing(letter_spacing))
+    }
+
+    pub fn get_word_spacing(&self) -> &str {
+        self.canvas().get_word_spacing()
+    }
+
+    pub fn set_word_spacing(&mut self, word_spacing: &str) -> Result<()> {
+        self.block_on(|canvas| canvas.set_word_spacing(word_spacing))
+    }
+
+    pub fn get_font_kerning(&self) -> FontKerning {
+        self.canvas().get_font_kerning()
+    }
+
+    pub fn set_font_kerning(&mut self, font_kerning: FontKerning) -> Result<()> {
+        self.block_on(|canvas| canvas.set_font_kerning(font_kerning))
+    }
+
+    pub fn get_font_stretch(&self) -> FontStretch {
+        self.canvas().get_font_stretch()
+    }
+
+    pub fn set_font_stretch(&mut self, font_stretch: FontStretch) -> Result<()> {
+        self.block_on(|canvas| canvas.set_font_stretch(font_stretch))
+    }
+
+    pub fn get_font_variant_caps(&self) -> FontVariantCaps {
+        self.canvas().get_font_variant_caps()
+    }
+
+    pub fn set_font_variant_caps(&mut self, font_variant_caps: FontVariantCaps) -> Result<()> {
+        self.block_on(|canvas| canvas.set_font_variant_caps(font_variant_caps))
+    }
+
+    pub fn get_text_rendering(&self) -> TextRendering {
+        self.canvas().get_text_rendering()
+    }
+
+    pub fn set_text_rendering(&mut self, text_rendering: TextRendering) -> Result<()> {
+        self.block_on(|canvas| canvas.set_text_rendering(text_rendering))
+    }
+
+    pub fn get_fill_style(&self) -> &str {
+        self.canvas().get_fill_style()
+    }
+
+    pub fn set_fill_style<S: Into<String>>(&mut self, fill_style: S) -> Result<()> {
+        self.block_on(|canvas| canvas.set_fill_style(fill_style))
+    }
+
+    pub fn get_stroke_style(&self) -> &str {
+        self.canvas().get_stroke_style()
+    }
+
+    pub fn set_stroke_style<S: Into<String>>(&mut self, stroke_style: S) -> Result<()> {
+        self.block_on(|canvas| canvas.set_stroke_style(stroke_style))
+    }
+
+    pub fn get_shadow_blur(&self) -> f64 {
+        self.canvas().get_shadow_blur()
+    }
+
+    pub fn set_shadow_blur(&mut self, shadow_blur: f64) -> Result<()> {
+        self.block_on(|canvas| canvas.set_shadow_blur(shadow_blur))
+    }
+
+    pub fn get_shadow_color(&self) -> &str {
+        self.canvas().get_shadow_color()
+    }
+
+    pub fn set_shadow_color<S: Into<String>>(&mut self, shadow_color: S) -> Result<()> {
+        self.block_on(|canvas| canvas.set_shadow_color(shadow_color))
+    }
+
+    pub fn get_shadow_offset_x(&self) -> f64 {
+        self.canvas().get_shadow_offset_x()
+    }
+
+    pub fn set_shadow_offset_x(&mut self, shadow_offset_x: f64) -> Result<()> {
+        self.block_on(|canvas| canvas.set_shadow_offset_x(shadow_offset_x))
+    }
+
+    pub fn get_shadow_offset_y(&self) -> f64 {
+        self.canvas().get_shadow_offset_y()
+    }
+
+    pub fn set_shadow_offset_y(&mut self, shadow_offset_y: f64) -> Result<()> {
+        self.block_on(|canvas| canvas.set_shadow_offset_y(shadow_offset_y))
+    }
+
+    pub fn get_global_alpha(&self) -> f64 {
+        self.canvas().get_global_alpha()
+    }
+
+    pub fn set_global_alpha(&mut self, global_alpha: f64) -> Result<()> {
+        self.block_on(|canvas| canvas.set_global_alpha(global_alpha))
+    }
+
+    pub fn get_global_composite_operation(&self) -> &str {
+        self.canvas().get_global_composite_operation()
+    }
+
+    pub fn set_global_composite_operation<S: Into<String>>(
+        &mut self,
+        global_composite_operation: S,
+    ) -> Result<()> {
+        self.block_on(|canvas| canvas.set_global_composite_operation(global_composite_operation))
+    }
+
+    pub fn get_image_smoothing_enabled(&self) -> bool {
+        self.canvas().get_image_smoothing_enabled()
+    }
+
+    pub fn set_image_smoothing_enabled(&mut self, image_smoothing_enabled: bool) -> Result<()> {
+        self.block_on(|canvas| canvas.set_image_smoothing_enabled(image_smoothing_enabled))
+    }
+
+    pub fn get_image_smoothing_quality(&self) -> Option<ImageSmoothingQuality> {
+        self.canvas().get_image_smoothing_quality()
+    }
+
+    pub fn set_image_smoothing_quality(
+        &mut self,
+        image_smoothing_quality: Option<ImageSmoothingQuality>,
+    ) -> Result<()> {
+        self.block_on(|canvas| canvas.set_image_smoothing_quality(image_smoothing_quality))
+    }
+
+    pub fn get_filter(&self) -> Option<&str> {
+        self.canvas().get_filter()
+    }
+
+    pub fn set_filter<F: Into<Filter>>(&mut self, filter: F) -> Result<()> {
+        self.block_on(|canvas| canvas.set_filter(filter))
+    }
+
+    pub fn get_width(&self) -> u32 {
+        self.canvas().get_width()
+    }
+
+    pub fn set_width(&mut self, width: u32) -> Result<()> {
+        self.block_on(|canvas| canvas.set_width(width))
+    }
+
+    pub fn get_height(&self) -> u32 {
+        self.canvas().get_height()
+    }
+
+    pub fn set_height(&mut self, height: u32) -> Result<()> {
+        self.block_on(|canvas| canvas.set_height(height))
+    }
+
+    pub fn resize(&mut self, width: u32, height: u32, preserve_content: bool) -> Result<()> {
+        self.block_on(|canvas| canvas.resize(width, height, preserve_content))
+    }
+
+    pub fn clear_rect(&mut self, x: f64, y: f64, width: f64, height: f64) -> Result<()> {
+        self.block_on(|canvas| canvas.clear_rect(x, y, width, height))
+    }
+
+    pub fn fill_rect(&mut self, x: f64, y: f64, width: f64, height: f64) -> Result<()> {
+        self.block_on(|canvas| canvas.fill_rect(x, y, width, height))
+    }
+
+    pub fn stroke_rect(&mut self, x: f64, y: f64, width: f64, height: f64) -> Result<()> {
+        self.block_on(|canvas| canvas.stroke_rect(x, y, width, height))
+    }
+
+    pub fn fill_text(&mut self, text: &str, x: f64, y: f64, max_width: Option<f64>) -> Result<()> {
+        self.block_on(|canvas| canvas.fill_text(text, x, y, max_width))
+    }
+
+    pub fn stroke_text(
+        &mut self,
+        text: &str,
+        x: f64,
+        y: f64,
+        max_width: Option<f64>,
+    ) -> Result<()> {
+        self.block_on(|canvas| canvas.stroke_text(text, x, y, max_width))
+    }
+
+    pub fn create_linear_gradient(
+        &mut self,
+        x0: f64,
+        y0: f64,
+        x1: f64,
+        y1: f64,
+        stops: Vec<(f64, String)>,
+    ) -> Result<CanvasGradient> {
+        self.block_on(|canvas| canvas.create_linear_gradient(x0, y0, x1, y1, stops))
+    }
+
+    pub fn create_radial_gradient(
+        &mut self,
+        x0: f64,
+        y0: f64,
+        r0: f64,
+        x1: f64,
+        y1: f64,
+        r1: f64,
+        stops: Vec<(f64, String)>,
+    ) -> Result<CanvasGradient> {
+        self.block_on(|canvas| canvas.create_radial_gradient(x0, y0, r0, x1, y1, r1, stops))
+    }
+
+    pub fn create_conic_gradient(
+        &mut self,
+        start_angle: f64,
+        x: f64,
+        y: f64,
+        stops: Vec<(f64, String)>,
+    ) -> Result<CanvasGradient> {
+        self.block_on(|canvas| canvas.create_conic_gradient(start_angle, x, y, stops))
+    }
+
+    pub fn create_pattern(&mut self, url: &str, repitition: PatternRepitition) -> Result<()> {
+        self.block_on(|canvas| canvas.create_pattern(url, repitition))
+    }
+
+    pub fn begin_path(&mut self, path_uuid: Option<Uuid>) -> Result<()> {
+        self.block_on(|canvas| canvas.begin_path(path_uuid))
+    }
+
+    pub fn close_path(&mut self, path_uuid: Option<Uuid>) -> Result<()> {
+        self.block_on(|canvas| canvas.close_path(path_uuid))
+    }
+
+    pub fn move_to(&mut self, path_uuid: Option<Uuid>, x: f64, y: f64) -> Result<()> {
+        self.block_on(|canvas| canvas.move_to(path_uuid, x, y))
+    }
+
+    pub fn line_to(&mut self, path_uuid: Option<Uuid>, x: f64, y: f64) -> Result<()> {
+        self.block_on(|canvas| canvas.line_to(path_uuid, x, y))
+    }
+
+    pub fn bezier_curve_to(
+        &mut self,
+        path_uuid: Option<Uuid>,
+        cp1x: f64,
+        cp1y: f64,
+        cp2x: f64,
+        cp2y: f64,
+        x: f64,
+        y: f64,
+    ) -> Result<()> {
+        self.block_on(|canvas| canvas.bezier_curve_to(path_uuid, cp1x, cp1y, cp2x, cp2y, x, y))
+    }
+
+    pub fn quadratic_curve_to(
+        &mut self,
+        path_uuid: Option<Uuid>,
+        cpx: f64,
+        cpy: f64,
+        x: f64,
+        y: f64,
+    ) -> Result<()> {
+        self.block_on(|canvas| canvas.quadratic_curve_to(path_uuid, cpx, cpy, x, y))
+    }
+
+    pub fn arc(
+        &mut self,
+        path_uuid: Option<Uuid>,
+        x: f64,
+        y: f64,
+        radius: f64,
+        start_angle: f64,
+        end_angle: f64,
+        anticlockwise: bool,
+    ) -> Result<()> {
+        self.block_on(|canvas| {
+            canvas.arc(
+                path_uuid,
+                x,
+                y,
+                radius,
+                start_angle,
+                end_angle,
+                anticlockwise,
+            )
+        })
+    }
+
+    pub fn arc_to(
+        &mut self,
+        path_uuid: Option<Uuid>,
+        x1: f64,
+        y1: f64,
+        x2: f64,
+        y2: f64,
+        radius: f64,
+    ) -> Result<()> {
+        self.block_on(|canvas| canvas.arc_to(path_uuid, x1, y1, x2, y2, radius))
+    }
+
+    pub fn ellipse(
+        &mut self,
+        path_uuid: Option<Uuid>,
+        x: f64,
+        y: f64,
+        radius_x: f64,
+        radius_y: f64,
+        rotation: f64,
+        start_angle: f64,
+        end_angle: f64,
+        anticlockwise: bool,
+    ) -> Result<()> {
+        self.block_on(|canvas| {
+            canvas.ellipse(
+                path_uuid,
+                x,
+                y,
+                radius_x,
+                radius_y,
+                rotation,
+                start_angle,
+                end_angle,
+                anticlockwise,
+            )
+        })
+    }
+
+    pub fn rect(
+        &mut self,
+        path_uuid: Option<Uuid>,
+        x: f64,
+        y: f64,
+        width: f64,
+        height: f64,
+    ) -> Result<()> {
+        self.block_on(|canvas| canvas.rect(path_uuid, x, y, width, height))
+    }
+
+    pub fn round_rect<R: Into<CornerRadii>>(
+        &mut self,
+        path_uuid: Option<Uuid>,
+        x: f64,
+        y: f64,
+        width: f64,
+        height: f64,
+        radii: R,
+    ) -> Result<()> {
+        self.block_on(|canvas| canvas.round_rect(path_uuid, x, y, width, height, radii))
+    }
+
+    pub fn fill(&mut self, path_uuid: Option<Uuid>, is_even_odd: bool) -> Result<()> {
+        self.block_on(|canvas| canvas.fill(path_uuid, is_even_odd))
+    }
+
+    pub fn stroke(&mut self, path_uuid: Option<Uuid>) -> Result<()> {
+        self.block_on(|canvas| canvas.stroke(path_uuid))
+    }
+
+    pub fn clip(&mut self, path_uuid: Option<Uuid>, is_even_odd: bool) -> Result<()> {
+        self.block_on(|canvas| canvas.clip(path_uuid, is_even_odd))
+    }
+
+    pub fn is_point_in_path(
+        &mut self,
+        path_uuid: Option<Uuid>,
+        x: f64,
+        y: f64,
+        is_even_odd: bool,
+    ) -> Result<bool> {
+        self.block_on(|canvas| canvas.is_point_in_path(path_uuid, x, y, is_even_odd))
+    }
+
+    pub fn is_point_in_stroke(&mut self, path_uuid: Option<Uuid>, x: f64, y: f64) -> Result<bool> {
+        self.block_on(|canvas| canvas.is_point_in_stroke(path_uuid, x, y))
+    }
+
+    pub fn current_state(&self) -> DrawState {
+        self.canvas().current_state()
+    }
+
+    pub fn apply_state(&mut self, state: &DrawState) -> Result<()> {
+        self.block_on(|canvas| canvas.apply_state(state))
+    }
+
+    pub fn get_transform(&self) -> DomMatrix {
+        self.canvas().get_transform()
+    }
+
+    pub fn user_to_device(&self, x: f64, y: f64) -> (f64, f64) {
+        self.canvas().user_to_device(x, y)
+    }
+
+    pub fn device_to_user(&self, x: f64, y: f64) -> Option<(f64, f64)> {
+        self.canvas().device_to_user(x, y)
+    }
+
+    pub fn rotate(&mut self, angle: f64) -> Result<()> {
+        self.block_on(|canvas| canvas.rotate(angle))
+    }
+
+    pub fn scale(&mut self, x: f64, y: f64) -> Result<()> {
+        self.block_on(|canvas| canvas.scale(x, y))
+    }
+
+    pub fn translate(&mut self, x: f64, y: f64) -> Result<()> {
+        self.block_on(|canvas| canvas.translate(x, y))
+    }
+
+    pub fn transform<M: Into<DomMatrix>>(&mut self, matrix: M) -> Result<()> {
+        self.block_on(|canvas| canvas.transform(matrix))
+    }
+
+    pub fn set_transform<M: Into<DomMatrix>>(&mut self, matrix: M) -> Result<()> {
+        self.block_on(|canvas| canvas.set_transform(matrix))
+    }
+
+    pub fn draw_image(
+        &mut self,
+        url: &str,
+        source: Option<(f64, f64, f64, f64)>,
+        dx: f64,
+        dy: f64,
+        d_dims: Option<(f64, f64)>,
+    ) -> Result<()> {
+        self.block_on(|canvas| canvas.draw_image(url, source, dx, dy, d_dims))
+    }
+
+    pub fn preload_images(&mut self, urls: &[&str]) -> Result<()> {
+        self.block_on(|canvas| canvas.preload_images(urls))
+    }
+
+    pub fn evict_images(&mut self, urls: &[&str]) -> Result<()> {
+        self.block_on(|canvas| canvas.evict_images(urls))
+    }
+
+    pub fn clear_image_cache(&mut self) -> Result<()> {
+        self.block_on(|canvas| canvas.clear_image_cache())
+    }
+
+    pub fn get_image_data(&mut self, sx: f64, sy: f64, sw: f64, sh: f64) -> Result<ImageData> {
+        self.block_on(|canvas| canvas.get_image_data(sx, sy, sw, sh))
+    }
+
+    pub fn set_image_data(
+        &mut self,
+        image_data: ImageData,
+        dx: f64,
+        dy: f64,
+        dirty_pos: Option<(f64, f64)>,
+        dirty_dims: Option<(f64, f64)>,
+    ) -> Result<()> {
+        self.block_on(|canvas| canvas.set_image_data(image_data, dx, dy, dirty_pos, dirty_dims))
+    }
+
+    pub fn save(&mut self) -> Result<()> {
+        self.block_on(|canvas| canvas.save())
+    }
+
+    pub fn restore(&mut self) -> Result<()> {
+        self.block_on(|canvas| canvas.restore())
+    }
+
+    pub fn reset(&mut self) -> Result<()> {
+        self.block_on(|canvas| canvas.reset())
+    }
+
+    pub fn get_context_attributes(&mut self) -> Result<ContextData2D> {
+        self.block_on(|canvas| canvas.get_context_attributes())
+    }
+
+    pub fn is_context_lost(&mut self) -> Result<bool> {
+        self.block_on(|canvas| canvas.is_context_lost())
+    }
+
+    pub fn to_data_url(&mut self, format: ImageFormat, quality: Option<f64>) -> Result<String> {
+        self.block_on(|canvas| canvas.to_data_url(format, quality))
+    }
+
+    pub fn export(&mut self, format: ImageFormat, quality: Option<f64>) -> Result<Vec<u8>> {
+        self.block_on(|canvas| canvas.export(format, quality))
+    }
+
+    pub fn layout_text(&mut self, text: &str, layout: &TextLayout) -> Result<Vec<LineBox>> {
+        self.block_on(|canvas| canvas.layout_text(text, layout))
+    }
+
+    pub fn fill_text_layout(&mut self, text: &str, layout: &TextLayout) -> Result<Vec<LineBox>> {
+        self.block_on(|canvas| canvas.fill_text_layout(text, layout))
+    }
+
+    pub fn draw_canvas(
+        &mut self,
+        other: &BlockingCanvas2D,
+        source_rect: Option<(f64, f64, f64, f64)>,
+        dest_rect: (f64, f64, f64, f64),
+    ) -> Result<()> {
+        self.block_on(|canvas| canvas.draw_canvas(other.canvas(), source_rect, dest_rect))
+    }
+
+    pub fn close(mut self) -> Result<()> {
+        let canvas = self.canvas.take().unwrap();
+        self.runtime.handle.block_on(canvas.close())
+    }
+
+    // runs `f` between a save() and a restore(), restoring even if `f` fails or leaves its own saves on the stack
+    pub fn with_saved_state<T, F>(&mut self, f: F) -> Result<T>
+    where
+        F: FnOnce(&mut BlockingCanvas2D) -> Result<T>,
+    {
+        self.save()?;
+        let depth = self.canvas().state_depth();
+        let result = f(self);
+        while self.canvas().state_depth() >= depth {
+            if let Err(e) = self.restore() {
+                return result.and(Err(e));
+            }
+        }
+        result
+    }
+}
+
+impl Drop for BlockingCanvas2D {
+    // destroyed from the runtime, which is kept running until it has been
+    fn drop(&mut self) {
+        if let Some(canvas) = self.canvas.take() {
+            let closing = self.runtime.closing.clone();
+            closing.fetch_add(1, Ordering::SeqCst);
+            self.runtime.handle.spawn(async move {
+                canvas.close().await.unwrap_or(());
+                closing.fetch_sub(1, Ordering::SeqCst);
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::websocket::tests::fake_browser;
+
+    #[test]
+    fn starts_and_stops_from_a_plain_thread() {
+        for _ in 0..2 {
+            let generator = start_server("127.0.0.1:7090", "127.0.0.1:7091")
+                .unwrap()
+                .with_creation_timeout(Duration::from_millis(50));
+            assert!(generator
+                .wait_for_browsers(1, Duration::from_millis(50))
+                .is_err());
+            assert!(generator
+                .make_2d_canvas(Default::default(), 10, 10)
+                .is_err());
+            // stops the runtime and joins its thread, freeing the ports for the next round
+            drop(generator);
+        }
+    }
+
+    #[test]
+    fn destroys_canvases_before_stopping() {
+        let generator = start_server("127.0.0.1:7092", "127.0.0.1:7093").unwrap();
+        let ops = fake_browser("127.0.0.1:7093");
+        generator
+            .wait_for_browsers(1, Duration::from_secs(5))
+            .unwrap();
+        let canvas = generator
+            .make_2d_canvas(Default::default(), 10, 10)
+            .unwrap();
+        drop(canvas);
+        drop(generator);
+        let ops: Vec<String> = ops.try_iter().collect();
+        assert_eq!(ops.first().map(|op| &**op), Some("Init2DCanvas"));
+        assert_eq!(ops.last().map(|op| &**op), Some("Destroy2DCanvas"));
+    }
+}
@@ -0,0 +1,79 @@
+use crate::canvas::{FontDescriptors, FontSource};
+use crate::websocket::{ContextRequest, WSMessageData};
+use std::collections::HashMap;
+use std::sync::Mutex;
+use tokio::sync::{mpsc, watch};
+use uuid::Uuid;
+
+#[derive(Clone)]
+pub(crate) struct RegisteredFont {
+    pub family: String,
+    pub source: FontSource,
+    pub descriptors: FontDescriptors,
+}
+
+impl RegisteredFont {
+    pub(crate) fn to_message(&self) -> WSMessageData {
+        WSMessageData::RegisterFont {
+            family: self.family.clone(),
+            source: self.source.clone(),
+            descriptors: self.descriptors.clone(),
+        }
+    }
+}
+
+// every connected browser, with a channel for assigning contexts to it directly
+pub(crate) struct Browsers {
+    browsers: Mutex<HashMap<Uuid, mpsc::Sender<ContextRequest>>>,
+    // loaded into every browser as it connects
+    fonts: Mutex<Vec<RegisteredFont>>,
+    // how many browsers are connected, for waiting on them to connect
+    connected: (watch::Sender<usize>, watch::Receiver<usize>),
+}
+
+impl Default for Browsers {
+    fn default() -> Browsers {
+        Browsers {
+            browsers: Mutex::new(HashMap::new()),
+            fonts: Mutex::new(vec![]),
+            connected: watch::channel(0),
+        }
+    }
+}
+
+impl Browsers {
+    pub(crate) fn register(&self) -> (Uuid, mpsc::Receiver<ContextRequest>) {
+        let id = Uuid::new_v4();
+        let (sender, receiver) = mpsc::channel::<ContextRequest>(1024);
+        let mut browsers = self.browsers.lock().unwrap();
+        browsers.insert(id, sender);
+        self.connected.0.broadcast(browsers.len()).unwrap_or(());
+        (id, receiver)
+    }
+
+    pub(crate) fn unregister(&self, id: &Uuid) {
+        let mut browsers = self.browsers.lock().unwrap();
+        browsers.remove(id);
+        self.connected.0.broadcast(browsers.len()).unwrap_or(());
+    }
+
+    pub(crate) fn watch_connected(&self) -> watch::Receiver<usize> {
+        self.connected.1.clone()
+    }
+
+    pub(crate) fn get(&self, id: &Uuid) -> Option<mpsc::Sender<ContextRequest>> {
+        self.browsers.lock().unwrap().get(id).cloned()
+    }
+
+    pub(crate) fn all(&self) -> Vec<mpsc::Sender<ContextRequest>> {
+        self.browsers.lock().unwrap().values().cloned().collect()
+    }
+
+    pub(crate) fn add_font(&self, font: RegisteredFont) {
+        self.fonts.lock().unwrap().push(font);
+    }
+
+    pub(crate) fn fonts(&self) -> Vec<RegisteredFont> {
+        self.fonts.lock().unwrap().clone()
+    }
+}
@@ -1,13 +1,44 @@
+use crate::browser::{Browsers, RegisteredFont};
+use crate::config::CanvasSizeLimits;
+use crate::matrix::*;
+use crate::metrics::Metrics;
+use crate::recording::Recording;
 use crate::result::*;
+use crate::style::Filter;
+use crate::text_cache::{TextMetricsCache, TextMetricsKey};
 use crate::websocket::*;
 use async_std::sync;
+use futures::channel::mpsc::{unbounded, UnboundedSender};
+use futures::future::{join_all, BoxFuture};
+use futures::StreamExt;
+use lazy_static::lazy_static;
 pub use serde::{Deserialize, Serialize};
-use tokio::sync::mpsc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::{mpsc, oneshot};
+use tracing::{info_span, Instrument, Span};
 use uuid::Uuid;
 
 #[derive(Clone)]
 pub struct CanvasGenerator {
-    pub(crate) sender: sync::Sender<mpsc::Receiver<WSMessage>>,
+    pub(crate) sender: sync::Sender<ContextRequest>,
+    pub(crate) browsers: Arc<Browsers>,
+    pub(crate) text_metrics_cache: Option<Arc<TextMetricsCache>>,
+    pub(crate) metrics: Arc<Metrics>,
+    pub(crate) creation_timeout: Option<Duration>,
+    pub(crate) max_pending_creations: Option<usize>,
+    pub(crate) pending_creations: Arc<AtomicUsize>,
+    pub(crate) size_limits: CanvasSizeLimits,
+}
+
+// counts a canvas creation as pending until it's dropped
+struct PendingCreation(Arc<AtomicUsize>);
+
+impl Drop for PendingCreation {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::SeqCst);
+    }
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -52,15 +83,118 @@ pub struct ContextDataWebgl {
     pub stencil: bool,
 }
 
-#[derive(Serialize, Deserialize, Default, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub enum ColorSpace {
+    #[serde(rename = "srgb")]
+    Srgb,
+    #[serde(rename = "display-p3")]
+    DisplayP3,
+}
+
+impl Default for ColorSpace {
+    fn default() -> ColorSpace {
+        ColorSpace::Srgb
+    }
+}
+
+#[derive(Serialize, Deserialize, Default, Debug, Clone)]
 pub struct ContextData2D {
     #[serde(default)]
     pub alpha: bool,
     #[serde(default)]
     pub desynchronized: bool,
+    #[serde(default)]
+    #[serde(rename = "colorSpace")]
+    pub color_space: ColorSpace,
+    #[serde(default)]
+    #[serde(rename = "willReadFrequently")]
+    pub will_read_frequently: bool,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub enum FontSource {
+    Bytes(Vec<u8>),
+    Url(String),
+}
+
+impl From<Vec<u8>> for FontSource {
+    fn from(bytes: Vec<u8>) -> FontSource {
+        FontSource::Bytes(bytes)
+    }
+}
+
+impl From<&[u8]> for FontSource {
+    fn from(bytes: &[u8]) -> FontSource {
+        FontSource::Bytes(bytes.to_vec())
+    }
+}
+
+impl From<&str> for FontSource {
+    fn from(url: &str) -> FontSource {
+        FontSource::Url(url.to_string())
+    }
+}
+
+impl From<String> for FontSource {
+    fn from(url: String) -> FontSource {
+        FontSource::Url(url)
+    }
 }
 
-fn map_js_error(result: Result<WsMessageResponse>) -> Result<()> {
+// see https://developer.mozilla.org/en-US/docs/Web/API/FontFace/FontFace
+#[derive(Serialize, Deserialize, Default, Debug, Clone)]
+pub struct FontDescriptors {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub style: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub weight: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub stretch: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(rename = "unicodeRange")]
+    pub unicode_range: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub variant: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(rename = "featureSettings")]
+    pub feature_settings: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub display: Option<String>,
+}
+
+// chrome's limits, the default for ServerConfig::max_canvas_size
+pub const MAX_CANVAS_DIMENSION: u32 = 32_767;
+pub const MAX_CANVAS_AREA: u64 = 268_435_456;
+
+fn validate_canvas_size(limits: &CanvasSizeLimits, width: u32, height: u32) -> Result<()> {
+    if width > limits.max_dimension || height > limits.max_dimension {
+        return Err(canvas_error!(
+            "canvas size {}x{} exceeds the maximum dimension of {}",
+            width,
+            height,
+            limits.max_dimension
+        ));
+    }
+    if width as u64 * height as u64 > limits.max_area {
+        return Err(canvas_error!(
+            "canvas size {}x{} exceeds the maximum area of {} pixels",
+            width,
+            height,
+            limits.max_area
+        ));
+    }
+    Ok(())
+}
+
+// in strict mode the browser responds with its normalized form of the value that was set
+fn property_value(response: WsMessageResponse, value: &str) -> String {
+    match response {
+        WsMessageResponse::Property { value } => value,
+        _ => value.to_string(),
+    }
+}
+
+pub(crate) fn map_js_error(result: Result<WsMessageResponse>) -> Result<()> {
     match result {
         Ok(WsMessageResponse::JsError { message }) => {
             return Err(canvas_error!("js error: {}", message));
@@ -74,7 +208,7 @@ fn map_js_error(result: Result<WsMessageResponse>) -> Result<()> {
     }
 }
 
-fn map_js_error_data(result: Result<WsMessageResponse>) -> Result<WsMessageResponse> {
+pub(crate) fn map_js_error_data(result: Result<WsMessageResponse>) -> Result<WsMessageResponse> {
     match result {
         Ok(WsMessageResponse::JsError { message }) => {
             return Err(canvas_error!("js error: {}", message));
@@ -89,17 +223,221 @@ fn map_js_error_data(result: Result<WsMessageResponse>) -> Result<WsMessageRespo
 }
 
 impl CanvasGenerator {
-    pub async fn make_2d_canvas(
+    pub(crate) fn new(
+        sender: sync::Sender<ContextRequest>,
+        browsers: Arc<Browsers>,
+        metrics: Arc<Metrics>,
+    ) -> CanvasGenerator {
+        CanvasGenerator {
+            sender,
+            browsers,
+            text_metrics_cache: None,
+            metrics,
+            creation_timeout: None,
+            max_pending_creations: None,
+            pending_creations: Arc::new(AtomicUsize::new(0)),
+            size_limits: CanvasSizeLimits::default(),
+        }
+    }
+
+    pub(crate) fn with_size_limits(mut self, limits: CanvasSizeLimits) -> CanvasGenerator {
+        self.size_limits = limits;
+        self
+    }
+
+    // answers repeated text measurements from every canvas locally, keeping at most `capacity` entries
+    pub fn with_text_metrics_cache(mut self, capacity: usize) -> CanvasGenerator {
+        self.text_metrics_cache = Some(Arc::new(TextMetricsCache::new(capacity)));
+        self
+    }
+
+    // fails canvas creations with NoBrowserAvailable if no browser has taken and initialized them within
+    // `timeout`, rather than waiting for one to connect or to answer
+    pub fn with_creation_timeout(mut self, timeout: Duration) -> CanvasGenerator {
+        self.creation_timeout = Some(timeout);
+        self
+    }
+
+    // fails canvas creations with CreationQueueFull while `max` others are still waiting to be created
+    pub fn with_max_pending_creations(mut self, max: usize) -> CanvasGenerator {
+        self.max_pending_creations = Some(max);
+        self
+    }
+
+    pub fn get_pending_creations(&self) -> usize {
+        self.pending_creations.load(Ordering::SeqCst)
+    }
+
+    // waits until at least `count` browsers are connected, failing with NoBrowserAvailable after `timeout`
+    pub async fn wait_for_browsers(&self, count: usize, timeout: Duration) -> Result<()> {
+        let mut connected = self.browsers.watch_connected();
+        let enough = async {
+            while let Some(connected) = connected.recv().await {
+                if connected >= count {
+                    return;
+                }
+            }
+        };
+        tokio::time::timeout(timeout, enough)
+            .await
+            .map_err(|_| Box::new(NoBrowserAvailable { waited: timeout }) as Error)
+    }
+
+    // queues the canvas' channel to be placed on a browser with room for it, only `near` if given, then
+    // initializes it once a browser has taken it
+    async fn create_canvas(
         &self,
         data: ContextData2D,
         width: u32,
         height: u32,
+        near: Option<Uuid>,
     ) -> Result<Canvas2D> {
+        validate_canvas_size(&self.size_limits, width, height)?;
+        let _pending = PendingCreation(self.pending_creations.clone());
+        let already_pending = self.pending_creations.fetch_add(1, Ordering::SeqCst);
+        if let Some(limit) = self.max_pending_creations {
+            if already_pending >= limit {
+                return Err(Box::new(CreationQueueFull { limit }));
+            }
+        }
+        let started = Instant::now();
+        let uuid = Uuid::new_v4();
         let (sender, receiver) = mpsc::channel::<WSMessage>(1024);
-        self.sender.send(receiver).await;
-        let mut canvas = Canvas2D {
-            uuid: Uuid::new_v4(),
-            sender,
+        let (browser_sender, browser_receiver) = oneshot::channel::<Assignment>();
+        let creation = async {
+            self.sender
+                .send(ContextRequest {
+                    receiver,
+                    browser: browser_sender,
+                    canvas: Some(CanvasPlacement {
+                        uuid,
+                        width,
+                        height,
+                        near,
+                    }),
+                })
+                .await;
+            let browser = browser_receiver
+                .await
+                .map_err(|_| canvas_error!("canvas was never assigned to a browser") as Error)?
+                .map_err(|rejected| Box::new(rejected) as Error)?;
+            Canvas2D::create(
+                uuid,
+                sender,
+                browser,
+                self.text_metrics_cache.clone(),
+                self.size_limits,
+                data,
+                width,
+                height,
+            )
+            .await
+        };
+        // a canvas given up on part way through initializing is destroyed as it's dropped
+        let canvas = match self.creation_timeout {
+            Some(timeout) => tokio::time::timeout(timeout, creation)
+                .await
+                .map_err(|_| Box::new(NoBrowserAvailable { waited: timeout }) as Error)??,
+            None => creation.await?,
+        };
+        self.metrics.canvas_creation(started.elapsed());
+        Ok(canvas)
+    }
+
+    pub async fn make_2d_canvas(
+        &self,
+        data: ContextData2D,
+        width: u32,
+        height: u32,
+    ) -> Result<Canvas2D> {
+        self.create_canvas(data, width, height, None).await
+    }
+
+    // loads the font into every connected browser, and every browser that connects later
+    pub async fn register_font<S: Into<FontSource>>(
+        &self,
+        family: &str,
+        source: S,
+        descriptors: FontDescriptors,
+    ) -> Result<()> {
+        let font = RegisteredFont {
+            family: family.to_string(),
+            source: source.into(),
+            descriptors,
+        };
+        self.browsers.add_font(font.clone());
+        let results = join_all(
+            self.browsers
+                .all()
+                .into_iter()
+                .map(|browser| send_to_browser(browser, font.to_message())),
+        )
+        .await;
+        // anything measured so far may have used a fallback face
+        if let Some(cache) = &self.text_metrics_cache {
+            cache.clear();
+        }
+        for result in results {
+            map_js_error(result)?;
+        }
+        Ok(())
+    }
+
+    // places the new canvas on the same browser as `near`, so that `draw_canvas` between them stays in the browser
+    pub async fn make_2d_canvas_near(
+        &self,
+        data: ContextData2D,
+        width: u32,
+        height: u32,
+        near: &Canvas2D,
+    ) -> Result<Canvas2D> {
+        if self.browsers.get(&near.browser).is_none() {
+            return Err(canvas_error!(
+                "browser {} is no longer connected",
+                near.browser
+            ));
+        }
+        self.create_canvas(data, width, height, Some(near.browser))
+            .await
+    }
+}
+
+// the drawing state mirrored from the browser, so that getters don't need a round trip
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct DrawState {
+    pub line_width: f64,
+    pub line_cap: LineCap,
+    pub line_join: LineJoin,
+    pub miter_limit: f64,
+    pub line_dash_offset: f64,
+    pub font: String,
+    pub text_align: TextAlign,
+    pub text_baseline: TextBaseline,
+    pub text_direction: TextDirection,
+    pub letter_spacing: String,
+    pub word_spacing: String,
+    pub font_kerning: FontKerning,
+    pub font_stretch: FontStretch,
+    pub font_variant_caps: FontVariantCaps,
+    pub text_rendering: TextRendering,
+    pub fill_style: String,
+    pub stroke_style: String,
+    pub shadow_blur: f64,
+    pub shadow_color: String,
+    pub shadow_offset_x: f64,
+    pub shadow_offset_y: f64,
+    pub global_alpha: f64,
+    pub global_composite_operation: String,
+    pub image_smoothing_enabled: bool,
+    pub image_smoothing_quality: Option<ImageSmoothingQuality>,
+    pub filter: Option<String>,
+    pub line_dash: Vec<f64>,
+    pub transform: DomMatrix,
+}
+
+impl Default for DrawState {
+    fn default() -> DrawState {
+        DrawState {
             line_width: 1.0,
             line_cap: LineCap::Butt,
             line_join: LineJoin::Miter,
@@ -109,6 +447,12 @@ impl CanvasGenerator {
             text_align: TextAlign::Start,
             text_baseline: TextBaseline::Alphabetic,
             text_direction: TextDirection::Inherit,
+            letter_spacing: "0px".to_string(),
+            word_spacing: "0px".to_string(),
+            font_kerning: FontKerning::Auto,
+            font_stretch: FontStretch::Normal,
+            font_variant_caps: FontVariantCaps::Normal,
+            text_rendering: TextRendering::Auto,
             fill_style: "#000".to_string(),
             stroke_style: "#000".to_string(),
             shadow_blur: 0.0,
@@ -120,42 +464,305 @@ impl CanvasGenerator {
             image_smoothing_enabled: true,
             image_smoothing_quality: Some(ImageSmoothingQuality::Low),
             filter: None,
-            width,
-            height,
-        };
-        canvas.initialize(data).await?;
-        return Ok(canvas);
+            line_dash: vec![],
+            transform: DomMatrix::identity(),
+        }
+    }
+}
+
+// the fields of a DrawState that need to change, as sent by apply_state
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub(crate) struct DrawStateDelta {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub line_width: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub line_cap: Option<LineCap>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub line_join: Option<LineJoin>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub miter_limit: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub line_dash_offset: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub font: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub text_align: Option<TextAlign>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub text_baseline: Option<TextBaseline>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub text_direction: Option<TextDirection>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub letter_spacing: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub word_spacing: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub font_kerning: Option<FontKerning>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub font_stretch: Option<FontStretch>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub font_variant_caps: Option<FontVariantCaps>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub text_rendering: Option<TextRendering>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub fill_style: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub stroke_style: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub shadow_blur: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub shadow_color: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub shadow_offset_x: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub shadow_offset_y: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub global_alpha: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub global_composite_operation: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub image_smoothing_enabled: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub image_smoothing_quality: Option<Option<ImageSmoothingQuality>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub filter: Option<Option<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub line_dash: Option<Vec<f64>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub transform: Option<DomMatrix>,
+}
+
+fn changed<T: PartialEq + Clone>(from: &T, to: &T) -> Option<T> {
+    if from == to {
+        None
+    } else {
+        Some(to.clone())
+    }
+}
+
+impl DrawState {
+    pub(crate) fn diff(&self, target: &DrawState) -> DrawStateDelta {
+        DrawStateDelta {
+            line_width: changed(&self.line_width, &target.line_width),
+            line_cap: changed(&self.line_cap, &target.line_cap),
+            line_join: changed(&self.line_join, &target.line_join),
+            miter_limit: changed(&self.miter_limit, &target.miter_limit),
+            line_dash_offset: changed(&self.line_dash_offset, &target.line_dash_offset),
+            font: changed(&self.font, &target.font),
+            text_align: changed(&self.text_align, &target.text_align),
+            text_baseline: changed(&self.text_baseline, &target.text_baseline),
+            text_direction: changed(&self.text_direction, &target.text_direction),
+            letter_spacing: changed(&self.letter_spacing, &target.letter_spacing),
+            word_spacing: changed(&self.word_spacing, &target.word_spacing),
+            font_kerning: changed(&self.font_kerning, &target.font_kerning),
+            font_stretch: changed(&self.font_stretch, &target.font_stretch),
+            font_variant_caps: changed(&self.font_variant_caps, &target.font_variant_caps),
+            text_rendering: changed(&self.text_rendering, &target.text_rendering),
+            fill_style: changed(&self.fill_style, &target.fill_style),
+            stroke_style: changed(&self.stroke_style, &target.stroke_style),
+            shadow_blur: changed(&self.shadow_blur, &target.shadow_blur),
+            shadow_color: changed(&self.shadow_color, &target.shadow_color),
+            shadow_offset_x: changed(&self.shadow_offset_x, &target.shadow_offset_x),
+            shadow_offset_y: changed(&self.shadow_offset_y, &target.shadow_offset_y),
+            global_alpha: changed(&self.global_alpha, &target.global_alpha),
+            global_composite_operation: changed(
+                &self.global_composite_operation,
+                &target.global_composite_operation,
+            ),
+            image_smoothing_enabled: changed(
+                &self.image_smoothing_enabled,
+                &target.image_smoothing_enabled,
+            ),
+            image_smoothing_quality: changed(
+                &self.image_smoothing_quality,
+                &target.image_smoothing_quality,
+            ),
+            filter: changed(&self.filter, &target.filter),
+            line_dash: changed(&self.line_dash, &target.line_dash),
+            transform: changed(&self.transform, &target.transform),
+        }
+    }
+
+    // the change a drawing state op makes, other ops are ignored
+    pub(crate) fn update(&mut self, op: &WSMessageData) {
+        match op {
+            WSMessageData::ApplyState { state: delta } => self.apply(delta),
+            WSMessageData::SetLineWidth { line_width } => self.line_width = *line_width,
+            WSMessageData::SetLineCap { line_cap } => self.line_cap = *line_cap,
+            WSMessageData::SetLineJoin { line_join } => self.line_join = *line_join,
+            WSMessageData::SetMiterLimit { miter_limit } => self.miter_limit = *miter_limit,
+            WSMessageData::SetLineDash { line_dash } => self.line_dash = line_dash.clone(),
+            WSMessageData::SetLineDashOffset { line_dash_offset } => {
+                self.line_dash_offset = *line_dash_offset
+            }
+            WSMessageData::SetFont { font } => self.font = font.clone(),
+            WSMessageData::SetTextAlign { text_align } => self.text_align = *text_align,
+            WSMessageData::SetTextBaseline { text_baseline } => self.text_baseline = *text_baseline,
+            WSMessageData::SetTextDirection { text_direction } => {
+                self.text_direction = *text_direction
+            }
+            WSMessageData::SetLetterSpacing { letter_spacing } => {
+                self.letter_spacing = letter_spacing.clone()
+            }
+            WSMessageData::SetWordSpacing { word_spacing } => {
+                self.word_spacing = word_spacing.clone()
+            }
+            WSMessageData::SetFontKerning { font_kerning } => self.font_kerning = *font_kerning,
+            WSMessageData::SetFontStretch { font_stretch } => self.font_stretch = *font_stretch,
+            WSMessageData::SetFontVariantCaps { font_variant_caps } => {
+                self.font_variant_caps = *font_variant_caps
+            }
+            WSMessageData::SetTextRendering { text_rendering } => {
+                self.text_rendering = *text_rendering
+            }
+            WSMessageData::SetFillStyle { fill_style } => self.fill_style = fill_style.clone(),
+            WSMessageData::SetStrokeStyle { stroke_style } => {
+                self.stroke_style = stroke_style.clone()
+            }
+            WSMessageData::SetShadowBlur { shadow_blur } => self.shadow_blur = *shadow_blur,
+            WSMessageData::SetShadowColor { shadow_color } => {
+                self.shadow_color = shadow_color.clone()
+            }
+            WSMessageData::SetShadowOffsetX { shadow_offset_x } => {
+                self.shadow_offset_x = *shadow_offset_x
+            }
+            WSMessageData::SetShadowOffsetY { shadow_offset_y } => {
+                self.shadow_offset_y = *shadow_offset_y
+            }
+            WSMessageData::SetGlobalAlpha { global_alpha } => self.global_alpha = *global_alpha,
+            WSMessageData::SetGlobalCompositeOperation {
+                global_composite_operation,
+            } => self.global_composite_operation = global_composite_operation.clone(),
+            WSMessageData::SetImageSmoothingEnabled {
+                image_smoothing_enabled,
+            } => self.image_smoothing_enabled = *image_smoothing_enabled,
+            WSMessageData::SetImageSmoothingQuality {
+                image_smoothing_quality,
+            } => self.image_smoothing_quality = *image_smoothing_quality,
+            WSMessageData::SetFilter { filter } => self.filter = filter.clone(),
+            WSMessageData::Rotate { angle } => {
+                self.transform = self.transform.multiply(&DomMatrix::rotation(*angle))
+            }
+            WSMessageData::Scale { x, y } => {
+                self.transform = self.transform.multiply(&DomMatrix::scaling(*x, *y))
+            }
+            WSMessageData::Translate { x, y } => {
+                self.transform = self.transform.multiply(&DomMatrix::translation(*x, *y))
+            }
+            WSMessageData::Transform { matrix } => self.transform = self.transform.multiply(matrix),
+            WSMessageData::SetTransform { matrix } => self.transform = *matrix,
+            _ => {}
+        }
+    }
+
+    pub(crate) fn apply(&mut self, delta: &DrawStateDelta) {
+        if let Some(line_width) = delta.line_width {
+            self.line_width = line_width;
+        }
+        if let Some(line_cap) = delta.line_cap {
+            self.line_cap = line_cap;
+        }
+        if let Some(line_join) = delta.line_join {
+            self.line_join = line_join;
+        }
+        if let Some(miter_limit) = delta.miter_limit {
+            self.miter_limit = miter_limit;
+        }
+        if let Some(line_dash_offset) = delta.line_dash_offset {
+            self.line_dash_offset = line_dash_offset;
+        }
+        if let Some(font) = &delta.font {
+            self.font = font.clone();
+        }
+        if let Some(text_align) = delta.text_align {
+            self.text_align = text_align;
+        }
+        if let Some(text_baseline) = delta.text_baseline {
+            self.text_baseline = text_baseline;
+        }
+        if let Some(text_direction) = delta.text_direction {
+            self.text_direction = text_direction;
+        }
+        if let Some(letter_spacing) = &delta.letter_spacing {
+            self.letter_spacing = letter_spacing.clone();
+        }
+        if let Some(word_spacing) = &delta.word_spacing {
+            self.word_spacing = word_spacing.clone();
+        }
+        if let Some(font_kerning) = delta.font_kerning {
+            self.font_kerning = font_kerning;
+        }
+        if let Some(font_stretch) = delta.font_stretch {
+            self.font_stretch = font_stretch;
+        }
+        if let Some(font_variant_caps) = delta.font_variant_caps {
+            self.font_variant_caps = font_variant_caps;
+        }
+        if let Some(text_rendering) = delta.text_rendering {
+            self.text_rendering = text_rendering;
+        }
+        if let Some(fill_style) = &delta.fill_style {
+            self.fill_style = fill_style.clone();
+        }
+        if let Some(stroke_style) = &delta.stroke_style {
+            self.stroke_style = stroke_style.clone();
+        }
+        if let Some(shadow_blur) = delta.shadow_blur {
+            self.shadow_blur = shadow_blur;
+        }
+        if let Some(shadow_color) = &delta.shadow_color {
+            self.shadow_color = shadow_color.clone();
+        }
+        if let Some(shadow_offset_x) = delta.shadow_offset_x {
+            self.shadow_offset_x = shadow_offset_x;
+        }
+        if let Some(shadow_offset_y) = delta.shadow_offset_y {
+            self.shadow_offset_y = shadow_offset_y;
+        }
+        if let Some(global_alpha) = delta.global_alpha {
+            self.global_alpha = global_alpha;
+        }
+        if let Some(global_composite_operation) = &delta.global_composite_operation {
+            self.global_composite_operation = global_composite_operation.clone();
+        }
+        if let Some(image_smoothing_enabled) = delta.image_smoothing_enabled {
+            self.image_smoothing_enabled = image_smoothing_enabled;
+        }
+        if let Some(image_smoothing_quality) = delta.image_smoothing_quality {
+            self.image_smoothing_quality = image_smoothing_quality;
+        }
+        if let Some(filter) = &delta.filter {
+            self.filter = filter.clone();
+        }
+        if let Some(line_dash) = &delta.line_dash {
+            self.line_dash = line_dash.clone();
+        }
+        if let Some(transform) = delta.transform {
+            self.transform = transform;
+        }
     }
 }
 
 pub struct Canvas2D {
     uuid: Uuid,
+    browser: Uuid,
     sender: mpsc::Sender<WSMessage>,
-    line_width: f64,
-    line_cap: LineCap,
-    line_join: LineJoin,
-    miter_limit: f64,
-    line_dash_offset: f64,
-    font: String,
-    text_align: TextAlign,
-    text_baseline: TextBaseline,
-    text_direction: TextDirection,
-    fill_style: String,
-    stroke_style: String,
-    shadow_blur: f64,
-    shadow_color: String,
-    shadow_offset_x: f64,
-    shadow_offset_y: f64,
-    global_alpha: f64,
-    global_composite_operation: String,
-    image_smoothing_enabled: bool,
-    image_smoothing_quality: Option<ImageSmoothingQuality>,
-    filter: Option<String>,
+    text_metrics_cache: Option<Arc<TextMetricsCache>>,
+    size_limits: CanvasSizeLimits,
+    state: DrawState,
+    // states pushed by save(), mirroring the browser's drawing state stack
+    state_stack: Vec<DrawState>,
+    strict: bool,
     width: u32,
     height: u32,
+    // set for recorders, which collect ops instead of sending them to a browser
+    recording: Option<Vec<WSMessageData>>,
+    // set by close(), so dropping doesn't destroy it again
+    closed: bool,
+    // the parent of every op's span
+    span: Span,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct TextMetrics {
     pub width: f64,
     #[serde(rename = "actualBoundingBoxLeft")]
@@ -182,21 +789,21 @@ pub struct TextMetrics {
     pub ideographic_baseline: f64,
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone, Copy)]
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
 pub enum LineCap {
     Butt,
     Round,
     Square,
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone, Copy)]
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
 pub enum LineJoin {
     Round,
     Bevel,
     Miter,
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone, Copy)]
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
 pub enum TextAlign {
     Start,
     End,
@@ -205,7 +812,7 @@ pub enum TextAlign {
     Center,
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone, Copy)]
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
 pub enum TextBaseline {
     Top,
     Hanging,
@@ -215,21 +822,151 @@ pub enum TextBaseline {
     Bottom,
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone, Copy)]
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum TextDirection {
     Ltr,
     Rtl,
     Inherit,
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone, Copy)]
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum FontKerning {
+    #[serde(rename = "auto")]
+    Auto,
+    #[serde(rename = "normal")]
+    Normal,
+    #[serde(rename = "none")]
+    None,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum FontStretch {
+    #[serde(rename = "ultra-condensed")]
+    UltraCondensed,
+    #[serde(rename = "extra-condensed")]
+    ExtraCondensed,
+    #[serde(rename = "condensed")]
+    Condensed,
+    #[serde(rename = "semi-condensed")]
+    SemiCondensed,
+    #[serde(rename = "normal")]
+    Normal,
+    #[serde(rename = "semi-expanded")]
+    SemiExpanded,
+    #[serde(rename = "expanded")]
+    Expanded,
+    #[serde(rename = "extra-expanded")]
+    ExtraExpanded,
+    #[serde(rename = "ultra-expanded")]
+    UltraExpanded,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum FontVariantCaps {
+    #[serde(rename = "normal")]
+    Normal,
+    #[serde(rename = "small-caps")]
+    SmallCaps,
+    #[serde(rename = "all-small-caps")]
+    AllSmallCaps,
+    #[serde(rename = "petite-caps")]
+    PetiteCaps,
+    #[serde(rename = "all-petite-caps")]
+    AllPetiteCaps,
+    #[serde(rename = "unicase")]
+    Unicase,
+    #[serde(rename = "titling-caps")]
+    TitlingCaps,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum TextRendering {
+    #[serde(rename = "auto")]
+    Auto,
+    #[serde(rename = "optimizeSpeed")]
+    OptimizeSpeed,
+    #[serde(rename = "optimizeLegibility")]
+    OptimizeLegibility,
+    #[serde(rename = "geometricPrecision")]
+    GeometricPrecision,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
 pub enum ImageSmoothingQuality {
     Low,
     Medium,
     High,
 }
 
-pub type DomMatrix = [f64; 6];
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub enum ImageFormat {
+    #[serde(rename = "image/png")]
+    Png,
+    #[serde(rename = "image/jpeg")]
+    Jpeg,
+    #[serde(rename = "image/webp")]
+    Webp,
+}
+
+impl ImageFormat {
+    pub fn mime_type(&self) -> &'static str {
+        match self {
+            ImageFormat::Png => "image/png",
+            ImageFormat::Jpeg => "image/jpeg",
+            ImageFormat::Webp => "image/webp",
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub struct CornerRadii {
+    pub top_left: f64,
+    pub top_right: f64,
+    pub bottom_right: f64,
+    pub bottom_left: f64,
+}
+
+impl CornerRadii {
+    pub fn uniform(radius: f64) -> CornerRadii {
+        CornerRadii {
+            top_left: radius,
+            top_right: radius,
+            bottom_right: radius,
+            bottom_left: radius,
+        }
+    }
+}
+
+impl From<f64> for CornerRadii {
+    fn from(radius: f64) -> CornerRadii {
+        CornerRadii::uniform(radius)
+    }
+}
+
+// a gradient created on a canvas, which is set as a fill or stroke style by passing it to
+// set_fill_style/set_stroke_style. it is referenced as "url(#gradient-<uuid>)", the same as in svg exports
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CanvasGradient {
+    uuid: Uuid,
+}
+
+impl CanvasGradient {
+    pub fn get_uuid(&self) -> Uuid {
+        self.uuid
+    }
+}
+
+impl From<CanvasGradient> for String {
+    fn from(gradient: CanvasGradient) -> String {
+        format!("url(#gradient-{})", gradient.uuid)
+    }
+}
+
+impl From<&CanvasGradient> for String {
+    fn from(gradient: &CanvasGradient) -> String {
+        String::from(*gradient)
+    }
+}
 
 #[derive(Serialize, Deserialize, Debug, Clone, Copy)]
 pub enum PatternRepitition {
@@ -241,41 +978,302 @@ pub enum PatternRepitition {
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct ImageData {
-    pub data: Vec<u32>, // (y*width + x) -> RGBA
+    pub data: Vec<u8>, // 4 * (y*width + x) -> R, G, B, A
     pub width: u32,
     pub height: u32,
 }
 
 impl ImageData {
+    pub fn new(width: u32, height: u32) -> ImageData {
+        ImageData {
+            data: vec![0; width as usize * height as usize * 4],
+            width,
+            height,
+        }
+    }
+
+    pub fn from_rgba8(width: u32, height: u32, data: Vec<u8>) -> Result<ImageData> {
+        if data.len() != width as usize * height as usize * 4 {
+            return Err(canvas_error!(
+                "expected {} bytes of RGBA8 data for {}x{} image, got {}",
+                width as usize * height as usize * 4,
+                width,
+                height,
+                data.len()
+            ));
+        }
+        Ok(ImageData {
+            data,
+            width,
+            height,
+        })
+    }
+
+    pub fn as_rgba8(&self) -> &[u8] {
+        &self.data
+    }
+
+    pub fn as_rgba8_mut(&mut self) -> &mut [u8] {
+        &mut self.data
+    }
+
+    pub fn into_rgba8(self) -> Vec<u8> {
+        self.data
+    }
+
+    // pixels are packed as 0xRRGGBBAA
     pub fn get_pixel(&self, x: u32, y: u32) -> u32 {
-        if x > self.width || y > self.height {
+        if x >= self.width || y >= self.height {
             return 0;
         }
-        return self.data[(y * self.width + x) as usize];
+        let offset = (y * self.width + x) as usize * 4;
+        let mut pixel = [0u8; 4];
+        pixel.copy_from_slice(&self.data[offset..offset + 4]);
+        u32::from_be_bytes(pixel)
     }
 
     pub fn set_pixel(&mut self, x: u32, y: u32, pixel: u32) {
-        if x > self.width || y > self.height {
+        if x >= self.width || y >= self.height {
             return;
         }
-        self.data[(y * self.width + x) as usize] = pixel;
+        let offset = (y * self.width + x) as usize * 4;
+        self.data[offset..offset + 4].copy_from_slice(&pixel.to_be_bytes());
     }
 }
 
-impl Canvas2D {
-    async fn send(&mut self, data: WSMessageData) -> Result<WsMessageResponse> {
-        let (sender, receiver) = sync::channel::<WsMessageResponse>(1);
-        self.sender
-            .send(WSMessage {
-                context: self.uuid,
-                data,
-                response: Some(sender),
-            })
-            .await?;
-        receiver.recv()
+#[cfg(feature = "image")]
+impl From<image::RgbaImage> for ImageData {
+    fn from(image: image::RgbaImage) -> ImageData {
+        let (width, height) = image.dimensions();
+        ImageData {
+            data: image.into_raw(),
+            width,
+            height,
+        }
+    }
+}
+
+#[cfg(feature = "image")]
+impl From<image::DynamicImage> for ImageData {
+    fn from(image: image::DynamicImage) -> ImageData {
+        image.into_rgba8().into()
+    }
+}
+
+#[cfg(feature = "image")]
+impl std::convert::TryFrom<ImageData> for image::RgbaImage {
+    type Error = Error;
+
+    fn try_from(image_data: ImageData) -> Result<image::RgbaImage> {
+        let (width, height) = (image_data.width, image_data.height);
+        image::RgbaImage::from_raw(width, height, image_data.data).ok_or_else(|| {
+            canvas_error!(
+                "image data does not match its {}x{} dimensions",
+                width,
+                height
+            ) as Error
+        })
+    }
+}
+
+#[cfg(feature = "image")]
+impl std::convert::TryFrom<ImageData> for image::DynamicImage {
+    type Error = Error;
+
+    fn try_from(image_data: ImageData) -> Result<image::DynamicImage> {
+        use std::convert::TryInto;
+        Ok(image::DynamicImage::ImageRgba8(image_data.try_into()?))
+    }
+}
+
+// recorders answer ops themselves, so anything that needs the browser to answer can't be recorded, nor be sent
+// inside a batch. finds the first such op, looking into nested batches
+pub(crate) fn find_unrecordable(ops: &[WSMessageData]) -> Option<&WSMessageData> {
+    ops.iter().find_map(|op| match op {
+        WSMessageData::Batch { ops } => find_unrecordable(ops),
+        WSMessageData::Init2DCanvas { .. }
+        | WSMessageData::Destroy2DCanvas { .. }
+        | WSMessageData::GetLineDash {}
+        | WSMessageData::MeasureText { .. }
+        | WSMessageData::MeasureTexts { .. }
+        | WSMessageData::IsPointInPath { .. }
+        | WSMessageData::IsPointInStroke { .. }
+        | WSMessageData::DrawCanvas { .. }
+        | WSMessageData::RegisterFont { .. }
+        | WSMessageData::GetImageData { .. }
+        | WSMessageData::GetContextAttributes {}
+        | WSMessageData::IsContextLost {}
+        | WSMessageData::ToDataUrl { .. }
+        | WSMessageData::Export { .. } => Some(op),
+        _ => None,
+    })
+}
+
+fn record(ops: &mut Vec<WSMessageData>, data: WSMessageData) -> Result<WsMessageResponse> {
+    if let Some(op) = find_unrecordable(std::slice::from_ref(&data)) {
+        return Err(canvas_error!("{} can't be recorded", op.name()));
+    }
+    let response = match &data {
+        WSMessageData::SetLineDash { line_dash } => WsMessageResponse::LineDash {
+            line_dash: line_dash.clone(),
+        },
+        _ => WsMessageResponse::Success {},
+    };
+    ops.push(data);
+    Ok(response)
+}
+
+async fn send_message(
+    sender: &mut mpsc::Sender<WSMessage>,
+    context: Uuid,
+    data: WSMessageData,
+    parent: &Span,
+) -> Result<WsMessageResponse> {
+    let (response_sender, receiver) = sync::channel::<WsMessageResponse>(1);
+    let message = WSMessage::new(context, data, Some(response_sender), parent);
+    let span = message.span.clone();
+    async move {
+        sender.send(message).await?;
+        receiver
+            .recv()
             .await
             .ok_or_else(|| canvas_error!("failed to receive response from client") as Error)
     }
+    .instrument(span)
+    .await
+}
+
+// sends a message that isn't tied to any context, over a short lived context channel
+async fn send_to_browser(
+    mut browser: mpsc::Sender<ContextRequest>,
+    data: WSMessageData,
+) -> Result<WsMessageResponse> {
+    let (mut sender, receiver) = mpsc::channel::<WSMessage>(1);
+    let (browser_sender, _browser_receiver) = oneshot::channel::<Assignment>();
+    browser
+        .send(ContextRequest {
+            receiver,
+            browser: browser_sender,
+            canvas: None,
+        })
+        .await
+        .map_err(|_| canvas_error!("browser disconnected") as Error)?;
+    send_message(&mut sender, Uuid::nil(), data, &Span::current()).await
+}
+
+impl Canvas2D {
+    async fn create(
+        uuid: Uuid,
+        sender: mpsc::Sender<WSMessage>,
+        browser: Uuid,
+        text_metrics_cache: Option<Arc<TextMetricsCache>>,
+        size_limits: CanvasSizeLimits,
+        data: ContextData2D,
+        width: u32,
+        height: u32,
+    ) -> Result<Canvas2D> {
+        let mut canvas = Canvas2D {
+            uuid,
+            browser,
+            sender,
+            text_metrics_cache,
+            size_limits,
+            state: DrawState::default(),
+            state_stack: vec![],
+            strict: false,
+            width,
+            height,
+            recording: None,
+            closed: false,
+            span: info_span!("canvas", context = %uuid, browser = %browser),
+        };
+        canvas.initialize(data).await?;
+        Ok(canvas)
+    }
+
+    // a canvas that isn't attached to any browser, and records what is drawn on it to be replayed later
+    pub fn new_recorder(width: u32, height: u32) -> Result<Canvas2D> {
+        let size_limits = CanvasSizeLimits::default();
+        validate_canvas_size(&size_limits, width, height)?;
+        let (sender, _) = mpsc::channel::<WSMessage>(1);
+        Ok(Canvas2D {
+            uuid: Uuid::new_v4(),
+            browser: Uuid::nil(),
+            sender,
+            text_metrics_cache: None,
+            size_limits,
+            state: DrawState::default(),
+            state_stack: vec![],
+            strict: false,
+            width,
+            height,
+            recording: Some(vec![]),
+            closed: false,
+            span: Span::none(),
+        })
+    }
+
+    pub fn is_recorder(&self) -> bool {
+        self.recording.is_some()
+    }
+
+    // takes everything recorded so far, leaving the recorder empty
+    pub fn take_recording(&mut self) -> Result<Recording> {
+        let ops = self
+            .recording
+            .as_mut()
+            .ok_or_else(|| canvas_error!("canvas is not a recorder") as Error)?;
+        Ok(Recording::new(self.width, self.height, std::mem::take(ops)))
+    }
+
+    pub(crate) async fn send(&mut self, data: WSMessageData) -> Result<WsMessageResponse> {
+        match &mut self.recording {
+            Some(ops) => record(ops, data),
+            None => send_message(&mut self.sender, self.uuid, data, &self.span).await,
+        }
+    }
+
+    // sends every op in a single message, and mirrors their effect on the drawing state
+    pub(crate) async fn send_batch(&mut self, ops: &[WSMessageData]) -> Result<()> {
+        map_js_error(self.send(WSMessageData::Batch { ops: ops.to_vec() }).await)?;
+        for op in ops {
+            self.mirror(op);
+        }
+        Ok(())
+    }
+
+    // the change an op that was sent without going through its method makes to the mirrored state
+    fn mirror(&mut self, op: &WSMessageData) {
+        match op {
+            WSMessageData::SetStrictMode { strict } => self.strict = *strict,
+            WSMessageData::Save {} => self.state_stack.push(self.state.clone()),
+            WSMessageData::Restore {} => {
+                if let Some(saved) = self.state_stack.pop() {
+                    self.state = saved;
+                }
+            }
+            WSMessageData::Reset {} => self.reset_after_resize(),
+            WSMessageData::SetWidth { width } => {
+                self.width = *width as u32;
+                self.reset_after_resize();
+            }
+            WSMessageData::SetHeight { height } => {
+                self.height = *height as u32;
+                self.reset_after_resize();
+            }
+            WSMessageData::Resize { width, height, .. } => {
+                self.width = *width;
+                self.height = *height;
+                self.reset_after_resize();
+            }
+            WSMessageData::Batch { ops } => {
+                for op in ops {
+                    self.mirror(op);
+                }
+            }
+            _ => self.state.update(op),
+        }
+    }
 
     async fn initialize(&mut self, data: ContextData2D) -> Result<()> {
         map_js_error(
@@ -288,10 +1286,35 @@ impl Canvas2D {
         )
     }
 
+    fn text_metrics_key(&self, font: Option<&str>, text: &str) -> TextMetricsKey {
+        TextMetricsKey {
+            browser: self.browser,
+            font: font.unwrap_or(&self.state.font).to_string(),
+            direction: self.state.text_direction,
+            letter_spacing: self.state.letter_spacing.clone(),
+            word_spacing: self.state.word_spacing.clone(),
+            font_kerning: self.state.font_kerning,
+            font_stretch: self.state.font_stretch,
+            font_variant_caps: self.state.font_variant_caps,
+            text_rendering: self.state.text_rendering,
+            text: text.to_string(),
+        }
+    }
+
     pub async fn measure_text(&mut self, text: String) -> Result<TextMetrics> {
+        let cache = self.text_metrics_cache.clone();
+        let key = self.text_metrics_key(None, &text);
+        if let Some(text_metrics) = cache.as_ref().and_then(|cache| cache.get(&key)) {
+            return Ok(text_metrics);
+        }
         let response = map_js_error_data(self.send(WSMessageData::MeasureText { text }).await)?;
         match response {
-            WsMessageResponse::MeasureText { text_metrics } => Ok(text_metrics),
+            WsMessageResponse::MeasureText { text_metrics } => {
+                if let Some(cache) = cache {
+                    cache.insert(key, text_metrics.clone());
+                }
+                Ok(text_metrics)
+            }
             _ => Err(canvas_error!(
                 "bad packet type received for measure_text: {:?}",
                 response
@@ -299,44 +1322,127 @@ impl Canvas2D {
         }
     }
 
+    // measures every text in a single message, skipping any that are already cached
+    pub async fn measure_texts(&mut self, texts: &[&str]) -> Result<Vec<TextMetrics>> {
+        self.measure_batch(texts.iter().map(|text| text.to_string()).collect(), None)
+            .await
+    }
+
+    pub(crate) async fn measure_batch(
+        &mut self,
+        texts: Vec<String>,
+        font: Option<String>,
+    ) -> Result<Vec<TextMetrics>> {
+        let cache = self.text_metrics_cache.clone();
+        let keys: Vec<TextMetricsKey> = texts
+            .iter()
+            .map(|text| self.text_metrics_key(font.as_deref(), text))
+            .collect();
+        let mut results: Vec<Option<TextMetrics>> = match &cache {
+            Some(cache) => keys.iter().map(|key| cache.get(key)).collect(),
+            None => vec![None; texts.len()],
+        };
+        let missing: Vec<String> = texts
+            .into_iter()
+            .zip(results.iter())
+            .filter(|(_, result)| result.is_none())
+            .map(|(text, _)| text)
+            .collect();
+        if !missing.is_empty() {
+            let expected = missing.len();
+            let response = map_js_error_data(
+                self.send(WSMessageData::MeasureTexts {
+                    texts: missing,
+                    font,
+                })
+                .await,
+            )?;
+            let text_metrics = match response {
+                WsMessageResponse::MeasureTexts { text_metrics } => text_metrics,
+                _ => {
+                    return Err(canvas_error!(
+                        "bad packet type received for measure_texts: {:?}",
+                        response
+                    ))
+                }
+            };
+            if text_metrics.len() != expected {
+                return Err(canvas_error!(
+                    "expected {} text metrics, got {}",
+                    expected,
+                    text_metrics.len()
+                ));
+            }
+            let mut text_metrics = text_metrics.into_iter();
+            for (key, result) in keys.into_iter().zip(results.iter_mut()) {
+                if result.is_none() {
+                    let measured = text_metrics.next().unwrap();
+                    if let Some(cache) = &cache {
+                        cache.insert(key, measured.clone());
+                    }
+                    *result = Some(measured);
+                }
+            }
+        }
+        Ok(results.into_iter().map(|result| result.unwrap()).collect())
+    }
+
+    pub fn get_browser_id(&self) -> Uuid {
+        self.browser
+    }
+
+    pub fn is_strict_mode(&self) -> bool {
+        self.strict
+    }
+
+    // in strict mode, string properties (styles, font, filter, ...) that the browser rejects are an error rather
+    // than silently ignored, and the getters return the browser's normalized value
+    pub async fn set_strict_mode(&mut self, strict: bool) -> Result<()> {
+        let result = map_js_error(self.send(WSMessageData::SetStrictMode { strict }).await);
+        if result.is_ok() {
+            self.strict = strict;
+        }
+        result
+    }
+
     pub fn get_line_width(&self) -> f64 {
-        self.line_width
+        self.state.line_width
     }
 
     pub async fn set_line_width(&mut self, line_width: f64) -> Result<()> {
         let result = map_js_error(self.send(WSMessageData::SetLineWidth { line_width }).await);
         if result.is_ok() {
-            self.line_width = line_width;
+            self.state.line_width = line_width;
         }
         result
     }
 
     pub fn get_line_cap(&self) -> LineCap {
-        self.line_cap
+        self.state.line_cap
     }
 
     pub async fn set_line_cap(&mut self, line_cap: LineCap) -> Result<()> {
         let result = map_js_error(self.send(WSMessageData::SetLineCap { line_cap }).await);
         if result.is_ok() {
-            self.line_cap = line_cap;
+            self.state.line_cap = line_cap;
         }
         result
     }
 
     pub fn get_line_join(&self) -> LineJoin {
-        self.line_join
+        self.state.line_join
     }
 
     pub async fn set_line_join(&mut self, line_join: LineJoin) -> Result<()> {
         let result = map_js_error(self.send(WSMessageData::SetLineJoin { line_join }).await);
         if result.is_ok() {
-            self.line_join = line_join;
+            self.state.line_join = line_join;
         }
         result
     }
 
     pub fn get_miter_limit(&self) -> f64 {
-        self.miter_limit
+        self.state.miter_limit
     }
 
     pub async fn set_miter_limit(&mut self, miter_limit: f64) -> Result<()> {
@@ -345,7 +1451,7 @@ impl Canvas2D {
                 .await,
         );
         if result.is_ok() {
-            self.miter_limit = miter_limit;
+            self.state.miter_limit = miter_limit;
         }
         result
     }
@@ -353,7 +1459,10 @@ impl Canvas2D {
     pub async fn get_line_dash(&mut self) -> Result<Vec<f64>> {
         let response = map_js_error_data(self.send(WSMessageData::GetLineDash {}).await)?;
         match response {
-            WsMessageResponse::LineDash { line_dash } => Ok(line_dash),
+            WsMessageResponse::LineDash { line_dash } => {
+                self.state.line_dash = line_dash.clone();
+                Ok(line_dash)
+            }
             _ => Err(canvas_error!(
                 "bad packet type received for get_line_dash: {:?}",
                 response
@@ -365,7 +1474,10 @@ impl Canvas2D {
         let response =
             map_js_error_data(self.send(WSMessageData::SetLineDash { line_dash }).await)?;
         match response {
-            WsMessageResponse::LineDash { line_dash } => Ok(line_dash),
+            WsMessageResponse::LineDash { line_dash } => {
+                self.state.line_dash = line_dash.clone();
+                Ok(line_dash)
+            }
             _ => Err(canvas_error!(
                 "bad packet type received for set_line_dash: {:?}",
                 response
@@ -374,7 +1486,7 @@ impl Canvas2D {
     }
 
     pub fn get_line_dash_offset(&self) -> f64 {
-        self.line_dash_offset
+        self.state.line_dash_offset
     }
 
     pub async fn set_line_dash_offset(&mut self, line_dash_offset: f64) -> Result<()> {
@@ -383,42 +1495,39 @@ impl Canvas2D {
                 .await,
         );
         if result.is_ok() {
-            self.line_dash_offset = line_dash_offset;
+            self.state.line_dash_offset = line_dash_offset;
         }
         result
     }
 
     pub fn get_font(&self) -> &str {
-        &self.font
+        &self.state.font
     }
 
-    pub async fn set_font(&mut self, font: &str) -> Result<()> {
-        let result = map_js_error(
-            self.send(WSMessageData::SetFont {
-                font: font.to_string(),
-            })
-            .await,
-        );
-        if result.is_ok() {
-            self.font = font.to_string();
-        }
-        result
+    pub async fn set_font<S: Into<String>>(&mut self, font: S) -> Result<()> {
+        let font = font.into();
+        let response = map_js_error_data(
+            self.send(WSMessageData::SetFont { font: font.clone() })
+                .await,
+        )?;
+        self.state.font = property_value(response, &font);
+        Ok(())
     }
 
     pub fn get_text_align(&self) -> TextAlign {
-        self.text_align
+        self.state.text_align
     }
 
     pub async fn set_text_align(&mut self, text_align: TextAlign) -> Result<()> {
         let result = map_js_error(self.send(WSMessageData::SetTextAlign { text_align }).await);
         if result.is_ok() {
-            self.text_align = text_align;
+            self.state.text_align = text_align;
         }
         result
     }
 
     pub fn get_text_baseline(&self) -> TextBaseline {
-        self.text_baseline
+        self.state.text_baseline
     }
 
     pub async fn set_text_baseline(&mut self, text_baseline: TextBaseline) -> Result<()> {
@@ -427,13 +1536,13 @@ impl Canvas2D {
                 .await,
         );
         if result.is_ok() {
-            self.text_baseline = text_baseline;
+            self.state.text_baseline = text_baseline;
         }
         result
     }
 
     pub fn get_text_direction(&self) -> TextDirection {
-        self.text_direction
+        self.state.text_direction
     }
 
     pub async fn set_text_direction(&mut self, text_direction: TextDirection) -> Result<()> {
@@ -442,79 +1551,169 @@ impl Canvas2D {
                 .await,
         );
         if result.is_ok() {
-            self.text_direction = text_direction;
+            self.state.text_direction = text_direction;
         }
         result
     }
 
-    pub fn get_fill_style(&self) -> &str {
-        &self.fill_style
+    pub fn get_letter_spacing(&self) -> &str {
+        &self.state.letter_spacing
     }
 
-    pub async fn set_fill_style(&mut self, fill_style: &str) -> Result<()> {
-        let result = map_js_error(
-            self.send(WSMessageData::SetFillStyle {
-                fill_style: fill_style.to_string(),
+    pub async fn set_letter_spacing(&mut self, letter_spacing: &str) -> Result<()> {
+        let response = map_js_error_data(
+            self.send(WSMessageData::SetLetterSpacing {
+                letter_spacing: letter_spacing.to_string(),
             })
             .await,
+        )?;
+        self.state.letter_spacing = property_value(response, letter_spacing);
+        Ok(())
+    }
+
+    pub fn get_word_spacing(&self) -> &str {
+        &self.state.word_spacing
+    }
+
+    pub async fn set_word_spacing(&mut self, word_spacing: &str) -> Result<()> {
+        let response = map_js_error_data(
+            self.send(WSMessageData::SetWordSpacing {
+                word_spacing: word_spacing.to_string(),
+            })
+            .await,
+        )?;
+        self.state.word_spacing = property_value(response, word_spacing);
+        Ok(())
+    }
+
+    pub fn get_font_kerning(&self) -> FontKerning {
+        self.state.font_kerning
+    }
+
+    pub async fn set_font_kerning(&mut self, font_kerning: FontKerning) -> Result<()> {
+        let result = map_js_error(
+            self.send(WSMessageData::SetFontKerning { font_kerning })
+                .await,
         );
         if result.is_ok() {
-            self.fill_style = fill_style.to_string();
+            self.state.font_kerning = font_kerning;
         }
         result
     }
 
-    pub fn get_stroke_style(&self) -> &str {
-        &self.stroke_style
+    pub fn get_font_stretch(&self) -> FontStretch {
+        self.state.font_stretch
     }
 
-    pub async fn set_stroke_style(&mut self, stroke_style: &str) -> Result<()> {
+    pub async fn set_font_stretch(&mut self, font_stretch: FontStretch) -> Result<()> {
         let result = map_js_error(
-            self.send(WSMessageData::SetStrokeStyle {
-                stroke_style: stroke_style.to_string(),
-            })
-            .await,
+            self.send(WSMessageData::SetFontStretch { font_stretch })
+                .await,
         );
         if result.is_ok() {
-            self.stroke_style = stroke_style.to_string();
+            self.state.font_stretch = font_stretch;
         }
         result
     }
 
-    pub fn get_shadow_blur(&self) -> f64 {
-        self.shadow_blur
+    pub fn get_font_variant_caps(&self) -> FontVariantCaps {
+        self.state.font_variant_caps
     }
 
-    pub async fn set_shadow_blur(&mut self, shadow_blur: f64) -> Result<()> {
+    pub async fn set_font_variant_caps(
+        &mut self,
+        font_variant_caps: FontVariantCaps,
+    ) -> Result<()> {
         let result = map_js_error(
-            self.send(WSMessageData::SetShadowBlur { shadow_blur })
+            self.send(WSMessageData::SetFontVariantCaps { font_variant_caps })
                 .await,
         );
         if result.is_ok() {
-            self.shadow_blur = shadow_blur;
+            self.state.font_variant_caps = font_variant_caps;
         }
         result
     }
 
-    pub fn get_shadow_color(&self) -> &str {
-        &self.shadow_color
+    pub fn get_text_rendering(&self) -> TextRendering {
+        self.state.text_rendering
     }
 
-    pub async fn set_shadow_color(&mut self, shadow_color: &str) -> Result<()> {
+    pub async fn set_text_rendering(&mut self, text_rendering: TextRendering) -> Result<()> {
         let result = map_js_error(
-            self.send(WSMessageData::SetShadowColor {
-                shadow_color: shadow_color.to_string(),
+            self.send(WSMessageData::SetTextRendering { text_rendering })
+                .await,
+        );
+        if result.is_ok() {
+            self.state.text_rendering = text_rendering;
+        }
+        result
+    }
+
+    pub fn get_fill_style(&self) -> &str {
+        &self.state.fill_style
+    }
+
+    pub async fn set_fill_style<S: Into<String>>(&mut self, fill_style: S) -> Result<()> {
+        let fill_style = fill_style.into();
+        let response = map_js_error_data(
+            self.send(WSMessageData::SetFillStyle {
+                fill_style: fill_style.clone(),
+            })
+            .await,
+        )?;
+        self.state.fill_style = property_value(response, &fill_style);
+        Ok(())
+    }
+
+    pub fn get_stroke_style(&self) -> &str {
+        &self.state.stroke_style
+    }
+
+    pub async fn set_stroke_style<S: Into<String>>(&mut self, stroke_style: S) -> Result<()> {
+        let stroke_style = stroke_style.into();
+        let response = map_js_error_data(
+            self.send(WSMessageData::SetStrokeStyle {
+                stroke_style: stroke_style.clone(),
             })
             .await,
+        )?;
+        self.state.stroke_style = property_value(response, &stroke_style);
+        Ok(())
+    }
+
+    pub fn get_shadow_blur(&self) -> f64 {
+        self.state.shadow_blur
+    }
+
+    pub async fn set_shadow_blur(&mut self, shadow_blur: f64) -> Result<()> {
+        let result = map_js_error(
+            self.send(WSMessageData::SetShadowBlur { shadow_blur })
+                .await,
         );
         if result.is_ok() {
-            self.shadow_color = shadow_color.to_string();
+            self.state.shadow_blur = shadow_blur;
         }
         result
     }
 
+    pub fn get_shadow_color(&self) -> &str {
+        &self.state.shadow_color
+    }
+
+    pub async fn set_shadow_color<S: Into<String>>(&mut self, shadow_color: S) -> Result<()> {
+        let shadow_color = shadow_color.into();
+        let response = map_js_error_data(
+            self.send(WSMessageData::SetShadowColor {
+                shadow_color: shadow_color.clone(),
+            })
+            .await,
+        )?;
+        self.state.shadow_color = property_value(response, &shadow_color);
+        Ok(())
+    }
+
     pub fn get_shadow_offset_x(&self) -> f64 {
-        self.shadow_offset_x
+        self.state.shadow_offset_x
     }
 
     pub async fn set_shadow_offset_x(&mut self, shadow_offset_x: f64) -> Result<()> {
@@ -523,13 +1722,13 @@ impl Canvas2D {
                 .await,
         );
         if result.is_ok() {
-            self.shadow_offset_x = shadow_offset_x;
+            self.state.shadow_offset_x = shadow_offset_x;
         }
         result
     }
 
     pub fn get_shadow_offset_y(&self) -> f64 {
-        self.shadow_offset_y
+        self.state.shadow_offset_y
     }
 
     pub async fn set_shadow_offset_y(&mut self, shadow_offset_y: f64) -> Result<()> {
@@ -538,13 +1737,13 @@ impl Canvas2D {
                 .await,
         );
         if result.is_ok() {
-            self.shadow_offset_y = shadow_offset_y;
+            self.state.shadow_offset_y = shadow_offset_y;
         }
         result
     }
 
     pub fn get_global_alpha(&self) -> f64 {
-        self.global_alpha
+        self.state.global_alpha
     }
 
     pub async fn set_global_alpha(&mut self, global_alpha: f64) -> Result<()> {
@@ -553,33 +1752,33 @@ impl Canvas2D {
                 .await,
         );
         if result.is_ok() {
-            self.global_alpha = global_alpha;
+            self.state.global_alpha = global_alpha;
         }
         result
     }
 
     pub fn get_global_composite_operation(&self) -> &str {
-        &self.global_composite_operation
+        &self.state.global_composite_operation
     }
 
-    pub async fn set_global_composite_operation(
+    pub async fn set_global_composite_operation<S: Into<String>>(
         &mut self,
-        global_composite_operation: &str,
+        global_composite_operation: S,
     ) -> Result<()> {
-        let result = map_js_error(
+        let global_composite_operation = global_composite_operation.into();
+        let response = map_js_error_data(
             self.send(WSMessageData::SetGlobalCompositeOperation {
-                global_composite_operation: global_composite_operation.to_string(),
+                global_composite_operation: global_composite_operation.clone(),
             })
             .await,
-        );
-        if result.is_ok() {
-            self.global_composite_operation = global_composite_operation.to_string();
-        }
-        result
+        )?;
+        self.state.global_composite_operation =
+            property_value(response, &global_composite_operation);
+        Ok(())
     }
 
     pub fn get_image_smoothing_enabled(&self) -> bool {
-        self.image_smoothing_enabled
+        self.state.image_smoothing_enabled
     }
 
     pub async fn set_image_smoothing_enabled(
@@ -593,13 +1792,13 @@ impl Canvas2D {
             .await,
         );
         if result.is_ok() {
-            self.image_smoothing_enabled = image_smoothing_enabled;
+            self.state.image_smoothing_enabled = image_smoothing_enabled;
         }
         result
     }
 
     pub fn get_image_smoothing_quality(&self) -> Option<ImageSmoothingQuality> {
-        self.image_smoothing_quality
+        self.state.image_smoothing_quality
     }
 
     pub async fn set_image_smoothing_quality(
@@ -613,33 +1812,43 @@ impl Canvas2D {
             .await,
         );
         if result.is_ok() {
-            self.image_smoothing_quality = image_smoothing_quality;
+            self.state.image_smoothing_quality = image_smoothing_quality;
         }
         result
     }
 
     pub fn get_filter(&self) -> Option<&str> {
-        self.filter.as_ref().map(|o| &**o)
+        self.state.filter.as_deref()
     }
 
-    pub async fn set_filter(&mut self, filter: Option<&str>) -> Result<()> {
-        let result = map_js_error(
+    pub async fn set_filter<F: Into<Filter>>(&mut self, filter: F) -> Result<()> {
+        let filter: Option<String> = filter.into().into();
+        let response = map_js_error_data(
             self.send(WSMessageData::SetFilter {
-                filter: filter.map(|o| o.to_string()),
+                filter: filter.clone(),
             })
             .await,
-        );
-        if result.is_ok() {
-            self.filter = filter.map(|o| o.to_string());
-        }
-        result
+        )?;
+        self.state.filter = match response {
+            WsMessageResponse::Property { value } if value == "none" => None,
+            WsMessageResponse::Property { value } => Some(value),
+            _ => filter,
+        };
+        Ok(())
     }
 
     pub fn get_width(&self) -> u32 {
         self.width
     }
 
+    // resizing resets the whole drawing state in the browser, even if the size doesn't change
+    fn reset_after_resize(&mut self) {
+        self.state = DrawState::default();
+        self.state_stack.clear();
+    }
+
     pub async fn set_width(&mut self, width: u32) -> Result<()> {
+        validate_canvas_size(&self.size_limits, width, self.height)?;
         let result = map_js_error(
             self.send(WSMessageData::SetWidth {
                 width: width as i32,
@@ -648,6 +1857,7 @@ impl Canvas2D {
         );
         if result.is_ok() {
             self.width = width;
+            self.reset_after_resize();
         }
         result
     }
@@ -657,6 +1867,7 @@ impl Canvas2D {
     }
 
     pub async fn set_height(&mut self, height: u32) -> Result<()> {
+        validate_canvas_size(&self.size_limits, self.width, height)?;
         let result = map_js_error(
             self.send(WSMessageData::SetHeight {
                 height: height as i32,
@@ -665,6 +1876,26 @@ impl Canvas2D {
         );
         if result.is_ok() {
             self.height = height;
+            self.reset_after_resize();
+        }
+        result
+    }
+
+    // sets both dimensions at once, optionally keeping the existing pixels anchored at the top left
+    pub async fn resize(&mut self, width: u32, height: u32, preserve_content: bool) -> Result<()> {
+        validate_canvas_size(&self.size_limits, width, height)?;
+        let result = map_js_error(
+            self.send(WSMessageData::Resize {
+                width,
+                height,
+                preserve_content,
+            })
+            .await,
+        );
+        if result.is_ok() {
+            self.width = width;
+            self.height = height;
+            self.reset_after_resize();
         }
         result
     }
@@ -748,9 +1979,13 @@ impl Canvas2D {
         x1: f64,
         y1: f64,
         stops: Vec<(f64, String)>,
-    ) -> Result<()> {
+    ) -> Result<CanvasGradient> {
+        let gradient = CanvasGradient {
+            uuid: Uuid::new_v4(),
+        };
         map_js_error(
             self.send(WSMessageData::CreateLinearGradient {
+                gradient_uuid: gradient.uuid,
                 x0,
                 y0,
                 x1,
@@ -758,7 +1993,8 @@ impl Canvas2D {
                 stops,
             })
             .await,
-        )
+        )?;
+        Ok(gradient)
     }
 
     pub async fn create_radial_gradient(
@@ -770,9 +2006,13 @@ impl Canvas2D {
         y1: f64,
         r1: f64,
         stops: Vec<(f64, String)>,
-    ) -> Result<()> {
+    ) -> Result<CanvasGradient> {
+        let gradient = CanvasGradient {
+            uuid: Uuid::new_v4(),
+        };
         map_js_error(
             self.send(WSMessageData::CreateRadialGradient {
+                gradient_uuid: gradient.uuid,
                 x0,
                 y0,
                 r0,
@@ -782,7 +2022,31 @@ impl Canvas2D {
                 stops,
             })
             .await,
-        )
+        )?;
+        Ok(gradient)
+    }
+
+    pub async fn create_conic_gradient(
+        &mut self,
+        start_angle: f64,
+        x: f64,
+        y: f64,
+        stops: Vec<(f64, String)>,
+    ) -> Result<CanvasGradient> {
+        let gradient = CanvasGradient {
+            uuid: Uuid::new_v4(),
+        };
+        map_js_error(
+            self.send(WSMessageData::CreateConicGradient {
+                gradient_uuid: gradient.uuid,
+                start_angle,
+                x,
+                y,
+                stops,
+            })
+            .await,
+        )?;
+        Ok(gradient)
     }
 
     pub async fn create_pattern(&mut self, url: &str, repitition: PatternRepitition) -> Result<()> {
@@ -949,6 +2213,28 @@ impl Canvas2D {
         )
     }
 
+    pub async fn round_rect<R: Into<CornerRadii>>(
+        &mut self,
+        path_uuid: Option<Uuid>,
+        x: f64,
+        y: f64,
+        width: f64,
+        height: f64,
+        radii: R,
+    ) -> Result<()> {
+        map_js_error(
+            self.send(WSMessageData::RoundRect {
+                path_uuid,
+                x,
+                y,
+                width,
+                height,
+                radii: radii.into(),
+            })
+            .await,
+        )
+    }
+
     pub async fn fill(&mut self, path_uuid: Option<Uuid>, is_even_odd: bool) -> Result<()> {
         map_js_error(
             self.send(WSMessageData::Fill {
@@ -1017,35 +2303,97 @@ impl Canvas2D {
         }
     }
 
-    pub async fn get_transform(&mut self) -> Result<DomMatrix> {
-        let response = map_js_error_data(self.send(WSMessageData::GetTransform {}).await)?;
-        match response {
-            WsMessageResponse::Transform { matrix } => Ok(matrix),
-            _ => Err(canvas_error!(
-                "bad packet type received for get_transform: {:?}",
-                response
-            )),
+    pub fn current_state(&self) -> DrawState {
+        self.state.clone()
+    }
+
+    // sends only the fields of `state` that differ from the current state, in a single message
+    pub async fn apply_state(&mut self, state: &DrawState) -> Result<()> {
+        let delta = self.state.diff(state);
+        if delta == DrawStateDelta::default() {
+            return Ok(());
         }
+        let response =
+            map_js_error_data(self.send(WSMessageData::ApplyState { state: delta }).await)?;
+        let mut state = state.clone();
+        // in strict mode the browser sends back its normalized form of the string properties
+        if let WsMessageResponse::Properties { values } = response {
+            for (property, value) in values {
+                match &*property {
+                    "font" => state.font = value,
+                    "letter_spacing" => state.letter_spacing = value,
+                    "word_spacing" => state.word_spacing = value,
+                    "fill_style" => state.fill_style = value,
+                    "stroke_style" => state.stroke_style = value,
+                    "shadow_color" => state.shadow_color = value,
+                    "global_composite_operation" => state.global_composite_operation = value,
+                    "filter" if value == "none" => state.filter = None,
+                    "filter" => state.filter = Some(value),
+                    _ => {}
+                }
+            }
+        }
+        self.state = state;
+        Ok(())
+    }
+
+    pub fn get_transform(&self) -> DomMatrix {
+        self.state.transform
+    }
+
+    // maps a point from the current user space (what drawing calls take) to canvas pixels
+    pub fn user_to_device(&self, x: f64, y: f64) -> (f64, f64) {
+        self.state.transform.transform_point(x, y)
+    }
+
+    // None if the current transform isn't invertible
+    pub fn device_to_user(&self, x: f64, y: f64) -> Option<(f64, f64)> {
+        self.state
+            .transform
+            .invert()
+            .map(|inverse| inverse.transform_point(x, y))
     }
 
     pub async fn rotate(&mut self, angle: f64) -> Result<()> {
-        map_js_error(self.send(WSMessageData::Rotate { angle }).await)
+        let result = map_js_error(self.send(WSMessageData::Rotate { angle }).await);
+        if result.is_ok() {
+            self.state.transform = self.state.transform.multiply(&DomMatrix::rotation(angle));
+        }
+        result
     }
 
     pub async fn scale(&mut self, x: f64, y: f64) -> Result<()> {
-        map_js_error(self.send(WSMessageData::Scale { x, y }).await)
+        let result = map_js_error(self.send(WSMessageData::Scale { x, y }).await);
+        if result.is_ok() {
+            self.state.transform = self.state.transform.multiply(&DomMatrix::scaling(x, y));
+        }
+        result
     }
 
     pub async fn translate(&mut self, x: f64, y: f64) -> Result<()> {
-        map_js_error(self.send(WSMessageData::Translate { x, y }).await)
+        let result = map_js_error(self.send(WSMessageData::Translate { x, y }).await);
+        if result.is_ok() {
+            self.state.transform = self.state.transform.multiply(&DomMatrix::translation(x, y));
+        }
+        result
     }
 
-    pub async fn transform(&mut self, matrix: DomMatrix) -> Result<()> {
-        map_js_error(self.send(WSMessageData::Transform { matrix }).await)
+    pub async fn transform<M: Into<DomMatrix>>(&mut self, matrix: M) -> Result<()> {
+        let matrix = matrix.into();
+        let result = map_js_error(self.send(WSMessageData::Transform { matrix }).await);
+        if result.is_ok() {
+            self.state.transform = self.state.transform.multiply(&matrix);
+        }
+        result
     }
 
-    pub async fn set_transform(&mut self, matrix: DomMatrix) -> Result<()> {
-        map_js_error(self.send(WSMessageData::SetTransform { matrix }).await)
+    pub async fn set_transform<M: Into<DomMatrix>>(&mut self, matrix: M) -> Result<()> {
+        let matrix = matrix.into();
+        let result = map_js_error(self.send(WSMessageData::SetTransform { matrix }).await);
+        if result.is_ok() {
+            self.state.transform = matrix;
+        }
+        result
     }
 
     pub async fn draw_image(
@@ -1068,6 +2416,75 @@ impl Canvas2D {
         )
     }
 
+    // draws `other` onto this canvas, copying pixels through the server if the two canvases live in different browsers
+    pub async fn draw_canvas(
+        &mut self,
+        other: &Canvas2D,
+        source_rect: Option<(f64, f64, f64, f64)>,
+        dest_rect: (f64, f64, f64, f64),
+    ) -> Result<()> {
+        if other.browser == self.browser {
+            return map_js_error(
+                self.send(WSMessageData::DrawCanvas {
+                    source_context: other.uuid,
+                    source: source_rect,
+                    dest: dest_rect,
+                })
+                .await,
+            );
+        }
+        let (sx, sy, sw, sh) =
+            source_rect.unwrap_or((0.0, 0.0, other.width as f64, other.height as f64));
+        let response = map_js_error_data(
+            send_message(
+                &mut other.sender.clone(),
+                other.uuid,
+                WSMessageData::GetImageData { sx, sy, sw, sh },
+                &other.span,
+            )
+            .await,
+        )?;
+        let image_data = match response {
+            WsMessageResponse::ImageData { image_data } => image_data,
+            _ => {
+                return Err(canvas_error!(
+                    "bad packet type received for draw_canvas: {:?}",
+                    response
+                ))
+            }
+        };
+        map_js_error(
+            self.send(WSMessageData::DrawImageData {
+                image_data,
+                dest: dest_rect,
+            })
+            .await,
+        )
+    }
+
+    // images are cached per browser by url, so this benefits every canvas on the same browser
+    pub async fn preload_images(&mut self, urls: &[&str]) -> Result<()> {
+        map_js_error(
+            self.send(WSMessageData::PreloadImages {
+                urls: urls.iter().map(|url| url.to_string()).collect(),
+            })
+            .await,
+        )
+    }
+
+    pub async fn evict_images(&mut self, urls: &[&str]) -> Result<()> {
+        map_js_error(
+            self.send(WSMessageData::EvictImages {
+                urls: Some(urls.iter().map(|url| url.to_string()).collect()),
+            })
+            .await,
+        )
+    }
+
+    pub async fn clear_image_cache(&mut self) -> Result<()> {
+        map_js_error(self.send(WSMessageData::EvictImages { urls: None }).await)
+    }
+
     pub async fn get_image_data(
         &mut self,
         sx: f64,
@@ -1109,20 +2526,98 @@ impl Canvas2D {
     }
 
     pub async fn save(&mut self) -> Result<()> {
-        map_js_error(self.send(WSMessageData::Save {}).await)
+        let result = map_js_error(self.send(WSMessageData::Save {}).await);
+        if result.is_ok() {
+            self.state_stack.push(self.state.clone());
+        }
+        result
     }
 
+    // like the browser, restoring with nothing saved does nothing
     pub async fn restore(&mut self) -> Result<()> {
-        map_js_error(self.send(WSMessageData::Restore {}).await)
+        let result = map_js_error(self.send(WSMessageData::Restore {}).await);
+        if result.is_ok() {
+            if let Some(state) = self.state_stack.pop() {
+                self.state = state;
+            }
+        }
+        result
+    }
+
+    pub(crate) fn state_depth(&self) -> usize {
+        self.state_stack.len()
+    }
+
+    // runs `f` between a save() and a restore(), restoring even if `f` fails or leaves its own saves on the stack
+    // i.e. `canvas.with_saved_state(|canvas| Box::pin(async move { canvas.fill_rect(0.0, 0.0, 1.0, 1.0).await })).await`
+    pub async fn with_saved_state<T, F>(&mut self, f: F) -> Result<T>
+    where
+        F: for<'a> FnOnce(&'a mut Canvas2D) -> BoxFuture<'a, Result<T>>,
+    {
+        self.save().await?;
+        let depth = self.state_stack.len();
+        let result = f(self).await;
+        while self.state_stack.len() >= depth {
+            if let Err(e) = self.restore().await {
+                return result.and(Err(e));
+            }
+        }
+        result
+    }
+
+    // destroys the canvas in the browser, waiting until it has been. dropping a canvas destroys it too, but without
+    // waiting or reporting failures
+    pub async fn close(mut self) -> Result<()> {
+        self.closed = true;
+        if self.recording.is_some() {
+            return Ok(());
+        }
+        map_js_error(
+            self.send(WSMessageData::Destroy2DCanvas { confirm: true })
+                .await,
+        )
+    }
+
+    // clears the canvas and returns every drawing state property to its default
+    pub async fn reset(&mut self) -> Result<()> {
+        let result = map_js_error(self.send(WSMessageData::Reset {}).await);
+        if result.is_ok() {
+            self.state = DrawState::default();
+            self.state_stack.clear();
+        }
+        result
+    }
+
+    pub async fn get_context_attributes(&mut self) -> Result<ContextData2D> {
+        let response = map_js_error_data(self.send(WSMessageData::GetContextAttributes {}).await)?;
+        match response {
+            WsMessageResponse::ContextAttributes { attributes } => Ok(attributes),
+            _ => Err(canvas_error!(
+                "bad packet type received for get_context_attributes: {:?}",
+                response
+            )),
+        }
+    }
+
+    pub async fn is_context_lost(&mut self) -> Result<bool> {
+        let response = map_js_error_data(self.send(WSMessageData::IsContextLost {}).await)?;
+        match response {
+            WsMessageResponse::ContextLost { is_lost } => Ok(is_lost),
+            _ => Err(canvas_error!(
+                "bad packet type received for is_context_lost: {:?}",
+                response
+            )),
+        }
     }
 
-    pub async fn to_data_url(&mut self, mime_type: &str, quality: Option<f64>) -> Result<String> {
+    pub async fn to_data_url(
+        &mut self,
+        format: ImageFormat,
+        quality: Option<f64>,
+    ) -> Result<String> {
         let response = map_js_error_data(
-            self.send(WSMessageData::ToDataUrl {
-                mime_type: mime_type.to_string(),
-                quality,
-            })
-            .await,
+            self.send(WSMessageData::ToDataUrl { format, quality })
+                .await,
         )?;
         match response {
             WsMessageResponse::DataUrl { data_url } => Ok(data_url),
@@ -1132,25 +2627,828 @@ impl Canvas2D {
             )),
         }
     }
+
+    // the encoded image, errors if the browser can't encode to `format` rather than falling back to PNG
+    pub async fn export(&mut self, format: ImageFormat, quality: Option<f64>) -> Result<Vec<u8>> {
+        let response =
+            map_js_error_data(self.send(WSMessageData::Export { format, quality }).await)?;
+        match response {
+            WsMessageResponse::Blob { data } => Ok(data),
+            _ => Err(canvas_error!(
+                "bad packet type received for export: {:?}",
+                response
+            )),
+        }
+    }
 }
 
-async fn send_out_of_band(mut sender: mpsc::Sender<WSMessage>, context: Uuid, data: WSMessageData) {
-    sender
-        .send(WSMessage {
-            context,
-            data,
-            response: None,
-        })
-        .await
-        .unwrap_or(());
+lazy_static! {
+    // destroys the canvases whose queue was full when they were dropped, once each has room, from a single thread
+    static ref DESTROYER: UnboundedSender<(mpsc::Sender<WSMessage>, WSMessage)> = {
+        let (sender, receiver) = unbounded::<(mpsc::Sender<WSMessage>, WSMessage)>();
+        std::thread::Builder::new()
+            .name("remote-canvas-destroyer".to_string())
+            .spawn(move || {
+                futures::executor::block_on(receiver.for_each_concurrent(
+                    None,
+                    |(mut sender, message)| async move { sender.send(message).await.unwrap_or(()) },
+                ))
+            })
+            .unwrap();
+        sender
+    };
 }
 
 impl Drop for Canvas2D {
+    // enqueued without waiting, so this works outside of any runtime. if the queue is full the destroyer sends it
+    // once there's room
     fn drop(&mut self) {
-        tokio::spawn(send_out_of_band(
-            self.sender.clone(),
+        if self.recording.is_some() || self.closed {
+            return;
+        }
+        let message = WSMessage::new(
             self.uuid,
-            WSMessageData::Destroy2DCanvas {},
-        ));
+            WSMessageData::Destroy2DCanvas { confirm: false },
+            None,
+            &self.span,
+        );
+        if let Err(mpsc::error::TrySendError::Full(message)) = self.sender.try_send(message) {
+            DESTROYER
+                .unbounded_send((self.sender.clone(), message))
+                .unwrap_or(());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::websocket::tests::{
+        fake_browser, fake_browser_with, measured, succeed, FakeAnswer, FakeEvent,
+    };
+
+    #[test]
+    fn draw_state_diff() {
+        let current = DrawState::default();
+        let target = DrawState {
+            fill_style: "#ff0000".to_string(),
+            filter: Some("blur(1px)".to_string()),
+            ..current.clone()
+        };
+        let delta = current.diff(&target);
+        assert_eq!(
+            delta,
+            DrawStateDelta {
+                fill_style: Some("#ff0000".to_string()),
+                filter: Some(Some("blur(1px)".to_string())),
+                ..DrawStateDelta::default()
+            }
+        );
+        assert_eq!(
+            serde_json::to_string(&target.diff(&current)).unwrap(),
+            r##"{"fill_style":"#000","filter":null}"##
+        );
+        assert_eq!(current.diff(&current), DrawStateDelta::default());
+    }
+
+    #[tokio::test]
+    async fn creation_without_browsers() {
+        let (sender, _receiver) = sync::channel::<ContextRequest>(8);
+        let browsers = Arc::new(Browsers::default());
+        let generator = CanvasGenerator::new(sender, browsers, Arc::new(Metrics::default()))
+            .with_creation_timeout(Duration::from_millis(10));
+        let error = generator
+            .make_2d_canvas(Default::default(), 10, 10)
+            .await
+            .err()
+            .unwrap();
+        assert!(error.downcast_ref::<NoBrowserAvailable>().is_some());
+        assert_eq!(generator.get_pending_creations(), 0);
+
+        let limited = generator.clone().with_max_pending_creations(0);
+        let error = limited
+            .make_2d_canvas(Default::default(), 10, 10)
+            .await
+            .err()
+            .unwrap();
+        assert!(error.downcast_ref::<CreationQueueFull>().is_some());
+
+        assert!(generator
+            .wait_for_browsers(1, Duration::from_millis(10))
+            .await
+            .is_err());
+        generator.browsers.register();
+        assert!(generator
+            .wait_for_browsers(1, Duration::from_millis(10))
+            .await
+            .is_ok());
+    }
+
+    #[actix_rt::test]
+    async fn registering_a_font_forgets_measurements() {
+        let generator = crate::start_server("127.0.0.1:7098", "127.0.0.1:7099")
+            .unwrap()
+            .with_text_metrics_cache(16);
+        let ops = fake_browser_with("127.0.0.1:7099", |op, packet| match op {
+            "MeasureText" => measured(10.0),
+            _ => succeed(op, packet),
+        });
+        generator
+            .wait_for_browsers(1, Duration::from_secs(5))
+            .await
+            .unwrap();
+        let mut canvas = generator
+            .make_2d_canvas(Default::default(), 10, 10)
+            .await
+            .unwrap();
+        canvas.measure_text("text".to_string()).await.unwrap();
+        canvas.measure_text("text".to_string()).await.unwrap();
+        generator
+            .register_font("Test", vec![0u8], Default::default())
+            .await
+            .unwrap();
+        canvas.measure_text("text".to_string()).await.unwrap();
+        let measured = ops
+            .try_iter()
+            .filter(|event| *event == FakeEvent::Received("MeasureText".to_string()))
+            .count();
+        assert_eq!(measured, 2);
+    }
+
+    #[actix_rt::test]
+    async fn creation_times_out_while_initializing() {
+        let generator = crate::start_server("127.0.0.1:7100", "127.0.0.1:7101")
+            .unwrap()
+            .with_creation_timeout(Duration::from_millis(200));
+        let ops = fake_browser_with("127.0.0.1:7101", |op, packet| match op {
+            "Init2DCanvas" => FakeAnswer::Nothing,
+            _ => succeed(op, packet),
+        });
+        generator
+            .wait_for_browsers(1, Duration::from_secs(5))
+            .await
+            .unwrap();
+        let error = generator
+            .make_2d_canvas(Default::default(), 10, 10)
+            .await
+            .err()
+            .unwrap();
+        assert!(error.downcast_ref::<NoBrowserAvailable>().is_some());
+        // the canvas it gave up on is destroyed
+        let destroyed = FakeEvent::Received("Destroy2DCanvas".to_string());
+        for _ in 0..100 {
+            if ops.try_iter().any(|event| event == destroyed) {
+                return;
+            }
+            tokio::time::delay_for(Duration::from_millis(10)).await;
+        }
+        panic!("the canvas wasn't destroyed");
+    }
+
+    #[actix_rt::test]
+    async fn counts_canvases_until_their_channel_closes() {
+        let generator = crate::start_server("127.0.0.1:7102", "127.0.0.1:7103").unwrap();
+        let _ops = fake_browser("127.0.0.1:7103");
+        generator
+            .wait_for_browsers(1, Duration::from_secs(5))
+            .await
+            .unwrap();
+        let mut canvas = generator
+            .make_2d_canvas(Default::default(), 10, 10)
+            .await
+            .unwrap();
+        let gauge = |count: usize| {
+            format!(
+                "remote_canvas_canvases{{browser=\"{}\"}} {}\n",
+                canvas.get_browser_id(),
+                count
+            )
+        };
+        let (live, gone) = (gauge(1), gauge(0));
+        assert!(generator.metrics.render().contains(&live));
+        // dropped without ever sending a destroy
+        canvas.closed = true;
+        drop(canvas);
+        for _ in 0..100 {
+            if generator.metrics.render().contains(&gone) {
+                return;
+            }
+            tokio::time::delay_for(Duration::from_millis(10)).await;
+        }
+        panic!("the canvas is still counted");
+    }
+
+    #[actix_rt::test]
+    async fn caches_images_in_the_browser() {
+        let generator = crate::start_server("127.0.0.1:7104", "127.0.0.1:7105").unwrap();
+        let (sender, packets) = std::sync::mpsc::channel();
+        let _ops = fake_browser_with("127.0.0.1:7105", move |op, packet| {
+            sender.send(packet["data"].clone()).unwrap_or(());
+            match op {
+                "PreloadImages" if packet["data"][op]["urls"][0] == "missing.png" => {
+                    FakeAnswer::Data(serde_json::json!({
+                        "JsError": { "message": "failed to load missing.png" }
+                    }))
+                }
+                _ => succeed(op, packet),
+            }
+        });
+        generator
+            .wait_for_browsers(1, Duration::from_secs(5))
+            .await
+            .unwrap();
+        let mut canvas = generator
+            .make_2d_canvas(Default::default(), 10, 10)
+            .await
+            .unwrap();
+        canvas.preload_images(&["a.png", "b.png"]).await.unwrap();
+        canvas
+            .draw_image("a.png", None, 1.0, 2.0, Some((3.0, 4.0)))
+            .await
+            .unwrap();
+        canvas.evict_images(&["a.png"]).await.unwrap();
+        canvas.clear_image_cache().await.unwrap();
+        let error = canvas.preload_images(&["missing.png"]).await.err().unwrap();
+        assert!(error.to_string().contains("failed to load missing.png"));
+
+        let packets: Vec<serde_json::Value> = packets.try_iter().skip(1).collect();
+        assert_eq!(
+            packets[..2],
+            [
+                serde_json::json!({ "PreloadImages": { "urls": ["a.png", "b.png"] } }),
+                serde_json::json!({ "DrawImage": {
+                    "url": "a.png",
+                    "source": null,
+                    "dx": 1.0,
+                    "dy": 2.0,
+                    "d_dims": [3.0, 4.0],
+                } }),
+            ]
+        );
+        assert_eq!(
+            packets[2..4],
+            [
+                serde_json::json!({ "EvictImages": { "urls": ["a.png"] } }),
+                serde_json::json!({ "EvictImages": { "urls": null } }),
+            ]
+        );
+    }
+
+    // a browser with a single red pixel to copy, which passes on the packets it's sent
+    fn pixel_browser(
+        packets: std::sync::mpsc::Sender<serde_json::Value>,
+    ) -> impl Fn(&str, &serde_json::Value) -> FakeAnswer + Send + 'static {
+        move |op, packet| {
+            packets.send(packet["data"].clone()).unwrap_or(());
+            match op {
+                "GetImageData" => FakeAnswer::Data(serde_json::json!({
+                    "ImageData": { "image_data": { "data": [255, 0, 0, 255], "width": 1, "height": 1 } }
+                })),
+                _ => succeed(op, packet),
+            }
+        }
+    }
+
+    #[actix_rt::test]
+    async fn draws_canvases_within_and_across_browsers() {
+        let generator = crate::start_server("127.0.0.1:7106", "127.0.0.1:7107").unwrap();
+        let (first, first_packets) = std::sync::mpsc::channel();
+        let (second, second_packets) = std::sync::mpsc::channel();
+        let _first = fake_browser_with("127.0.0.1:7107", pixel_browser(first));
+        let _second = fake_browser_with("127.0.0.1:7107", pixel_browser(second));
+        generator
+            .wait_for_browsers(2, Duration::from_secs(5))
+            .await
+            .unwrap();
+        let source = generator
+            .make_2d_canvas(Default::default(), 1, 1)
+            .await
+            .unwrap();
+        // whichever browser the source was initialized in
+        let on_first = first_packets.try_iter().count() > 0;
+        second_packets.try_iter().count();
+        let (source_packets, other_packets) = if on_first {
+            (first_packets, second_packets)
+        } else {
+            (second_packets, first_packets)
+        };
+
+        // placed next to the source even though the other browser has fewer canvases
+        let mut near = generator
+            .make_2d_canvas_near(Default::default(), 1, 1, &source)
+            .await
+            .unwrap();
+        assert_eq!(near.get_browser_id(), source.get_browser_id());
+        let dest = (0.0, 0.0, 2.0, 2.0);
+        near.draw_canvas(&source, None, dest).await.unwrap();
+        let packets: Vec<serde_json::Value> = source_packets.try_iter().skip(1).collect();
+        assert_eq!(
+            packets,
+            vec![serde_json::json!({ "DrawCanvas": {
+                "source_context": source.uuid,
+                "source": null,
+                "dest": [0.0, 0.0, 2.0, 2.0],
+            } })]
+        );
+
+        // the pixels go through the server between browsers
+        let mut other = generator
+            .make_2d_canvas(Default::default(), 1, 1)
+            .await
+            .unwrap();
+        assert_ne!(other.get_browser_id(), source.get_browser_id());
+        other
+            .draw_canvas(&source, Some((0.0, 0.0, 1.0, 1.0)), dest)
+            .await
+            .unwrap();
+        assert_eq!(
+            source_packets.try_recv().unwrap(),
+            serde_json::json!({ "GetImageData": { "sx": 0.0, "sy": 0.0, "sw": 1.0, "sh": 1.0 } })
+        );
+        let packets: Vec<serde_json::Value> = other_packets.try_iter().skip(1).collect();
+        assert_eq!(
+            packets,
+            vec![serde_json::json!({ "DrawImageData": {
+                "image_data": { "data": [255, 0, 0, 255], "width": 1, "height": 1 },
+                "dest": [0.0, 0.0, 2.0, 2.0],
+            } })]
+        );
+    }
+
+    #[actix_rt::test]
+    async fn exports_binary_images() {
+        let generator = crate::start_server("127.0.0.1:7108", "127.0.0.1:7109").unwrap();
+        let (sender, packets) = std::sync::mpsc::channel();
+        let _ops = fake_browser_with("127.0.0.1:7109", move |op, packet| {
+            sender.send(packet["data"].clone()).unwrap_or(());
+            match (op, packet["data"][op]["format"].as_str()) {
+                ("Export", Some("image/png")) => FakeAnswer::Binary(vec![0x89, b'P', b'N', b'G']),
+                ("Export", _) => FakeAnswer::Data(serde_json::json!({
+                    "JsError": { "message": "browser cannot encode image/webp, fell back to image/png" }
+                })),
+                _ => succeed(op, packet),
+            }
+        });
+        generator
+            .wait_for_browsers(1, Duration::from_secs(5))
+            .await
+            .unwrap();
+        let mut canvas = generator
+            .make_2d_canvas(Default::default(), 10, 10)
+            .await
+            .unwrap();
+        let png = canvas.export(ImageFormat::Png, Some(0.5)).await.unwrap();
+        assert_eq!(png, vec![0x89, b'P', b'N', b'G']);
+        let error = canvas.export(ImageFormat::Webp, None).await.err().unwrap();
+        assert!(error.to_string().contains("cannot encode image/webp"));
+        let packets: Vec<serde_json::Value> = packets.try_iter().skip(1).collect();
+        assert_eq!(
+            packets,
+            vec![
+                serde_json::json!({ "Export": { "format": "image/png", "quality": 0.5 } }),
+                serde_json::json!({ "Export": { "format": "image/webp", "quality": null } }),
+            ]
+        );
+
+        // there's nothing to export from a recorder
+        let mut recorder = Canvas2D::new_recorder(10, 10).unwrap();
+        assert!(recorder.export(ImageFormat::Png, None).await.is_err());
+    }
+
+    #[actix_rt::test]
+    async fn registers_fonts_in_every_browser() {
+        let generator = crate::start_server("127.0.0.1:7110", "127.0.0.1:7111").unwrap();
+        let (sender, packets) = std::sync::mpsc::channel();
+        let first = sender.clone();
+        let _first = fake_browser_with("127.0.0.1:7111", move |op, packet| {
+            first.send(packet["data"].clone()).unwrap_or(());
+            succeed(op, packet)
+        });
+        let _second = fake_browser_with("127.0.0.1:7111", move |op, packet| {
+            sender.send(packet["data"].clone()).unwrap_or(());
+            match packet["data"][op]["family"].as_str() {
+                Some("Broken") => FakeAnswer::Data(serde_json::json!({
+                    "JsError": { "message": "failed to load font" }
+                })),
+                _ => succeed(op, packet),
+            }
+        });
+        generator
+            .wait_for_browsers(2, Duration::from_secs(5))
+            .await
+            .unwrap();
+        let descriptors = FontDescriptors {
+            weight: Some("bold".to_string()),
+            ..Default::default()
+        };
+        generator
+            .register_font("Test", "https://example.com/test.woff2", descriptors)
+            .await
+            .unwrap();
+        let registered = serde_json::json!({ "RegisterFont": {
+            "family": "Test",
+            "source": { "Url": "https://example.com/test.woff2" },
+            "descriptors": { "weight": "bold" },
+        } });
+        let packets: Vec<serde_json::Value> = packets.try_iter().collect();
+        assert_eq!(packets, vec![registered.clone(), registered]);
+
+        // a browser failing to load it fails the registration, though it's still sent to browsers that connect later
+        let error = generator
+            .register_font("Broken", vec![0u8, 1], Default::default())
+            .await
+            .err()
+            .unwrap();
+        assert!(error.to_string().contains("failed to load font"));
+        let families: Vec<String> = generator
+            .browsers
+            .fonts()
+            .into_iter()
+            .map(|font| font.family)
+            .collect();
+        assert_eq!(families, vec!["Test", "Broken"]);
+    }
+
+    #[actix_rt::test]
+    async fn strict_mode_mirrors_normalized_values() {
+        let generator = crate::start_server("127.0.0.1:7112", "127.0.0.1:7113").unwrap();
+        fake_browser_with("127.0.0.1:7113", |op, packet| {
+            let data = &packet["data"][op];
+            match op {
+                "SetFillStyle" if data["fill_style"] == "nope" => FakeAnswer::Data(
+                    serde_json::json!({ "JsError": { "message": "invalid fill style" } }),
+                ),
+                "SetFillStyle" => {
+                    FakeAnswer::Data(serde_json::json!({ "Property": { "value": "#ff0000" } }))
+                }
+                "SetFilter" => {
+                    FakeAnswer::Data(serde_json::json!({ "Property": { "value": "none" } }))
+                }
+                "ApplyState" => FakeAnswer::Data(serde_json::json!({ "Properties": {
+                    "values": { "stroke_style": "#0000ff" }
+                } })),
+                _ => succeed(op, packet),
+            }
+        });
+        generator
+            .wait_for_browsers(1, Duration::from_secs(5))
+            .await
+            .unwrap();
+        let mut canvas = generator
+            .make_2d_canvas(Default::default(), 10, 10)
+            .await
+            .unwrap();
+        canvas.set_strict_mode(true).await.unwrap();
+        assert!(canvas.is_strict_mode());
+
+        canvas.set_fill_style("red").await.unwrap();
+        assert_eq!(canvas.get_fill_style(), "#ff0000");
+        // a rejected value leaves the mirrored state alone
+        assert!(canvas.set_fill_style("nope").await.is_err());
+        assert_eq!(canvas.get_fill_style(), "#ff0000");
+        canvas.set_filter("blur(0px)").await.unwrap();
+        assert_eq!(canvas.get_filter(), None);
+
+        let mut state = canvas.current_state();
+        state.stroke_style = "blue".to_string();
+        state.line_width = 3.0;
+        canvas.apply_state(&state).await.unwrap();
+        assert_eq!(canvas.get_stroke_style(), "#0000ff");
+        assert_eq!(canvas.get_line_width(), 3.0);
+    }
+
+    // the fields of every span created while it's the default subscriber, by span id - 1
+    #[derive(Clone, Default)]
+    struct SpanRecorder(Arc<std::sync::Mutex<Vec<RecordedSpan>>>);
+
+    #[derive(Debug, Default)]
+    struct RecordedSpan {
+        name: &'static str,
+        parent: Option<u64>,
+        fields: std::collections::HashMap<&'static str, String>,
+    }
+
+    impl tracing::field::Visit for RecordedSpan {
+        fn record_str(&mut self, field: &tracing::field::Field, value: &str) {
+            self.fields.insert(field.name(), value.to_string());
+        }
+
+        fn record_debug(&mut self, field: &tracing::field::Field, value: &dyn std::fmt::Debug) {
+            self.fields.insert(field.name(), format!("{:?}", value));
+        }
+    }
+
+    impl tracing::Subscriber for SpanRecorder {
+        fn enabled(&self, _: &tracing::Metadata) -> bool {
+            true
+        }
+
+        fn new_span(&self, attributes: &tracing::span::Attributes) -> tracing::Id {
+            let mut span = RecordedSpan {
+                name: attributes.metadata().name(),
+                parent: attributes.parent().map(|parent| parent.into_u64()),
+                ..Default::default()
+            };
+            attributes.record(&mut span);
+            let mut spans = self.0.lock().unwrap();
+            spans.push(span);
+            tracing::Id::from_u64(spans.len() as u64)
+        }
+
+        fn record(&self, id: &tracing::Id, values: &tracing::span::Record) {
+            values.record(&mut self.0.lock().unwrap()[id.into_u64() as usize - 1]);
+        }
+
+        fn record_follows_from(&self, _: &tracing::Id, _: &tracing::Id) {}
+
+        fn event(&self, _: &tracing::Event) {}
+
+        fn enter(&self, _: &tracing::Id) {}
+
+        fn exit(&self, _: &tracing::Id) {}
+    }
+
+    #[actix_rt::test]
+    async fn spans_carry_the_canvas_and_op_timings() {
+        let recorder = SpanRecorder::default();
+        let _guard = tracing::subscriber::set_default(recorder.clone());
+        let generator = crate::start_server("127.0.0.1:7114", "127.0.0.1:7115").unwrap();
+        fake_browser("127.0.0.1:7115");
+        generator
+            .wait_for_browsers(1, Duration::from_secs(5))
+            .await
+            .unwrap();
+        let mut canvas = generator
+            .make_2d_canvas(Default::default(), 10, 10)
+            .await
+            .unwrap();
+        canvas.fill_rect(0.0, 0.0, 1.0, 1.0).await.unwrap();
+
+        let spans = recorder.0.lock().unwrap();
+        let (id, span) = spans
+            .iter()
+            .enumerate()
+            .find(|(_, span)| span.name == "canvas")
+            .unwrap();
+        assert_eq!(span.fields["context"], canvas.uuid.to_string());
+        assert_eq!(span.fields["browser"], canvas.get_browser_id().to_string());
+        let send = spans
+            .iter()
+            .find(|span| span.name == "send" && span.fields["op"] == "FillRect")
+            .unwrap();
+        assert_eq!(send.parent, Some(id as u64 + 1));
+        assert!(Uuid::parse_str(&send.fields["txn"]).is_ok());
+        for timing in &["queue_ms", "browser_ms"] {
+            assert!(send.fields[timing].parse::<f64>().is_ok());
+        }
+    }
+
+    #[tokio::test]
+    async fn mirrors_text_properties() {
+        let mut canvas = Canvas2D::new_recorder(10, 10).unwrap();
+        let key = canvas.text_metrics_key(None, "text");
+        canvas.save().await.unwrap();
+        canvas.set_letter_spacing("2px").await.unwrap();
+        canvas.set_word_spacing("4px").await.unwrap();
+        canvas.set_font_kerning(FontKerning::None).await.unwrap();
+        canvas
+            .set_font_stretch(FontStretch::UltraCondensed)
+            .await
+            .unwrap();
+        canvas
+            .set_font_variant_caps(FontVariantCaps::SmallCaps)
+            .await
+            .unwrap();
+        canvas
+            .set_text_rendering(TextRendering::OptimizeLegibility)
+            .await
+            .unwrap();
+        assert_eq!(canvas.get_letter_spacing(), "2px");
+        assert_eq!(canvas.get_word_spacing(), "4px");
+        assert_eq!(canvas.get_font_kerning(), FontKerning::None);
+        assert_eq!(canvas.get_font_stretch(), FontStretch::UltraCondensed);
+        assert_eq!(canvas.get_font_variant_caps(), FontVariantCaps::SmallCaps);
+        assert_eq!(
+            canvas.get_text_rendering(),
+            TextRendering::OptimizeLegibility
+        );
+        // text measured with other properties isn't answered from the cache
+        assert_ne!(canvas.text_metrics_key(None, "text"), key);
+
+        let recording = canvas.take_recording().unwrap();
+        let ops: Vec<serde_json::Value> = recording.ops()[1..]
+            .iter()
+            .map(|op| serde_json::to_value(op).unwrap())
+            .collect();
+        assert_eq!(
+            ops,
+            vec![
+                serde_json::json!({ "SetLetterSpacing": { "letter_spacing": "2px" } }),
+                serde_json::json!({ "SetWordSpacing": { "word_spacing": "4px" } }),
+                serde_json::json!({ "SetFontKerning": { "font_kerning": "none" } }),
+                serde_json::json!({ "SetFontStretch": { "font_stretch": "ultra-condensed" } }),
+                serde_json::json!({ "SetFontVariantCaps": { "font_variant_caps": "small-caps" } }),
+                serde_json::json!({ "SetTextRendering": { "text_rendering": "optimizeLegibility" } }),
+            ]
+        );
+
+        // they're part of the drawing state
+        canvas.restore().await.unwrap();
+        assert_eq!(canvas.get_letter_spacing(), "0px");
+        assert_eq!(canvas.get_font_stretch(), FontStretch::Normal);
+        assert_eq!(canvas.text_metrics_key(None, "text"), key);
+    }
+
+    #[tokio::test]
+    async fn records_round_rects_conic_gradients_and_resets() {
+        let mut canvas = Canvas2D::new_recorder(10, 10).unwrap();
+        canvas
+            .round_rect(None, 1.0, 2.0, 3.0, 4.0, 0.5)
+            .await
+            .unwrap();
+        let gradient = canvas
+            .create_conic_gradient(
+                1.5,
+                5.0,
+                5.0,
+                vec![(0.0, "red".to_string()), (1.0, "blue".to_string())],
+            )
+            .await
+            .unwrap();
+        canvas.set_fill_style(gradient).await.unwrap();
+        assert_eq!(
+            canvas.get_fill_style(),
+            format!("url(#gradient-{})", gradient.get_uuid())
+        );
+        canvas.save().await.unwrap();
+        canvas.set_line_width(4.0).await.unwrap();
+        canvas.translate(2.0, 2.0).await.unwrap();
+
+        canvas.reset().await.unwrap();
+        assert_eq!(canvas.get_fill_style(), "#000");
+        assert_eq!(canvas.get_line_width(), 1.0);
+        assert_eq!(canvas.state_depth(), 0);
+        assert_eq!(canvas.get_transform(), DomMatrix::identity());
+        // a reset with nothing saved restores nothing
+        assert!(canvas.restore().await.is_ok());
+
+        let recording = canvas.take_recording().unwrap();
+        let ops: Vec<serde_json::Value> = recording
+            .ops()
+            .iter()
+            .map(|op| serde_json::to_value(op).unwrap())
+            .collect();
+        assert_eq!(
+            ops[0],
+            serde_json::json!({ "RoundRect": {
+                "path_uuid": null,
+                "x": 1.0,
+                "y": 2.0,
+                "width": 3.0,
+                "height": 4.0,
+                "radii": {
+                    "top_left": 0.5,
+                    "top_right": 0.5,
+                    "bottom_right": 0.5,
+                    "bottom_left": 0.5,
+                },
+            } })
+        );
+        assert_eq!(
+            ops[1],
+            serde_json::json!({ "CreateConicGradient": {
+                "gradient_uuid": gradient.get_uuid(),
+                "start_angle": 1.5,
+                "x": 5.0,
+                "y": 5.0,
+                "stops": [[0.0, "red"], [1.0, "blue"]],
+            } })
+        );
+        assert!(ops.contains(&serde_json::json!({ "Reset": {} })));
+    }
+
+    #[tokio::test]
+    async fn resizing_resets_the_drawing_state() {
+        let mut canvas = Canvas2D::new_recorder(10, 10).unwrap();
+        canvas.set_fill_style("#ff0000").await.unwrap();
+        canvas.save().await.unwrap();
+        canvas.translate(5.0, 5.0).await.unwrap();
+        canvas.resize(20, 20, true).await.unwrap();
+        assert_eq!(canvas.get_fill_style(), "#000");
+        assert_eq!(canvas.state_depth(), 0);
+        assert_eq!(canvas.get_transform(), DomMatrix::identity());
+
+        canvas.set_line_width(4.0).await.unwrap();
+        canvas.set_width(30).await.unwrap();
+        assert_eq!(canvas.get_line_width(), 1.0);
+        assert!(canvas.set_height(MAX_CANVAS_DIMENSION + 1).await.is_err());
+        assert_eq!(canvas.get_height(), 20);
+    }
+
+    #[tokio::test]
+    async fn saved_state_is_always_restored() {
+        let mut canvas = Canvas2D::new_recorder(10, 10).unwrap();
+        canvas.set_fill_style("#ff0000").await.unwrap();
+        // saves that `f` leaves behind are restored as well
+        let width = canvas
+            .with_saved_state(|canvas| {
+                Box::pin(async move {
+                    canvas.save().await?;
+                    canvas.set_fill_style("#00ff00").await?;
+                    canvas.save().await?;
+                    canvas.translate(1.0, 1.0).await?;
+                    Ok(canvas.get_width())
+                })
+            })
+            .await
+            .unwrap();
+        assert_eq!(width, 10);
+        assert_eq!(canvas.get_fill_style(), "#ff0000");
+        assert_eq!(canvas.get_transform(), DomMatrix::identity());
+        assert_eq!(canvas.state_depth(), 0);
+        let recording = canvas.take_recording().unwrap();
+        let count = |name| {
+            recording
+                .ops()
+                .iter()
+                .filter(|op| op.name() == name)
+                .count()
+        };
+        assert_eq!((count("Save"), count("Restore")), (3, 3));
+
+        let failed: Result<()> = canvas
+            .with_saved_state(|canvas| {
+                Box::pin(async move {
+                    canvas.set_fill_style("#0000ff").await?;
+                    Err(canvas_error!("failed") as Error)
+                })
+            })
+            .await;
+        assert!(failed.is_err());
+        assert_eq!(canvas.get_fill_style(), "#ff0000");
+        assert_eq!(canvas.state_depth(), 0);
+
+        // like the browser, restoring with nothing saved leaves the state as it is
+        canvas.restore().await.unwrap();
+        assert_eq!(canvas.get_fill_style(), "#ff0000");
+        assert_eq!(canvas.state_depth(), 0);
+    }
+
+    #[tokio::test]
+    async fn rejects_canvases_over_the_size_limits() {
+        let (sender, _receiver) = sync::channel::<ContextRequest>(8);
+        let generator = CanvasGenerator::new(sender, Default::default(), Default::default())
+            .with_size_limits(CanvasSizeLimits {
+                max_dimension: 4096,
+                max_area: 4096 * 4096,
+            });
+        for (width, height) in &[(4097, 1), (4096, 4097)] {
+            assert!(generator
+                .make_2d_canvas(Default::default(), *width, *height)
+                .await
+                .is_err());
+        }
+        assert_eq!(generator.get_pending_creations(), 0);
+    }
+
+    #[test]
+    fn drop_outside_runtime() {
+        let (sender, mut receiver) = mpsc::channel::<WSMessage>(1);
+        // the queue only has room for the first, the others are sent by the destroyer as it's emptied
+        for _ in 0..3 {
+            let mut canvas = Canvas2D::new_recorder(1, 1).unwrap();
+            canvas.sender = sender.clone();
+            canvas.recording = None;
+            drop(canvas);
+        }
+        for _ in 0..3 {
+            match futures::executor::block_on(receiver.recv()).unwrap().data {
+                WSMessageData::Destroy2DCanvas { confirm } => assert!(!confirm),
+                data => panic!("unexpected message: {:?}", data),
+            }
+        }
+    }
+
+    #[test]
+    fn image_data_pixel_order() {
+        let mut image_data = ImageData::from_rgba8(2, 1, vec![1, 2, 3, 4, 5, 6, 7, 8]).unwrap();
+        assert_eq!(image_data.get_pixel(0, 0), 0x01020304);
+        assert_eq!(image_data.get_pixel(1, 0), 0x05060708);
+        assert_eq!(image_data.get_pixel(2, 0), 0);
+        image_data.set_pixel(1, 0, 0xAABBCCDD);
+        assert_eq!(image_data.as_rgba8(), &[1, 2, 3, 4, 0xAA, 0xBB, 0xCC, 0xDD]);
+        assert!(ImageData::from_rgba8(2, 2, vec![0; 8]).is_err());
+    }
+
+    #[cfg(feature = "image")]
+    #[test]
+    fn image_data_round_trip() {
+        use std::convert::TryInto;
+        let mut image = image::RgbaImage::new(3, 2);
+        image.put_pixel(2, 1, image::Rgba([10, 20, 30, 40]));
+        let image_data: ImageData = image.clone().into();
+        assert_eq!(image_data.get_pixel(2, 1), 0x0A141E28);
+        let back: image::RgbaImage = image_data.try_into().unwrap();
+        assert_eq!(back, image);
     }
 }
@@ -22,11 +22,16 @@ const connectionOpened = () => {
     JsError { message: String },
     Success {},
     MeasureText { text_metrics: TextMetrics },
+    MeasureTexts { text_metrics: Vec<TextMetrics> },
     LineDash { line_dash: Vec<f64> },
     IsPointIn { is_in: bool },
-    Transform { matrix: DomMatrix },
+    Property { value: String },
+    Properties { values: HashMap<String, String> },
+    ContextAttributes { attributes: ContextData2D },
+    ContextLost { is_lost: bool },
     ImageData { image_data: ImageData },
     DataUrl { data_url: String },
+    Blob { data: Vec<u8> }, (binary frame)
 
 
 #[derive(Serialize, Deserialize)]
@@ -36,10 +41,26 @@ struct WSNetResponse {
 }
 */
 
+// txn uuid -> responses to the ops of the batch running under it, collected instead of being sent
+const captured = new Map();
+
 const send = (uuid, id, packet) => {
+    if (captured.has(uuid)) {
+        captured.get(uuid).push({ [id]: packet });
+        return;
+    }
     websocket.send(JSON.stringify({ data: { [id]: packet }, txn_uuid: uuid }));
 }
 
+// binary responses are prefixed with the 16 raw bytes of the txn uuid
+const sendBinary = (uuid, buffer) => {
+    const header = uuid.replace(/-/g, '').match(/../g).map(byte => parseInt(byte, 16));
+    const message = new Uint8Array(header.length + buffer.byteLength);
+    message.set(header);
+    message.set(new Uint8Array(buffer), header.length);
+    websocket.send(message);
+}
+
 const genericSuccess = (uuid) => {
     send(uuid, 'Success', {});
 }
@@ -48,6 +69,121 @@ const sendError = (uuid, e) => {
     send(uuid, 'JsError', { message: e.toString() });
 }
 
+// TextMetrics fields are getters on the prototype, so JSON.stringify would drop them
+const textMetricFields = [
+    'width',
+    'actualBoundingBoxLeft',
+    'actualBoundingBoxRight',
+    'fontBoundingBoxAscent',
+    'fontBoundingBoxDescent',
+    'actualBoundingBoxAscent',
+    'actualBoundingBoxDescent',
+    'emHeightAscent',
+    'emHeightDescent',
+    'hangingBaseline',
+    'alphabeticBaseline',
+    'ideographicBaseline',
+];
+
+const textMetrics = metrics => Object.fromEntries(textMetricFields.map(field => [field, metrics[field] == null ? 0 : metrics[field]]));
+
+// invalid values are silently ignored by the browser, so to tell whether one was accepted it is set on a
+// scratch context holding each of two different values, if neither changes it was rejected
+const probeContext = document.createElement('canvas').getContext('2d');
+const probeValues = {
+    font: ['10px serif', '12px monospace'],
+    letterSpacing: ['0px', '1px'],
+    wordSpacing: ['0px', '1px'],
+    fillStyle: ['#000000', '#ffffff'],
+    strokeStyle: ['#000000', '#ffffff'],
+    shadowColor: ['#000000', '#ffffff'],
+    globalCompositeOperation: ['source-over', 'copy'],
+    filter: ['none', 'blur(1px)'],
+};
+
+const isAccepted = (property, value) => probeValues[property].some(probe => {
+    probeContext[property] = probe;
+    probeContext[property] = value;
+    return probeContext[property] != probe;
+});
+
+// gradients are set as styles by reference, i.e. "url(#gradient-<uuid>)"
+const gradientReference = /^url\(#gradient-(.+)\)$/;
+
+const findGradient = (canvi, property, value) => {
+    if (property != 'fillStyle' && property != 'strokeStyle') {
+        return null;
+    }
+    const match = gradientReference.exec(value);
+    if (match == null) {
+        return null;
+    }
+    const gradient = canvi.gradients[match[1]];
+    if (gradient == null) {
+        throw new Error(`unknown gradient: ${value}`);
+    }
+    return gradient;
+};
+
+// in strict mode, rejected values are an error and the browser's normalized value is sent back
+const setProperty = (canvi, txn_uuid, property, value) => {
+    const gradient = findGradient(canvi, property, value);
+    if (gradient == null && canvi.strict && !isAccepted(property, value)) {
+        throw new Error(`invalid value for ${property}: ${value}`);
+    }
+    canvi.ctx[property] = gradient == null ? value : gradient;
+    if (canvi.strict) {
+        send(txn_uuid, 'Property', { value: gradient == null ? String(canvi.ctx[property]) : value });
+    } else {
+        genericSuccess(txn_uuid);
+    }
+};
+
+// DrawState fields that aren't the camel cased context property, or whose values need lower casing
+const stateProperties = { text_direction: 'direction' };
+const lowerCasedState = ['line_cap', 'line_join', 'text_align', 'text_baseline', 'text_direction', 'image_smoothing_quality'];
+
+// applies every field of a DrawState delta, validating all of them before any is set in strict mode
+const applyState = (canvi, txn_uuid, state) => {
+    const ctx = canvi.ctx;
+    const properties = [];
+    for (const [field, value] of Object.entries(state)) {
+        if (field == 'line_dash' || field == 'transform') {
+            continue;
+        }
+        const property = stateProperties[field] || field.replace(/_([a-z])/g, (_, c) => c.toUpperCase());
+        let propertyValue = value;
+        if (field == 'filter' && value == null) {
+            propertyValue = 'none';
+        } else if (lowerCasedState.includes(field) && value != null) {
+            propertyValue = value.toLowerCase();
+        }
+        const gradient = findGradient(canvi, property, propertyValue);
+        if (gradient == null && canvi.strict && probeValues[property] && !isAccepted(property, propertyValue)) {
+            throw new Error(`invalid value for ${property}: ${propertyValue}`);
+        }
+        properties.push([field, property, propertyValue, gradient]);
+    }
+    for (const [, property, value, gradient] of properties) {
+        ctx[property] = gradient == null ? value : gradient;
+    }
+    if (state.line_dash) {
+        ctx.setLineDash(state.line_dash);
+    }
+    if (state.transform) {
+        const { a, b, c, d, e, f } = state.transform;
+        ctx.setTransform(a, b, c, d, e, f);
+    }
+    if (canvi.strict) {
+        const values = Object.fromEntries(properties
+            .filter(([, property]) => probeValues[property])
+            .map(([field, property, value, gradient]) => [field, gradient == null ? String(ctx[property]) : value]));
+        send(txn_uuid, 'Properties', { values });
+    } else {
+        genericSuccess(txn_uuid);
+    }
+};
+
 const canvii = {};
 window.canvii = canvii;
 
@@ -69,10 +205,26 @@ const getTargetImplied = (path_uuid, canvi) => {
     return target;
 };
 
-const receiveMessage = message => {
-    const parsed = JSON.parse(message.data);
-    console.log(message.data);
-    let { data, txn_uuid, context } = parsed;
+// url -> Promise<HTMLImageElement>, shared by every canvas in this browser
+const images = new Map();
+
+const loadImage = url => {
+    let image = images.get(url);
+    if (image == null) {
+        const img = document.createElement('img');
+        img.src = url;
+        image = img.decode().then(() => img, e => {
+            images.delete(url);
+            throw new Error(`failed to load image ${url}: ${e.message}`);
+        });
+        images.set(url, image);
+    }
+    return image;
+};
+
+const toImageData = image_data => new ImageData(new Uint8ClampedArray(image_data.data), image_data.width, image_data.height);
+
+const handlePacket = async ({ data, txn_uuid, context }) => {
     try {
         const [id, packet] = Object.entries(data)[0];
         const canvi = canvii[context];
@@ -87,15 +239,27 @@ const receiveMessage = message => {
                 canvas,
                 ctx,
                 paths: {},
+                gradients: {},
+                strict: false,
             };
             genericSuccess(txn_uuid);
         } else if (id == "Destroy2DCanvas") {
             delete canvii[context];
-            // nobody is listening
-            //genericSuccess(txn_uuid);
+            // nobody is listening unless the canvas was closed explicitly
+            if (packet.confirm) {
+                genericSuccess(txn_uuid);
+            }
         } else if (id == "MeasureText") {
             const metrics = ctx.measureText(packet.text);
-            send(txn_uuid, 'MeasureText', { text_metrics: metrics });
+            send(txn_uuid, 'MeasureText', { text_metrics: textMetrics(metrics) });
+        } else if (id == "MeasureTexts") {
+            ctx.save();
+            if (packet.font != null) {
+                ctx.font = packet.font;
+            }
+            const text_metrics = packet.texts.map(text => textMetrics(ctx.measureText(text)));
+            ctx.restore();
+            send(txn_uuid, 'MeasureTexts', { text_metrics });
         } else if (id == "SetLineWidth") {
             ctx.lineWidth = packet.line_width;
             genericSuccess(txn_uuid);
@@ -118,9 +282,13 @@ const receiveMessage = message => {
         } else if (id == "SetLineDashOffset") {
             ctx.lineDashOffset = packet.line_dash_offset;
             genericSuccess(txn_uuid);
-        } else if (id == "SetFont") {
-            ctx.font = packet.font;
+        } else if (id == "ApplyState") {
+            applyState(canvi, txn_uuid, packet.state);
+        } else if (id == "SetStrictMode") {
+            canvi.strict = packet.strict;
             genericSuccess(txn_uuid);
+        } else if (id == "SetFont") {
+            setProperty(canvi, txn_uuid, 'font', packet.font);
         } else if (id == "SetTextAlign") {
             ctx.textAlign = packet.text_align.toLowerCase();
             genericSuccess(txn_uuid);
@@ -130,18 +298,31 @@ const receiveMessage = message => {
         } else if (id == "SetTextDirection") {
             ctx.direction = packet.text_direction.toLowerCase();
             genericSuccess(txn_uuid);
-        } else if (id == "SetFillStyle") {
-            ctx.fillStyle = packet.fill_style;
+        } else if (id == "SetLetterSpacing") {
+            setProperty(canvi, txn_uuid, 'letterSpacing', packet.letter_spacing);
+        } else if (id == "SetWordSpacing") {
+            setProperty(canvi, txn_uuid, 'wordSpacing', packet.word_spacing);
+        } else if (id == "SetFontKerning") {
+            ctx.fontKerning = packet.font_kerning;
             genericSuccess(txn_uuid);
-        } else if (id == "SetStrokeStyle") {
-            ctx.strokeStyle = packet.stroke_style;
+        } else if (id == "SetFontStretch") {
+            ctx.fontStretch = packet.font_stretch;
+            genericSuccess(txn_uuid);
+        } else if (id == "SetFontVariantCaps") {
+            ctx.fontVariantCaps = packet.font_variant_caps;
+            genericSuccess(txn_uuid);
+        } else if (id == "SetTextRendering") {
+            ctx.textRendering = packet.text_rendering;
             genericSuccess(txn_uuid);
+        } else if (id == "SetFillStyle") {
+            setProperty(canvi, txn_uuid, 'fillStyle', packet.fill_style);
+        } else if (id == "SetStrokeStyle") {
+            setProperty(canvi, txn_uuid, 'strokeStyle', packet.stroke_style);
         } else if (id == "SetShadowBlur") {
             ctx.shadowBlur = packet.shadow_blur;
             genericSuccess(txn_uuid);
         } else if (id == "SetShadowColor") {
-            ctx.shadowColor = packet.shadow_color;
-            genericSuccess(txn_uuid);
+            setProperty(canvi, txn_uuid, 'shadowColor', packet.shadow_color);
         } else if (id == "SetShadowOffsetX") {
             ctx.shadowOffsetX = packet.shadow_offset_x;
             genericSuccess(txn_uuid);
@@ -152,8 +333,7 @@ const receiveMessage = message => {
             ctx.globalAlpha = packet.global_alpha;
             genericSuccess(txn_uuid);
         } else if (id == "SetGlobalCompositeOperation") {
-            ctx.globalCompositeOperation = packet.global_composite_operation;
-            genericSuccess(txn_uuid);
+            setProperty(canvi, txn_uuid, 'globalCompositeOperation', packet.global_composite_operation);
         } else if (id == "SetImageSmoothingEnabled") {
             ctx.imageSmoothingEnabled = packet.image_smoothing_enabled;
             genericSuccess(txn_uuid);
@@ -161,14 +341,27 @@ const receiveMessage = message => {
             ctx.imageSmoothingQuality = packet.image_smoothing_quality ? packet.image_smoothing_quality.toLowerCase() : null;
             genericSuccess(txn_uuid);
         } else if (id == "SetFilter") {
-            ctx.filter = packet.filter ? packet.filter : null;
-            genericSuccess(txn_uuid);
+            setProperty(canvi, txn_uuid, 'filter', packet.filter ? packet.filter : 'none');
         } else if (id == "SetWidth") {
             canvas.width = packet.width;
             genericSuccess(txn_uuid);
         } else if (id == "SetHeight") {
             canvas.height = packet.height;
             genericSuccess(txn_uuid);
+        } else if (id == "Resize") {
+            let previous = null;
+            if (packet.preserve_content) {
+                previous = document.createElement('canvas');
+                previous.width = canvas.width;
+                previous.height = canvas.height;
+                previous.getContext('2d').drawImage(canvas, 0, 0);
+            }
+            canvas.width = packet.width;
+            canvas.height = packet.height;
+            if (previous != null) {
+                ctx.drawImage(previous, 0, 0);
+            }
+            genericSuccess(txn_uuid);
         } else if (id == "ClearRect") {
             ctx.clearRect(packet.x, packet.y, packet.width, packet.height);
             genericSuccess(txn_uuid);
@@ -184,21 +377,50 @@ const receiveMessage = message => {
         } else if (id == "StrokeText") {
             ctx.strokeText(packet.text, packet.x, packet.y, ...(packet.max_width == null ? [] : [packet.max_width]));
             genericSuccess(txn_uuid);
+        } else if (id == "FillTextLines") {
+            ctx.save();
+            if (packet.clip != null) {
+                ctx.beginPath();
+                ctx.rect(...packet.clip);
+                ctx.clip();
+            }
+            ctx.font = packet.font;
+            if (packet.letter_spacing != null) {
+                ctx.letterSpacing = packet.letter_spacing;
+            }
+            if (packet.word_spacing != null) {
+                ctx.wordSpacing = packet.word_spacing;
+            }
+            ctx.textAlign = 'left';
+            ctx.textBaseline = 'alphabetic';
+            for (const [text, x, y] of packet.lines) {
+                ctx.fillText(text, x, y);
+            }
+            ctx.restore();
+            genericSuccess(txn_uuid);
         } else if (id == "CreateLinearGradient") {
             const gradient = ctx.createLinearGradient(packet.x0, packet.y0, packet.x1, packet.y1);
             for ([offset, color] of packet.stops) {
                 gradient.addColorStop(offset, color);
             }
+            canvi.gradients[packet.gradient_uuid] = gradient;
             genericSuccess(txn_uuid);
         } else if (id == "CreateRadialGradient") {
             const gradient = ctx.createRadialGradient(packet.x0, packet.y0, packet.r0, packet.x1, packet.y1, packet.r1);
             for ([offset, color] of packet.stops) {
                 gradient.addColorStop(offset, color);
             }
+            canvi.gradients[packet.gradient_uuid] = gradient;
+            genericSuccess(txn_uuid);
+        } else if (id == "CreateConicGradient") {
+            const gradient = ctx.createConicGradient(packet.start_angle, packet.x, packet.y);
+            for ([offset, color] of packet.stops) {
+                gradient.addColorStop(offset, color);
+            }
+            canvi.gradients[packet.gradient_uuid] = gradient;
             genericSuccess(txn_uuid);
         } else if (id == "CreatePattern") {
-            const img = document.createElement('img');
-            img.src = packet.url;
+            const img = await loadImage(packet.url);
             let repitition = null;
             if (packet.repitition == 'Repeat') {
                 repitition = 'repeat';
@@ -258,6 +480,11 @@ const receiveMessage = message => {
             const target = getTarget(packet.path_uuid, canvi);
             target.ellipse(packet.x, packet.y, packet.width, packet.height);
             genericSuccess(txn_uuid);
+        } else if (id == "RoundRect") {
+            const target = getTarget(packet.path_uuid, canvi);
+            const { top_left, top_right, bottom_right, bottom_left } = packet.radii;
+            target.roundRect(packet.x, packet.y, packet.width, packet.height, [top_left, top_right, bottom_right, bottom_left]);
+            genericSuccess(txn_uuid);
         } else if (id == "Fill") {
             const target = getTargetImplied(packet.path_uuid, canvi);
             if (target) {
@@ -300,9 +527,6 @@ const receiveMessage = message => {
                 is_in = ctx.isPointInPath(packet.x, packet.y);
             }
             send(txn_uuid, 'IsPointIn', { is_in });
-        } else if (id == "GetTransform") {
-            const [a, b, c, d, e, f] = ctx.getTransform();
-            send(txn_uuid, 'Transform', { matrix: [a, b, c, d, e, f] });
         } else if (id == "Rotate") {
             ctx.rotate(packet.angle);
             genericSuccess(txn_uuid);
@@ -313,14 +537,15 @@ const receiveMessage = message => {
             ctx.translate(packet.x, packet.y);
             genericSuccess(txn_uuid);
         } else if (id == "Transform") {
-            ctx.transform.apply(ctx, packet.matrix);
+            const { a, b, c, d, e, f } = packet.matrix;
+            ctx.transform(a, b, c, d, e, f);
             genericSuccess(txn_uuid);
         } else if (id == "SetTransform") {
-            ctx.setTransform.apply(ctx, packet.matrix);
+            const { a, b, c, d, e, f } = packet.matrix;
+            ctx.setTransform(a, b, c, d, e, f);
             genericSuccess(txn_uuid);
         } else if (id == "DrawImage") {
-            const img = document.createElement('img');
-            img.src = packet.url;
+            const img = await loadImage(packet.url);
             const { source, d_dims } = packet;
             const args = [img];
             if (source != null) {
@@ -334,16 +559,52 @@ const receiveMessage = message => {
             }
             ctx.drawImage(...args);
             genericSuccess(txn_uuid);
+        } else if (id == "DrawCanvas") {
+            const source = canvii[packet.source_context];
+            if (source == null) {
+                throw new Error(`unknown source canvas: ${packet.source_context}`);
+            }
+            const source_rect = packet.source != null ? packet.source : [0, 0, source.canvas.width, source.canvas.height];
+            ctx.drawImage(source.canvas, ...source_rect, ...packet.dest);
+            genericSuccess(txn_uuid);
+        } else if (id == "DrawImageData") {
+            const image_data = toImageData(packet.image_data);
+            const scratch = document.createElement('canvas');
+            scratch.width = image_data.width;
+            scratch.height = image_data.height;
+            scratch.getContext('2d').putImageData(image_data, 0, 0);
+            ctx.drawImage(scratch, ...packet.dest);
+            genericSuccess(txn_uuid);
+        } else if (id == "RegisterFont") {
+            const { Url, Bytes } = packet.source;
+            const source = Url != null ? `url(${JSON.stringify(Url)})` : new Uint8Array(Bytes).buffer;
+            const face = new FontFace(packet.family, source, packet.descriptors);
+            await face.load();
+            document.fonts.add(face);
+            await document.fonts.ready;
+            genericSuccess(txn_uuid);
+        } else if (id == "PreloadImages") {
+            await Promise.all(packet.urls.map(loadImage));
+            genericSuccess(txn_uuid);
+        } else if (id == "EvictImages") {
+            if (packet.urls == null) {
+                images.clear();
+            } else {
+                for (const url of packet.urls) {
+                    images.delete(url);
+                }
+            }
+            genericSuccess(txn_uuid);
         } else if (id == "GetImageData") {
             const data = ctx.getImageData(packet.sx, packet.sy, packet.sw, packet.sh);
             const image_data = {
-                data: new Uint32Array(data.data.buffer),
+                data: Array.from(data.data),
                 width: data.width,
                 height: data.height,
             };
             send(txn_uuid, 'ImageData', { image_data: image_data });
         } else if (id == "SetImageData") {
-            const image_data = new ImageData(new Uint8ClampedArray(new Uint32Array(packet.image_data.data).buffer), packet.image_data.width, packet.image_data.height);
+            const image_data = toImageData(packet.image_data);
             const args = [image_data, packet.dx, packet.dy];
             if (packet.dirty_pos != null) {
                 args.push(...packet.dirty_pos);
@@ -357,14 +618,59 @@ const receiveMessage = message => {
             }
             ctx.putImageData(...args);
             genericSuccess(txn_uuid);
+        } else if (id == "Batch") {
+            // nested batches run under the same txn uuid
+            const outer = captured.get(txn_uuid);
+            try {
+                for (const op of packet.ops) {
+                    captured.set(txn_uuid, []);
+                    await handlePacket({ data: op, txn_uuid, context });
+                    const error = captured.get(txn_uuid).find(response => response.JsError != null);
+                    if (error != null) {
+                        throw new Error(error.JsError.message);
+                    }
+                }
+            } finally {
+                if (outer != null) {
+                    captured.set(txn_uuid, outer);
+                } else {
+                    captured.delete(txn_uuid);
+                }
+            }
+            genericSuccess(txn_uuid);
         } else if (id == "Save") {
             ctx.save();
             genericSuccess(txn_uuid);
         } else if (id == "Restore") {
             ctx.restore();
             genericSuccess(txn_uuid);
+        } else if (id == "Reset") {
+            if (ctx.reset != null) {
+                ctx.reset();
+            } else {
+                // resizing resets the context in browsers without reset()
+                canvas.width = canvas.width;
+            }
+            genericSuccess(txn_uuid);
+        } else if (id == "GetContextAttributes") {
+            send(txn_uuid, 'ContextAttributes', { attributes: ctx.getContextAttributes() });
+        } else if (id == "IsContextLost") {
+            send(txn_uuid, 'ContextLost', { is_lost: ctx.isContextLost != null && ctx.isContextLost() });
         } else if (id == "ToDataUrl") {
-            send(txn_uuid, 'DataUrl', { data_url: canvas.toDataURL(data.mime_type, packet.quality == null ? void 0 : packet.quality) });
+            const data_url = canvas.toDataURL(packet.format, packet.quality == null ? void 0 : packet.quality);
+            if (!data_url.startsWith(`data:${packet.format}`)) {
+                throw new Error(`browser cannot encode ${packet.format}`);
+            }
+            send(txn_uuid, 'DataUrl', { data_url });
+        } else if (id == "Export") {
+            const blob = await new Promise(resolve => canvas.toBlob(resolve, packet.format, packet.quality == null ? void 0 : packet.quality));
+            if (blob == null) {
+                throw new Error('failed to encode canvas');
+            }
+            if (blob.type != packet.format) {
+                throw new Error(`browser cannot encode ${packet.format}, fell back to ${blob.type}`);
+            }
+            sendBinary(txn_uuid, await blob.arrayBuffer());
         } else {
             throw new Error(`invalid packet id: ${id}`);
         }
@@ -374,6 +680,31 @@ const receiveMessage = message => {
     }
 }
 
+// context -> its last message, so that each context's messages are handled strictly in order even when a handler
+// has to wait on the browser, i.e. for an image to load. other contexts carry on meanwhile
+const queues = new Map();
+
+const receiveMessage = message => {
+    console.log(message.data);
+    const packet = JSON.parse(message.data);
+    const [id, data] = Object.entries(packet.data)[0];
+    // drawing one canvas onto another waits for both
+    const contexts = id == "DrawCanvas" ? [packet.context, data.source_context] : [packet.context];
+    const handled = Promise.all(contexts.map(context => queues.get(context)))
+        .then(() => handlePacket(packet))
+        .catch(e => console.error(e));
+    for (const context of contexts) {
+        queues.set(context, handled);
+    }
+    handled.then(() => {
+        for (const context of contexts) {
+            if (queues.get(context) === handled) {
+                queues.delete(context);
+            }
+        }
+    });
+}
+
 function reconnect() {
     if (websocket != null) {
         websocket.close();
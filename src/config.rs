@@ -0,0 +1,83 @@
+use crate::canvas::{MAX_CANVAS_AREA, MAX_CANVAS_DIMENSION};
+
+// where the servers listen, and what else the web server serves
+#[derive(Clone, Debug)]
+pub struct ServerConfig {
+    pub(crate) web_addr: String,
+    pub(crate) websocket_addr: String,
+    pub(crate) metrics_path: Option<String>,
+    pub(crate) browser_limits: BrowserLimits,
+    pub(crate) canvas_size_limits: CanvasSizeLimits,
+}
+
+// how much each browser connection is given before new canvases wait for another browser, or for room on it
+#[derive(Clone, Debug, Default)]
+pub(crate) struct BrowserLimits {
+    pub max_contexts: Option<usize>,
+    pub max_pixels: Option<u64>,
+    pub max_in_flight: Option<usize>,
+}
+
+// the largest canvas that is created or resized to, rather than leaving the browser to fail it
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct CanvasSizeLimits {
+    pub max_dimension: u32,
+    pub max_area: u64,
+}
+
+impl Default for CanvasSizeLimits {
+    fn default() -> CanvasSizeLimits {
+        CanvasSizeLimits {
+            max_dimension: MAX_CANVAS_DIMENSION,
+            max_area: MAX_CANVAS_AREA,
+        }
+    }
+}
+
+impl ServerConfig {
+    pub fn new(web_addr: &str, websocket_addr: &str) -> ServerConfig {
+        ServerConfig {
+            web_addr: web_addr.to_string(),
+            websocket_addr: websocket_addr.to_string(),
+            metrics_path: None,
+            browser_limits: BrowserLimits::default(),
+            canvas_size_limits: CanvasSizeLimits::default(),
+        }
+    }
+
+    // serves metrics in prometheus' text format from the web server at `path`, i.e. "/metrics"
+    pub fn metrics_path(mut self, path: &str) -> ServerConfig {
+        self.metrics_path = Some(path.to_string());
+        self
+    }
+
+    // the most canvases a single browser is given at once
+    pub fn max_contexts_per_browser(mut self, max: usize) -> ServerConfig {
+        self.browser_limits.max_contexts = Some(max);
+        self
+    }
+
+    // the most canvas pixels (width * height, summed over its canvases) a single browser is given at once. a
+    // canvas larger than this on its own is rejected
+    pub fn max_pixels_per_browser(mut self, max: u64) -> ServerConfig {
+        self.browser_limits.max_pixels = Some(max);
+        self
+    }
+
+    // the most ops a single browser can have unanswered, further ops wait to be sent until it catches up
+    pub fn max_in_flight_per_browser(mut self, max: usize) -> ServerConfig {
+        self.browser_limits.max_in_flight = Some(max);
+        self
+    }
+
+    // the largest width or height, and width * height, of any canvas. these default to chrome's limits, which
+    // firefox shares, but safari refuses canvases over 16_777_216 pixels on iOS, so lower them to the smallest
+    // limits of the browsers that will connect
+    pub fn max_canvas_size(mut self, max_dimension: u32, max_area: u64) -> ServerConfig {
+        self.canvas_size_limits = CanvasSizeLimits {
+            max_dimension,
+            max_area,
+        };
+        self
+    }
+}
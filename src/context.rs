@@ -0,0 +1,840 @@
+use crate::canvas::*;
+use crate::matrix::DomMatrix;
+use crate::result::*;
+use crate::style::Filter;
+use crate::text_layout::*;
+use async_trait::async_trait;
+use uuid::Uuid;
+
+// the drawing API of a 2d canvas independent of where it's drawn, so rendering code can be written against
+// `impl Canvas2DContext`/`dyn Canvas2DContext` and run on a browser canvas, a recorder (Canvas2D::new_recorder) or
+// any other backend. browser specific controls (strict mode, the image cache, context attributes) stay on Canvas2D
+#[async_trait]
+#[allow(clippy::too_many_arguments)]
+pub trait Canvas2DContext: Send {
+    fn get_width(&self) -> u32;
+    async fn set_width(&mut self, width: u32) -> Result<()>;
+    fn get_height(&self) -> u32;
+    async fn set_height(&mut self, height: u32) -> Result<()>;
+    async fn resize(&mut self, width: u32, height: u32, preserve_content: bool) -> Result<()>;
+
+    fn current_state(&self) -> DrawState;
+    async fn apply_state(&mut self, state: &DrawState) -> Result<()>;
+    async fn save(&mut self) -> Result<()>;
+    async fn restore(&mut self) -> Result<()>;
+    async fn reset(&mut self) -> Result<()>;
+
+    fn get_line_width(&self) -> f64;
+    async fn set_line_width(&mut self, line_width: f64) -> Result<()>;
+    fn get_line_cap(&self) -> LineCap;
+    async fn set_line_cap(&mut self, line_cap: LineCap) -> Result<()>;
+    fn get_line_join(&self) -> LineJoin;
+    async fn set_line_join(&mut self, line_join: LineJoin) -> Result<()>;
+    fn get_miter_limit(&self) -> f64;
+    async fn set_miter_limit(&mut self, miter_limit: f64) -> Result<()>;
+    async fn get_line_dash(&mut self) -> Result<Vec<f64>>;
+    async fn set_line_dash(&mut self, line_dash: Vec<f64>) -> Result<Vec<f64>>;
+    fn get_line_dash_offset(&self) -> f64;
+    async fn set_line_dash_offset(&mut self, line_dash_offset: f64) -> Result<()>;
+
+    fn get_font(&self) -> &str;
+    async fn set_font(&mut self, font: &str) -> Result<()>;
+    fn get_text_align(&self) -> TextAlign;
+    async fn set_text_align(&mut self, text_align: TextAlign) -> Result<()>;
+    fn get_text_baseline(&self) -> TextBaseline;
+    async fn set_text_baseline(&mut self, text_baseline: TextBaseline) -> Result<()>;
+    fn get_text_direction(&self) -> TextDirection;
+    async fn set_text_direction(&mut self, text_direction: TextDirection) -> Result<()>;
+    fn get_letter_spacing(&self) -> &str;
+    async fn set_letter_spacing(&mut self, letter_spacing: &str) -> Result<()>;
+    fn get_word_spacing(&self) -> &str;
+    async fn set_word_spacing(&mut self, word_spacing: &str) -> Result<()>;
+    fn get_font_kerning(&self) -> FontKerning;
+    async fn set_font_kerning(&mut self, font_kerning: FontKerning) -> Result<()>;
+    fn get_font_stretch(&self) -> FontStretch;
+    async fn set_font_stretch(&mut self, font_stretch: FontStretch) -> Result<()>;
+    fn get_font_variant_caps(&self) -> FontVariantCaps;
+    async fn set_font_variant_caps(&mut self, font_variant_caps: FontVariantCaps) -> Result<()>;
+    fn get_text_rendering(&self) -> TextRendering;
+    async fn set_text_rendering(&mut self, text_rendering: TextRendering) -> Result<()>;
+
+    fn get_fill_style(&self) -> &str;
+    async fn set_fill_style(&mut self, fill_style: &str) -> Result<()>;
+    fn get_stroke_style(&self) -> &str;
+    async fn set_stroke_style(&mut self, stroke_style: &str) -> Result<()>;
+    fn get_shadow_blur(&self) -> f64;
+    async fn set_shadow_blur(&mut self, shadow_blur: f64) -> Result<()>;
+    fn get_shadow_color(&self) -> &str;
+    async fn set_shadow_color(&mut self, shadow_color: &str) -> Result<()>;
+    fn get_shadow_offset_x(&self) -> f64;
+    async fn set_shadow_offset_x(&mut self, shadow_offset_x: f64) -> Result<()>;
+    fn get_shadow_offset_y(&self) -> f64;
+    async fn set_shadow_offset_y(&mut self, shadow_offset_y: f64) -> Result<()>;
+    fn get_global_alpha(&self) -> f64;
+    async fn set_global_alpha(&mut self, global_alpha: f64) -> Result<()>;
+    fn get_global_composite_operation(&self) -> &str;
+    async fn set_global_composite_operation(
+        &mut self,
+        global_composite_operation: &str,
+    ) -> Result<()>;
+    fn get_image_smoothing_enabled(&self) -> bool;
+    async fn set_image_smoothing_enabled(&mut self, image_smoothing_enabled: bool) -> Result<()>;
+    fn get_image_smoothing_quality(&self) -> Option<ImageSmoothingQuality>;
+    async fn set_image_smoothing_quality(
+        &mut self,
+        image_smoothing_quality: Option<ImageSmoothingQuality>,
+    ) -> Result<()>;
+    fn get_filter(&self) -> Option<&str>;
+    async fn set_filter(&mut self, filter: Filter) -> Result<()>;
+
+    fn get_transform(&self) -> DomMatrix;
+    fn user_to_device(&self, x: f64, y: f64) -> (f64, f64);
+    fn device_to_user(&self, x: f64, y: f64) -> Option<(f64, f64)>;
+    async fn rotate(&mut self, angle: f64) -> Result<()>;
+    async fn scale(&mut self, x: f64, y: f64) -> Result<()>;
+    async fn translate(&mut self, x: f64, y: f64) -> Result<()>;
+    async fn transform(&mut self, matrix: DomMatrix) -> Result<()>;
+    async fn set_transform(&mut self, matrix: DomMatrix) -> Result<()>;
+
+    async fn clear_rect(&mut self, x: f64, y: f64, width: f64, height: f64) -> Result<()>;
+    async fn fill_rect(&mut self, x: f64, y: f64, width: f64, height: f64) -> Result<()>;
+    async fn stroke_rect(&mut self, x: f64, y: f64, width: f64, height: f64) -> Result<()>;
+    async fn fill_text(&mut self, text: &str, x: f64, y: f64, max_width: Option<f64>)
+        -> Result<()>;
+    async fn stroke_text(
+        &mut self,
+        text: &str,
+        x: f64,
+        y: f64,
+        max_width: Option<f64>,
+    ) -> Result<()>;
+    async fn measure_text(&mut self, text: String) -> Result<TextMetrics>;
+    async fn measure_texts(&mut self, texts: &[&str]) -> Result<Vec<TextMetrics>>;
+    async fn layout_text(&mut self, text: &str, layout: &TextLayout) -> Result<Vec<LineBox>>;
+    async fn fill_text_layout(&mut self, text: &str, layout: &TextLayout) -> Result<Vec<LineBox>>;
+
+    async fn create_linear_gradient(
+        &mut self,
+        x0: f64,
+        y0: f64,
+        x1: f64,
+        y1: f64,
+        stops: Vec<(f64, String)>,
+    ) -> Result<CanvasGradient>;
+    async fn create_radial_gradient(
+        &mut self,
+        x0: f64,
+        y0: f64,
+        r0: f64,
+        x1: f64,
+        y1: f64,
+        r1: f64,
+        stops: Vec<(f64, String)>,
+    ) -> Result<CanvasGradient>;
+    async fn create_conic_gradient(
+        &mut self,
+        start_angle: f64,
+        x: f64,
+        y: f64,
+        stops: Vec<(f64, String)>,
+    ) -> Result<CanvasGradient>;
+    async fn create_pattern(&mut self, url: &str, repitition: PatternRepitition) -> Result<()>;
+
+    async fn begin_path(&mut self, path_uuid: Option<Uuid>) -> Result<()>;
+    async fn close_path(&mut self, path_uuid: Option<Uuid>) -> Result<()>;
+    async fn move_to(&mut self, path_uuid: Option<Uuid>, x: f64, y: f64) -> Result<()>;
+    async fn line_to(&mut self, path_uuid: Option<Uuid>, x: f64, y: f64) -> Result<()>;
+    async fn bezier_curve_to(
+        &mut self,
+        path_uuid: Option<Uuid>,
+        cp1x: f64,
+        cp1y: f64,
+        cp2x: f64,
+        cp2y: f64,
+        x: f64,
+        y: f64,
+    ) -> Result<()>;
+    async fn quadratic_curve_to(
+        &mut self,
+        path_uuid: Option<Uuid>,
+        cpx: f64,
+        cpy: f64,
+        x: f64,
+        y: f64,
+    ) -> Result<()>;
+    async fn arc(
+        &mut self,
+        path_uuid: Option<Uuid>,
+        x: f64,
+        y: f64,
+        radius: f64,
+        start_angle: f64,
+        end_angle: f64,
+        anticlockwise: bool,
+    ) -> Result<()>;
+    async fn arc_to(
+        &mut self,
+        path_uuid: Option<Uuid>,
+        x1: f64,
+        y1: f64,
+        x2: f64,
+        y2: f64,
+        radius: f64,
+    ) -> Result<()>;
+    async fn ellipse(
+        &mut self,
+        path_uuid: Option<Uuid>,
+        x: f64,
+        y: f64,
+        radius_x: f64,
+        radius_y: f64,
+        rotation: f64,
+        start_angle: f64,
+        end_angle: f64,
+        anticlockwise: bool,
+    ) -> Result<()>;
+    async fn rect(
+        &mut self,
+        path_uuid: Option<Uuid>,
+        x: f64,
+        y: f64,
+        width: f64,
+        height: f64,
+    ) -> Result<()>;
+    async fn round_rect(
+        &mut self,
+        path_uuid: Option<Uuid>,
+        x: f64,
+        y: f64,
+        width: f64,
+        height: f64,
+        radii: CornerRadii,
+    ) -> Result<()>;
+    async fn fill(&mut self, path_uuid: Option<Uuid>, is_even_odd: bool) -> Result<()>;
+    async fn stroke(&mut self, path_uuid: Option<Uuid>) -> Result<()>;
+    async fn clip(&mut self, path_uuid: Option<Uuid>, is_even_odd: bool) -> Result<()>;
+    async fn is_point_in_path(
+        &mut self,
+        path_uuid: Option<Uuid>,
+        x: f64,
+        y: f64,
+        is_even_odd: bool,
+    ) -> Result<bool>;
+    async fn is_point_in_stroke(&mut self, path_uuid: Option<Uuid>, x: f64, y: f64)
+        -> Result<bool>;
+
+    async fn draw_image(
+        &mut self,
+        url: &str,
+        source: Option<(f64, f64, f64, f64)>,
+        dx: f64,
+        dy: f64,
+        d_dims: Option<(f64, f64)>,
+    ) -> Result<()>;
+    async fn get_image_data(&mut self, sx: f64, sy: f64, sw: f64, sh: f64) -> Result<ImageData>;
+    async fn set_image_data(
+        &mut self,
+        image_data: ImageData,
+        dx: f64,
+        dy: f64,
+        dirty_pos: Option<(f64, f64)>,
+        dirty_dims: Option<(f64, f64)>,
+    ) -> Result<()>;
+    async fn to_data_url(&mut self, format: ImageFormat, quality: Option<f64>) -> Result<String>;
+    async fn export(&mut self, format: ImageFormat, quality: Option<f64>) -> Result<Vec<u8>>;
+}
+
+#[async_trait]
+#[allow(clippy::too_many_arguments)]
+impl Canvas2DContext for Canvas2D {
+    fn get_width(&self) -> u32 {
+        Canvas2D::get_width(self)
+    }
+
+    async fn set_width(&mut self, width: u32) -> Result<()> {
+        Canvas2D::set_width(self, width).await
+    }
+
+    fn get_height(&self) -> u32 {
+        Canvas2D::get_height(self)
+    }
+
+    async fn set_height(&mut self, height: u32) -> Result<()> {
+        Canvas2D::set_height(self, height).await
+    }
+
+    async fn resize(&mut self, width: u32, height: u32, preserve_content: bool) -> Result<()> {
+        Canvas2D::resize(self, width, height, preserve_content).await
+    }
+
+    fn current_state(&self) -> DrawState {
+        Canvas2D::current_state(self)
+    }
+
+    async fn apply_state(&mut self, state: &DrawState) -> Result<()> {
+        Canvas2D::apply_state(self, state).await
+    }
+
+    async fn save(&mut self) -> Result<()> {
+        Canvas2D::save(self).await
+    }
+
+    async fn restore(&mut self) -> Result<()> {
+        Canvas2D::restore(self).await
+    }
+
+    async fn reset(&mut self) -> Result<()> {
+        Canvas2D::reset(self).await
+    }
+
+    fn get_line_width(&self) -> f64 {
+        Canvas2D::get_line_width(self)
+    }
+
+    async fn set_line_width(&mut self, line_width: f64) -> Result<()> {
+        Canvas2D::set_line_width(self, line_width).await
+    }
+
+    fn get_line_cap(&self) -> LineCap {
+        Canvas2D::get_line_cap(self)
+    }
+
+    async fn set_line_cap(&mut self, line_cap: LineCap) -> Result<()> {
+        Canvas2D::set_line_cap(self, line_cap).await
+    }
+
+    fn get_line_join(&self) -> LineJoin {
+        Canvas2D::get_line_join(self)
+    }
+
+    async fn set_line_join(&mut self, line_join: LineJoin) -> Result<()> {
+        Canvas2D::set_line_join(self, line_join).await
+    }
+
+    fn get_miter_limit(&self) -> f64 {
+        Canvas2D::get_miter_limit(self)
+    }
+
+    async fn set_miter_limit(&mut self, miter_limit: f64) -> Result<()> {
+        Canvas2D::set_miter_limit(self, miter_limit).await
+    }
+
+    async fn get_line_dash(&mut self) -> Result<Vec<f64>> {
+        Canvas2D::get_line_dash(self).await
+    }
+
+    async fn set_line_dash(&mut self, line_dash: Vec<f64>) -> Result<Vec<f64>> {
+        Canvas2D::set_line_dash(self, line_dash).await
+    }
+
+    fn get_line_dash_offset(&self) -> f64 {
+        Canvas2D::get_line_dash_offset(self)
+    }
+
+    async fn set_line_dash_offset(&mut self, line_dash_offset: f64) -> Result<()> {
+        Canvas2D::set_line_dash_offset(self, line_dash_offset).await
+    }
+
+    fn get_font(&self) -> &str {
+        Canvas2D::get_font(self)
+    }
+
+    async fn set_font(&mut self, font: &str) -> Result<()> {
+        Canvas2D::set_font(self, font).await
+    }
+
+    fn get_text_align(&self) -> TextAlign {
+        Canvas2D::get_text_align(self)
+    }
+
+    async fn set_text_align(&mut self, text_align: TextAlign) -> Result<()> {
+        Canvas2D::set_text_align(self, text_align).await
+    }
+
+    fn get_text_baseline(&self) -> TextBaseline {
+        Canvas2D::get_text_baseline(self)
+    }
+
+    async fn set_text_baseline(&mut self, text_baseline: TextBaseline) -> Result<()> {
+        Canvas2D::set_text_baseline(self, text_baseline).await
+    }
+
+    fn get_text_direction(&self) -> TextDirection {
+        Canvas2D::get_text_direction(self)
+    }
+
+    async fn set_text_direction(&mut self, text_direction: TextDirection) -> Result<()> {
+        Canvas2D::set_text_direction(self, text_direction).await
+    }
+
+    fn get_letter_spacing(&self) -> &str {
+        Canvas2D::get_letter_spacing(self)
+    }
+
+    async fn set_letter_spacing(&mut self, letter_spacing: &str) -> Result<()> {
+        Canvas2D::set_letter_spacing(self, letter_spacing).await
+    }
+
+    fn get_word_spacing(&self) -> &str {
+        Canvas2D::get_word_spacing(self)
+    }
+
+    async fn set_word_spacing(&mut self, word_spacing: &str) -> Result<()> {
+        Canvas2D::set_word_spacing(self, word_spacing).await
+    }
+
+    fn get_font_kerning(&self) -> FontKerning {
+        Canvas2D::get_font_kerning(self)
+    }
+
+    async fn set_font_kerning(&mut self, font_kerning: FontKerning) -> Result<()> {
+        Canvas2D::set_font_kerning(self, font_kerning).await
+    }
+
+    fn get_font_stretch(&self) -> FontStretch {
+        Canvas2D::get_font_stretch(self)
+    }
+
+    async fn set_font_stretch(&mut self, font_stretch: FontStretch) -> Result<()> {
+        Canvas2D::set_font_stretch(self, font_stretch).await
+    }
+
+    fn get_font_variant_caps(&self) -> FontVariantCaps {
+        Canvas2D::get_font_variant_caps(self)
+    }
+
+    async fn set_font_variant_caps(&mut self, font_variant_caps: FontVariantCaps) -> Result<()> {
+        Canvas2D::set_font_variant_caps(self, font_variant_caps).await
+    }
+
+    fn get_text_rendering(&self) -> TextRendering {
+        Canvas2D::get_text_rendering(self)
+    }
+
+    async fn set_text_rendering(&mut self, text_rendering: TextRendering) -> Result<()> {
+        Canvas2D::set_text_rendering(self, text_rendering).await
+    }
+
+    fn get_fill_style(&self) -> &str {
+        Canvas2D::get_fill_style(self)
+    }
+
+    async fn set_fill_style(&mut self, fill_style: &str) -> Result<()> {
+        Canvas2D::set_fill_style(self, fill_style).await
+    }
+
+    fn get_stroke_style(&self) -> &str {
+        Canvas2D::get_stroke_style(self)
+    }
+
+    async fn set_stroke_style(&mut self, stroke_style: &str) -> Result<()> {
+        Canvas2D::set_stroke_style(self, stroke_style).await
+    }
+
+    fn get_shadow_blur(&self) -> f64 {
+        Canvas2D::get_shadow_blur(self)
+    }
+
+    async fn set_shadow_blur(&mut self, shadow_blur: f64) -> Result<()> {
+        Canvas2D::set_shadow_blur(self, shadow_blur).await
+    }
+
+    fn get_shadow_color(&self) -> &str {
+        Canvas2D::get_shadow_color(self)
+    }
+
+    async fn set_shadow_color(&mut self, shadow_color: &str) -> Result<()> {
+        Canvas2D::set_shadow_color(self, shadow_color).await
+    }
+
+    fn get_shadow_offset_x(&self) -> f64 {
+        Canvas2D::get_shadow_offset_x(self)
+    }
+
+    async fn set_shadow_offset_x(&mut self, shadow_offset_x: f64) -> Result<()> {
+        Canvas2D::set_shadow_offset_x(self, shadow_offset_x).await
+    }
+
+    fn get_shadow_offset_y(&self) -> f64 {
+        Canvas2D::get_shadow_offset_y(self)
+    }
+
+    async fn set_shadow_offset_y(&mut self, shadow_offset_y: f64) -> Result<()> {
+        Canvas2D::set_shadow_offset_y(self, shadow_offset_y).await
+    }
+
+    fn get_global_alpha(&self) -> f64 {
+        Canvas2D::get_global_alpha(self)
+    }
+
+    async fn set_global_alpha(&mut self, global_alpha: f64) -> Result<()> {
+        Canvas2D::set_global_alpha(self, global_alpha).await
+    }
+
+    fn get_global_composite_operation(&self) -> &str {
+        Canvas2D::get_global_composite_operation(self)
+    }
+
+    async fn set_global_composite_operation(
+        &mut self,
+        global_composite_operation: &str,
+    ) -> Result<()> {
+        Canvas2D::set_global_composite_operation(self, global_composite_operation).await
+    }
+
+    fn get_image_smoothing_enabled(&self) -> bool {
+        Canvas2D::get_image_smoothing_enabled(self)
+    }
+
+    async fn set_image_smoothing_enabled(&mut self, image_smoothing_enabled: bool) -> Result<()> {
+        Canvas2D::set_image_smoothing_enabled(self, image_smoothing_enabled).await
+    }
+
+    fn get_image_smoothing_quality(&self) -> Option<ImageSmoothingQuality> {
+        Canvas2D::get_image_smoothing_quality(self)
+    }
+
+    async fn set_image_smoothing_quality(
+        &mut self,
+        image_smoothing_quality: Option<ImageSmoothingQuality>,
+    ) -> Result<()> {
+        Canvas2D::set_image_smoothing_quality(self, image_smoothing_quality).await
+    }
+
+    fn get_filter(&self) -> Option<&str> {
+        Canvas2D::get_filter(self)
+    }
+
+    async fn set_filter(&mut self, filter: Filter) -> Result<()> {
+        Canvas2D::set_filter(self, filter).await
+    }
+
+    fn get_transform(&self) -> DomMatrix {
+        Canvas2D::get_transform(self)
+    }
+
+    fn user_to_device(&self, x: f64, y: f64) -> (f64, f64) {
+        Canvas2D::user_to_device(self, x, y)
+    }
+
+    fn device_to_user(&self, x: f64, y: f64) -> Option<(f64, f64)> {
+        Canvas2D::device_to_user(self, x, y)
+    }
+
+    async fn rotate(&mut self, angle: f64) -> Result<()> {
+        Canvas2D::rotate(self, angle).await
+    }
+
+    async fn scale(&mut self, x: f64, y: f64) -> Result<()> {
+        Canvas2D::scale(self, x, y).await
+    }
+
+    async fn translate(&mut self, x: f64, y: f64) -> Result<()> {
+        Canvas2D::translate(self, x, y).await
+    }
+
+    async fn transform(&mut self, matrix: DomMatrix) -> Result<()> {
+        Canvas2D::transform(self, matrix).await
+    }
+
+    async fn set_transform(&mut self, matrix: DomMatrix) -> Result<()> {
+        Canvas2D::set_transform(self, matrix).await
+    }
+
+    async fn clear_rect(&mut self, x: f64, y: f64, width: f64, height: f64) -> Result<()> {
+        Canvas2D::clear_rect(self, x, y, width, height).await
+    }
+
+    async fn fill_rect(&mut self, x: f64, y: f64, width: f64, height: f64) -> Result<()> {
+        Canvas2D::fill_rect(self, x, y, width, height).await
+    }
+
+    async fn stroke_rect(&mut self, x: f64, y: f64, width: f64, height: f64) -> Result<()> {
+        Canvas2D::stroke_rect(self, x, y, width, height).await
+    }
+
+    async fn fill_text(
+        &mut self,
+        text: &str,
+        x: f64,
+        y: f64,
+        max_width: Option<f64>,
+    ) -> Result<()> {
+        Canvas2D::fill_text(self, text, x, y, max_width).await
+    }
+
+    async fn stroke_text(
+        &mut self,
+        text: &str,
+        x: f64,
+        y: f64,
+        max_width: Option<f64>,
+    ) -> Result<()> {
+        Canvas2D::stroke_text(self, text, x, y, max_width).await
+    }
+
+    async fn measure_text(&mut self, text: String) -> Result<TextMetrics> {
+        Canvas2D::measure_text(self, text).await
+    }
+
+    async fn measure_texts(&mut self, texts: &[&str]) -> Result<Vec<TextMetrics>> {
+        Canvas2D::measure_texts(self, texts).await
+    }
+
+    async fn layout_text(&mut self, text: &str, layout: &TextLayout) -> Result<Vec<LineBox>> {
+        Canvas2D::layout_text(self, text, layout).await
+    }
+
+    async fn fill_text_layout(&mut self, text: &str, layout: &TextLayout) -> Result<Vec<LineBox>> {
+        Canvas2D::fill_text_layout(self, text, layout).await
+    }
+
+    async fn create_linear_gradient(
+        &mut self,
+        x0: f64,
+        y0: f64,
+        x1: f64,
+        y1: f64,
+        stops: Vec<(f64, String)>,
+    ) -> Result<CanvasGradient> {
+        Canvas2D::create_linear_gradient(self, x0, y0, x1, y1, stops).await
+    }
+
+    async fn create_radial_gradient(
+        &mut self,
+        x0: f64,
+        y0: f64,
+        r0: f64,
+        x1: f64,
+        y1: f64,
+        r1: f64,
+        stops: Vec<(f64, String)>,
+    ) -> Result<CanvasGradient> {
+        Canvas2D::create_radial_gradient(self, x0, y0, r0, x1, y1, r1, stops).await
+    }
+
+    async fn create_conic_gradient(
+        &mut self,
+        start_angle: f64,
+        x: f64,
+        y: f64,
+        stops: Vec<(f64, String)>,
+    ) -> Result<CanvasGradient> {
+        Canvas2D::create_conic_gradient(self, start_angle, x, y, stops).await
+    }
+
+    async fn create_pattern(&mut self, url: &str, repitition: PatternRepitition) -> Result<()> {
+        Canvas2D::create_pattern(self, url, repitition).await
+    }
+
+    async fn begin_path(&mut self, path_uuid: Option<Uuid>) -> Result<()> {
+        Canvas2D::begin_path(self, path_uuid).await
+    }
+
+    async fn close_path(&mut self, path_uuid: Option<Uuid>) -> Result<()> {
+        Canvas2D::close_path(self, path_uuid).await
+    }
+
+    async fn move_to(&mut self, path_uuid: Option<Uuid>, x: f64, y: f64) -> Result<()> {
+        Canvas2D::move_to(self, path_uuid, x, y).await
+    }
+
+    async fn line_to(&mut self, path_uuid: Option<Uuid>, x: f64, y: f64) -> Result<()> {
+        Canvas2D::line_to(self, path_uuid, x, y).await
+    }
+
+    async fn bezier_curve_to(
+        &mut self,
+        path_uuid: Option<Uuid>,
+        cp1x: f64,
+        cp1y: f64,
+        cp2x: f64,
+        cp2y: f64,
+        x: f64,
+        y: f64,
+    ) -> Result<()> {
+        Canvas2D::bezier_curve_to(self, path_uuid, cp1x, cp1y, cp2x, cp2y, x, y).await
+    }
+
+    async fn quadratic_curve_to(
+        &mut self,
+        path_uuid: Option<Uuid>,
+        cpx: f64,
+        cpy: f64,
+        x: f64,
+        y: f64,
+    ) -> Result<()> {
+        Canvas2D::quadratic_curve_to(self, path_uuid, cpx, cpy, x, y).await
+    }
+
+    async fn arc(
+        &mut self,
+        path_uuid: Option<Uuid>,
+        x: f64,
+        y: f64,
+        radius: f64,
+        start_angle: f64,
+        end_angle: f64,
+        anticlockwise: bool,
+    ) -> Result<()> {
+        Canvas2D::arc(
+            self,
+            path_uuid,
+            x,
+            y,
+            radius,
+            start_angle,
+            end_angle,
+            anticlockwise,
+        )
+        .await
+    }
+
+    async fn arc_to(
+        &mut self,
+        path_uuid: Option<Uuid>,
+        x1: f64,
+        y1: f64,
+        x2: f64,
+        y2: f64,
+        radius: f64,
+    ) -> Result<()> {
+        Canvas2D::arc_to(self, path_uuid, x1, y1, x2, y2, radius).await
+    }
+
+    async fn ellipse(
+        &mut self,
+        path_uuid: Option<Uuid>,
+        x: f64,
+        y: f64,
+        radius_x: f64,
+        radius_y: f64,
+        rotation: f64,
+        start_angle: f64,
+        end_angle: f64,
+        anticlockwise: bool,
+    ) -> Result<()> {
+        Canvas2D::ellipse(
+            self,
+            path_uuid,
+            x,
+            y,
+            radius_x,
+            radius_y,
+            rotation,
+            start_angle,
+            end_angle,
+            anticlockwise,
+        )
+        .await
+    }
+
+    async fn rect(
+        &mut self,
+        path_uuid: Option<Uuid>,
+        x: f64,
+        y: f64,
+        width: f64,
+        height: f64,
+    ) -> Result<()> {
+        Canvas2D::rect(self, path_uuid, x, y, width, height).await
+    }
+
+    async fn round_rect(
+        &mut self,
+        path_uuid: Option<Uuid>,
+        x: f64,
+        y: f64,
+        width: f64,
+        height: f64,
+        radii: CornerRadii,
+    ) -> Result<()> {
+        Canvas2D::round_rect(self, path_uuid, x, y, width, height, radii).await
+    }
+
+    async fn fill(&mut self, path_uuid: Option<Uuid>, is_even_odd: bool) -> Result<()> {
+        Canvas2D::fill(self, path_uuid, is_even_odd).await
+    }
+
+    async fn stroke(&mut self, path_uuid: Option<Uuid>) -> Result<()> {
+        Canvas2D::stroke(self, path_uuid).await
+    }
+
+    async fn clip(&mut self, path_uuid: Option<Uuid>, is_even_odd: bool) -> Result<()> {
+        Canvas2D::clip(self, path_uuid, is_even_odd).await
+    }
+
+    async fn is_point_in_path(
+        &mut self,
+        path_uuid: Option<Uuid>,
+        x: f64,
+        y: f64,
+        is_even_odd: bool,
+    ) -> Result<bool> {
+        Canvas2D::is_point_in_path(self, path_uuid, x, y, is_even_odd).await
+    }
+
+    async fn is_point_in_stroke(
+        &mut self,
+        path_uuid: Option<Uuid>,
+        x: f64,
+        y: f64,
+    ) -> Result<bool> {
+        Canvas2D::is_point_in_stroke(self, path_uuid, x, y).await
+    }
+
+    async fn draw_image(
+        &mut self,
+        url: &str,
+        source: Option<(f64, f64, f64, f64)>,
+        dx: f64,
+        dy: f64,
+        d_dims: Option<(f64, f64)>,
+    ) -> Result<()> {
+        Canvas2D::draw_image(self, url, source, dx, dy, d_dims).await
+    }
+
+    async fn get_image_data(&mut self, sx: f64, sy: f64, sw: f64, sh: f64) -> Result<ImageData> {
+        Canvas2D::get_image_data(self, sx, sy, sw, sh).await
+    }
+
+    async fn set_image_data(
+        &mut self,
+        image_data: ImageData,
+        dx: f64,
+        dy: f64,
+        dirty_pos: Option<(f64, f64)>,
+        dirty_dims: Option<(f64, f64)>,
+    ) -> Result<()> {
+        Canvas2D::set_image_data(self, image_data, dx, dy, dirty_pos, dirty_dims).await
+    }
+
+    async fn to_data_url(&mut self, format: ImageFormat, quality: Option<f64>) -> Result<String> {
+        Canvas2D::to_data_url(self, format, quality).await
+    }
+
+    async fn export(&mut self, format: ImageFormat, quality: Option<f64>) -> Result<Vec<u8>> {
+        Canvas2D::export(self, format, quality).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    async fn draw_badge(context: &mut dyn Canvas2DContext) -> Result<()> {
+        context.save().await?;
+        context.set_fill_style("#336699").await?;
+        context.translate(5.0, 5.0).await?;
+        context.fill_rect(0.0, 0.0, 20.0, 10.0).await?;
+        context.restore().await
+    }
+
+    #[tokio::test]
+    async fn draws_through_the_trait() {
+        let mut recorder = Canvas2D::new_recorder(40, 20).unwrap();
+        draw_badge(&mut recorder).await.unwrap();
+        assert_eq!(Canvas2DContext::get_fill_style(&recorder), "#000");
+        assert_eq!(recorder.take_recording().unwrap().len(), 5);
+    }
+}
@@ -4,27 +4,60 @@ extern crate tokio;
 #[macro_use]
 mod result;
 
+pub mod blocking;
+mod browser;
 mod canvas;
+mod config;
+mod context;
+mod matrix;
+mod metrics;
+mod pool;
+mod recording;
+mod style;
+mod svg;
+mod text_cache;
+mod text_layout;
 mod web;
 mod websocket;
 pub use result::*;
 
 use async_std::sync;
+use browser::Browsers;
 pub use canvas::*;
-use tokio::sync::mpsc;
-use websocket::WSMessage;
+pub use config::*;
+pub use context::*;
+pub use matrix::*;
+use metrics::Metrics;
+pub use pool::*;
+pub use recording::*;
+use std::sync::Arc;
+pub use style::*;
+pub use svg::*;
+pub use text_layout::*;
+use websocket::ContextRequest;
 
 pub fn start_server(web_addr: &str, websocket_addr: &str) -> Result<CanvasGenerator> {
-    let (sender, receiver) = sync::channel::<mpsc::Receiver<WSMessage>>(1024);
+    start_server_with_config(ServerConfig::new(web_addr, websocket_addr))
+}
+
+pub fn start_server_with_config(config: ServerConfig) -> Result<CanvasGenerator> {
+    let (sender, receiver) = sync::channel::<ContextRequest>(1024);
+    let browsers = Arc::new(Browsers::default());
+    let metrics = Arc::new(Metrics::default());
     tokio::spawn(websocket::start_server(
-        websocket_addr.to_string(),
+        config.websocket_addr.clone(),
         receiver,
+        browsers.clone(),
+        metrics.clone(),
+        config.browser_limits,
     ));
     tokio::task::spawn_local(web::start_server(
-        web_addr.to_string(),
-        websocket_addr.to_string(),
+        config.web_addr,
+        config.websocket_addr,
+        config.metrics_path,
+        metrics.clone(),
     ));
-    Ok(CanvasGenerator { sender })
+    Ok(CanvasGenerator::new(sender, browsers, metrics).with_size_limits(config.canvas_size_limits))
 }
 
 #[cfg(test)]
@@ -47,7 +80,7 @@ mod tests {
         canvas.fill_rect(30.0, 30.0, 30.0, 30.0).await.unwrap();
         info!(
             "data url: {}",
-            canvas.to_data_url("image/png", None).await.unwrap()
+            canvas.to_data_url(ImageFormat::Png, None).await.unwrap()
         );
         drop(canvas);
         info!("drop canvas");
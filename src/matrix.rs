@@ -0,0 +1,218 @@
+use serde::{Deserialize, Serialize};
+
+// a 2D affine transform, laid out like the browser's DOMMatrix:
+// | a c e |
+// | b d f |
+// | 0 0 1 |
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub struct DomMatrix {
+    pub a: f64,
+    pub b: f64,
+    pub c: f64,
+    pub d: f64,
+    pub e: f64,
+    pub f: f64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DecomposedMatrix {
+    pub translate_x: f64,
+    pub translate_y: f64,
+    // radians
+    pub rotation: f64,
+    pub scale_x: f64,
+    pub scale_y: f64,
+    // horizontal skew, in radians
+    pub skew_x: f64,
+}
+
+impl Default for DomMatrix {
+    fn default() -> DomMatrix {
+        DomMatrix::identity()
+    }
+}
+
+impl From<[f64; 6]> for DomMatrix {
+    fn from([a, b, c, d, e, f]: [f64; 6]) -> DomMatrix {
+        DomMatrix { a, b, c, d, e, f }
+    }
+}
+
+impl From<DomMatrix> for [f64; 6] {
+    fn from(matrix: DomMatrix) -> [f64; 6] {
+        [matrix.a, matrix.b, matrix.c, matrix.d, matrix.e, matrix.f]
+    }
+}
+
+impl DomMatrix {
+    pub fn new(a: f64, b: f64, c: f64, d: f64, e: f64, f: f64) -> DomMatrix {
+        DomMatrix { a, b, c, d, e, f }
+    }
+
+    pub fn identity() -> DomMatrix {
+        DomMatrix::new(1.0, 0.0, 0.0, 1.0, 0.0, 0.0)
+    }
+
+    pub fn translation(x: f64, y: f64) -> DomMatrix {
+        DomMatrix::new(1.0, 0.0, 0.0, 1.0, x, y)
+    }
+
+    pub fn scaling(x: f64, y: f64) -> DomMatrix {
+        DomMatrix::new(x, 0.0, 0.0, y, 0.0, 0.0)
+    }
+
+    pub fn rotation(angle: f64) -> DomMatrix {
+        let (sin, cos) = angle.sin_cos();
+        DomMatrix::new(cos, sin, -sin, cos, 0.0, 0.0)
+    }
+
+    pub fn is_identity(&self) -> bool {
+        *self == DomMatrix::identity()
+    }
+
+    // self * other, the same as calling transform(other) on a context with this transform
+    pub fn multiply(&self, other: &DomMatrix) -> DomMatrix {
+        DomMatrix {
+            a: self.a * other.a + self.c * other.b,
+            b: self.b * other.a + self.d * other.b,
+            c: self.a * other.c + self.c * other.d,
+            d: self.b * other.c + self.d * other.d,
+            e: self.a * other.e + self.c * other.f + self.e,
+            f: self.b * other.e + self.d * other.f + self.f,
+        }
+    }
+
+    pub fn determinant(&self) -> f64 {
+        self.a * self.d - self.b * self.c
+    }
+
+    // None if the matrix isn't invertible, i.e. it has been scaled to zero
+    pub fn invert(&self) -> Option<DomMatrix> {
+        let determinant = self.determinant();
+        if determinant == 0.0 || !determinant.is_finite() {
+            return None;
+        }
+        Some(DomMatrix {
+            a: self.d / determinant,
+            b: -self.b / determinant,
+            c: -self.c / determinant,
+            d: self.a / determinant,
+            e: (self.c * self.f - self.d * self.e) / determinant,
+            f: (self.b * self.e - self.a * self.f) / determinant,
+        })
+    }
+
+    pub fn transform_point(&self, x: f64, y: f64) -> (f64, f64) {
+        (
+            self.a * x + self.c * y + self.e,
+            self.b * x + self.d * y + self.f,
+        )
+    }
+
+    // the axis aligned bounding box of the transformed rect, as (x, y, width, height)
+    pub fn transform_rect(&self, x: f64, y: f64, width: f64, height: f64) -> (f64, f64, f64, f64) {
+        let corners = [
+            self.transform_point(x, y),
+            self.transform_point(x + width, y),
+            self.transform_point(x, y + height),
+            self.transform_point(x + width, y + height),
+        ];
+        let min_x = corners.iter().map(|o| o.0).fold(f64::INFINITY, f64::min);
+        let min_y = corners.iter().map(|o| o.1).fold(f64::INFINITY, f64::min);
+        let max_x = corners
+            .iter()
+            .map(|o| o.0)
+            .fold(f64::NEG_INFINITY, f64::max);
+        let max_y = corners
+            .iter()
+            .map(|o| o.1)
+            .fold(f64::NEG_INFINITY, f64::max);
+        (min_x, min_y, max_x - min_x, max_y - min_y)
+    }
+
+    // splits the matrix into translate(tx, ty) * rotate(rotation) * skewX(skew_x) * scale(sx, sy)
+    pub fn decompose(&self) -> DecomposedMatrix {
+        let (mut a, mut b, mut c, mut d) = (self.a, self.b, self.c, self.d);
+        let mut scale_x = (a * a + b * b).sqrt();
+        if scale_x != 0.0 {
+            a /= scale_x;
+            b /= scale_x;
+        }
+        let mut shear = a * c + b * d;
+        c -= a * shear;
+        d -= b * shear;
+        let scale_y = (c * c + d * d).sqrt();
+        if scale_y != 0.0 {
+            shear /= scale_y;
+        }
+        if self.determinant() < 0.0 {
+            a = -a;
+            b = -b;
+            shear = -shear;
+            scale_x = -scale_x;
+        }
+        DecomposedMatrix {
+            translate_x: self.e,
+            translate_y: self.f,
+            rotation: b.atan2(a),
+            scale_x,
+            scale_y,
+            skew_x: shear.atan(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(left: &DomMatrix, right: &DomMatrix) {
+        let left: [f64; 6] = (*left).into();
+        let right: [f64; 6] = (*right).into();
+        for (l, r) in left.iter().zip(right.iter()) {
+            assert!((l - r).abs() < 1e-9, "{:?} != {:?}", left, right);
+        }
+    }
+
+    #[test]
+    fn multiply_and_invert() {
+        let matrix = DomMatrix::translation(10.0, 20.0)
+            .multiply(&DomMatrix::rotation(0.5))
+            .multiply(&DomMatrix::scaling(2.0, 3.0));
+        let (x, y) = matrix.transform_point(1.0, 1.0);
+        let inverse = matrix.invert().unwrap();
+        let (ux, uy) = inverse.transform_point(x, y);
+        assert!((ux - 1.0).abs() < 1e-9 && (uy - 1.0).abs() < 1e-9);
+        assert_close(&matrix.multiply(&inverse), &DomMatrix::identity());
+        assert!(DomMatrix::scaling(0.0, 1.0).invert().is_none());
+        assert_eq!(
+            DomMatrix::scaling(2.0, 2.0).transform_rect(1.0, 1.0, 2.0, 3.0),
+            (2.0, 2.0, 4.0, 6.0)
+        );
+    }
+
+    #[test]
+    fn decompose() {
+        let matrix = DomMatrix::translation(5.0, -3.0)
+            .multiply(&DomMatrix::rotation(1.2))
+            .multiply(&DomMatrix::new(1.0, 0.0, 0.4, 1.0, 0.0, 0.0))
+            .multiply(&DomMatrix::scaling(2.0, 0.5));
+        let decomposed = matrix.decompose();
+        assert!((decomposed.rotation - 1.2).abs() < 1e-9);
+        assert!((decomposed.scale_x - 2.0).abs() < 1e-9);
+        assert!((decomposed.scale_y - 0.5).abs() < 1e-9);
+        assert!((decomposed.skew_x - 0.4f64.atan()).abs() < 1e-9);
+        let recomposed = DomMatrix::translation(decomposed.translate_x, decomposed.translate_y)
+            .multiply(&DomMatrix::rotation(decomposed.rotation))
+            .multiply(&DomMatrix::new(
+                1.0,
+                0.0,
+                decomposed.skew_x.tan(),
+                1.0,
+                0.0,
+                0.0,
+            ))
+            .multiply(&DomMatrix::scaling(decomposed.scale_x, decomposed.scale_y));
+        assert_close(&recomposed, &matrix);
+    }
+}
@@ -0,0 +1,322 @@
+use std::collections::HashMap;
+use std::fmt::Write;
+use std::sync::atomic::{AtomicI64, AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::Duration;
+use uuid::Uuid;
+
+// upper bounds in seconds, shared by every histogram
+const BUCKETS: [f64; 14] = [
+    0.0005, 0.001, 0.0025, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0,
+];
+
+#[derive(Default, Clone)]
+struct Histogram {
+    // not cumulative, the count of observations falling in each bucket (and past the last one)
+    counts: [u64; BUCKETS.len() + 1],
+    sum: f64,
+}
+
+impl Histogram {
+    fn observe(&mut self, duration: Duration) {
+        let seconds = duration.as_secs_f64();
+        let bucket = BUCKETS
+            .iter()
+            .position(|bound| seconds <= *bound)
+            .unwrap_or(BUCKETS.len());
+        self.counts[bucket] += 1;
+        self.sum += seconds;
+    }
+
+    fn write(&self, out: &mut String, name: &str, labels: &str) {
+        let mut cumulative = 0;
+        for (bound, count) in BUCKETS.iter().zip(self.counts.iter()) {
+            cumulative += count;
+            writeln!(
+                out,
+                "{}_bucket{{{}le=\"{}\"}} {}",
+                name, labels, bound, cumulative
+            )
+            .unwrap();
+        }
+        cumulative += self.counts[BUCKETS.len()];
+        writeln!(
+            out,
+            "{}_bucket{{{}le=\"+Inf\"}} {}",
+            name, labels, cumulative
+        )
+        .unwrap();
+        let labels = match labels.trim_end_matches(',') {
+            "" => String::new(),
+            labels => format!("{{{}}}", labels),
+        };
+        writeln!(out, "{}_sum{} {}", name, labels, self.sum).unwrap();
+        writeln!(out, "{}_count{} {}", name, labels, cumulative).unwrap();
+    }
+}
+
+// collected by the websocket server and canvas generator, rendered in prometheus' text format by the web server
+#[derive(Default)]
+pub(crate) struct Metrics {
+    browsers: AtomicI64,
+    canvases: Mutex<HashMap<Uuid, i64>>,
+    ops: Mutex<HashMap<&'static str, u64>>,
+    js_errors: Mutex<HashMap<&'static str, u64>>,
+    latency: Mutex<HashMap<&'static str, Histogram>>,
+    bytes_sent: AtomicU64,
+    bytes_received: AtomicU64,
+    pending: Mutex<HashMap<Uuid, i64>>,
+    creation: Mutex<Histogram>,
+}
+
+impl Metrics {
+    pub(crate) fn browser_connected(&self, browser: Uuid) {
+        self.browsers.fetch_add(1, Ordering::Relaxed);
+        self.canvases.lock().unwrap().insert(browser, 0);
+        self.pending.lock().unwrap().insert(browser, 0);
+    }
+
+    pub(crate) fn browser_disconnected(&self, browser: &Uuid) {
+        self.browsers.fetch_sub(1, Ordering::Relaxed);
+        self.canvases.lock().unwrap().remove(browser);
+        self.pending.lock().unwrap().remove(browser);
+    }
+
+    pub(crate) fn canvas_created(&self, browser: &Uuid) {
+        if let Some(count) = self.canvases.lock().unwrap().get_mut(browser) {
+            *count += 1;
+        }
+    }
+
+    pub(crate) fn canvas_destroyed(&self, browser: &Uuid) {
+        if let Some(count) = self.canvases.lock().unwrap().get_mut(browser) {
+            *count -= 1;
+        }
+    }
+
+    pub(crate) fn op_sent(
+        &self,
+        browser: &Uuid,
+        op: &'static str,
+        bytes: usize,
+        awaits_response: bool,
+    ) {
+        *self.ops.lock().unwrap().entry(op).or_insert(0) += 1;
+        self.bytes_sent.fetch_add(bytes as u64, Ordering::Relaxed);
+        if awaits_response {
+            if let Some(pending) = self.pending.lock().unwrap().get_mut(browser) {
+                *pending += 1;
+            }
+        }
+    }
+
+    pub(crate) fn response_received(&self, bytes: usize) {
+        self.bytes_received
+            .fetch_add(bytes as u64, Ordering::Relaxed);
+    }
+
+    pub(crate) fn op_answered(
+        &self,
+        browser: &Uuid,
+        op: &'static str,
+        latency: Duration,
+        is_js_error: bool,
+    ) {
+        if let Some(pending) = self.pending.lock().unwrap().get_mut(browser) {
+            *pending -= 1;
+        }
+        self.latency
+            .lock()
+            .unwrap()
+            .entry(op)
+            .or_default()
+            .observe(latency);
+        if is_js_error {
+            *self.js_errors.lock().unwrap().entry(op).or_insert(0) += 1;
+        }
+    }
+
+    // how long make_2d_canvas waited for a browser to pick up and initialize the canvas
+    pub(crate) fn canvas_creation(&self, wait: Duration) {
+        self.creation.lock().unwrap().observe(wait);
+    }
+
+    pub(crate) fn render(&self) -> String {
+        let mut out = String::new();
+        header(
+            &mut out,
+            "remote_canvas_browsers",
+            "gauge",
+            "Connected browsers.",
+        );
+        writeln!(
+            out,
+            "remote_canvas_browsers {}",
+            self.browsers.load(Ordering::Relaxed)
+        )
+        .unwrap();
+
+        header(
+            &mut out,
+            "remote_canvas_canvases",
+            "gauge",
+            "Live canvases per browser.",
+        );
+        for (browser, count) in sorted(&self.canvases.lock().unwrap()) {
+            writeln!(
+                out,
+                "remote_canvas_canvases{{browser=\"{}\"}} {}",
+                browser, count
+            )
+            .unwrap();
+        }
+
+        header(
+            &mut out,
+            "remote_canvas_ops_total",
+            "counter",
+            "Ops sent to browsers.",
+        );
+        for (op, count) in sorted(&self.ops.lock().unwrap()) {
+            writeln!(out, "remote_canvas_ops_total{{op=\"{}\"}} {}", op, count).unwrap();
+        }
+
+        header(
+            &mut out,
+            "remote_canvas_js_errors_total",
+            "counter",
+            "Ops that failed with a javascript error.",
+        );
+        for (op, count) in sorted(&self.js_errors.lock().unwrap()) {
+            writeln!(
+                out,
+                "remote_canvas_js_errors_total{{op=\"{}\"}} {}",
+                op, count
+            )
+            .unwrap();
+        }
+
+        header(
+            &mut out,
+            "remote_canvas_op_duration_seconds",
+            "histogram",
+            "Time from sending an op to the browser answering it.",
+        );
+        for (op, histogram) in sorted(&self.latency.lock().unwrap()) {
+            histogram.write(
+                &mut out,
+                "remote_canvas_op_duration_seconds",
+                &format!("op=\"{}\",", op),
+            );
+        }
+
+        header(
+            &mut out,
+            "remote_canvas_sent_bytes_total",
+            "counter",
+            "Bytes sent to browsers.",
+        );
+        writeln!(
+            out,
+            "remote_canvas_sent_bytes_total {}",
+            self.bytes_sent.load(Ordering::Relaxed)
+        )
+        .unwrap();
+        header(
+            &mut out,
+            "remote_canvas_received_bytes_total",
+            "counter",
+            "Bytes received from browsers.",
+        );
+        writeln!(
+            out,
+            "remote_canvas_received_bytes_total {}",
+            self.bytes_received.load(Ordering::Relaxed)
+        )
+        .unwrap();
+
+        header(
+            &mut out,
+            "remote_canvas_pending_transactions",
+            "gauge",
+            "Ops sent to each browser that haven't been answered yet.",
+        );
+        for (browser, count) in sorted(&self.pending.lock().unwrap()) {
+            writeln!(
+                out,
+                "remote_canvas_pending_transactions{{browser=\"{}\"}} {}",
+                browser, count
+            )
+            .unwrap();
+        }
+
+        header(
+            &mut out,
+            "remote_canvas_canvas_creation_seconds",
+            "histogram",
+            "Time spent waiting for a new canvas to be ready.",
+        );
+        self.creation
+            .lock()
+            .unwrap()
+            .write(&mut out, "remote_canvas_canvas_creation_seconds", "");
+        out
+    }
+}
+
+fn header(out: &mut String, name: &str, kind: &str, help: &str) {
+    writeln!(out, "# HELP {} {}", name, help).unwrap();
+    writeln!(out, "# TYPE {} {}", name, kind).unwrap();
+}
+
+// so the output is stable between scrapes
+fn sorted<K: ToString, V: Clone>(map: &HashMap<K, V>) -> Vec<(String, V)> {
+    let mut entries: Vec<(String, V)> = map
+        .iter()
+        .map(|(key, value)| (key.to_string(), value.clone()))
+        .collect();
+    entries.sort_by(|a, b| a.0.cmp(&b.0));
+    entries
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn renders_prometheus_text() {
+        let metrics = Metrics::default();
+        let browser = Uuid::nil();
+        metrics.browser_connected(browser);
+        metrics.canvas_created(&browser);
+        metrics.op_sent(&browser, "FillRect", 100, true);
+        metrics.response_received(20);
+        metrics.op_answered(&browser, "FillRect", Duration::from_millis(3), true);
+        metrics.canvas_creation(Duration::from_secs(20));
+
+        let text = metrics.render();
+        assert!(text.contains("remote_canvas_browsers 1\n"));
+        assert!(text.contains(&format!(
+            "remote_canvas_canvases{{browser=\"{}\"}} 1\n",
+            browser
+        )));
+        assert!(text.contains("remote_canvas_ops_total{op=\"FillRect\"} 1\n"));
+        assert!(text.contains("remote_canvas_js_errors_total{op=\"FillRect\"} 1\n"));
+        assert!(text.contains(
+            "remote_canvas_op_duration_seconds_bucket{op=\"FillRect\",le=\"0.0025\"} 0\n"
+        ));
+        assert!(text.contains(
+            "remote_canvas_op_duration_seconds_bucket{op=\"FillRect\",le=\"0.005\"} 1\n"
+        ));
+        assert!(text.contains("remote_canvas_op_duration_seconds_count{op=\"FillRect\"} 1\n"));
+        assert!(text.contains("remote_canvas_sent_bytes_total 100\n"));
+        assert!(text.contains("remote_canvas_received_bytes_total 20\n"));
+        assert!(text.contains(&format!(
+            "remote_canvas_pending_transactions{{browser=\"{}\"}} 0\n",
+            browser
+        )));
+        assert!(text.contains("remote_canvas_canvas_creation_seconds_bucket{le=\"10\"} 0\n"));
+        assert!(text.contains("remote_canvas_canvas_creation_seconds_bucket{le=\"+Inf\"} 1\n"));
+        assert!(text.contains("remote_canvas_canvas_creation_seconds_count 1\n"));
+    }
+}
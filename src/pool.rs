@@ -0,0 +1,206 @@
+use crate::browser::Browsers;
+use crate::canvas::*;
+use crate::result::*;
+use crate::websocket::WSMessageData;
+use std::collections::HashMap;
+use std::ops::{Deref, DerefMut};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, Weak};
+use uuid::Uuid;
+
+// idle canvases of the pool's size, by the browser they live on
+struct Idle {
+    width: u32,
+    height: u32,
+    max_per_browser: AtomicUsize,
+    canvases: Mutex<HashMap<Uuid, Vec<Canvas2D>>>,
+}
+
+impl Idle {
+    fn new(width: u32, height: u32, max_per_browser: usize) -> Idle {
+        Idle {
+            width,
+            height,
+            max_per_browser: AtomicUsize::new(max_per_browser),
+            canvases: Mutex::new(HashMap::new()),
+        }
+    }
+
+    // canvases that were resized, or that don't fit, are dropped and so destroyed
+    fn put(&self, canvas: Canvas2D) {
+        if canvas.get_width() != self.width || canvas.get_height() != self.height {
+            return;
+        }
+        let mut canvases = self.canvases.lock().unwrap();
+        let idle = canvases.entry(canvas.get_browser_id()).or_default();
+        if idle.len() < self.max_per_browser.load(Ordering::SeqCst) {
+            idle.push(canvas);
+        }
+    }
+
+    // forgets the canvases of browsers that have disconnected along the way
+    fn take(&self, browsers: &Browsers) -> Option<Canvas2D> {
+        let mut canvases = self.canvases.lock().unwrap();
+        canvases.retain(|browser, idle| !idle.is_empty() && browsers.get(browser).is_some());
+        let idle = canvases.values_mut().next()?;
+        idle.pop()
+    }
+
+    fn set_max_per_browser(&self, max: usize) {
+        self.max_per_browser.store(max, Ordering::SeqCst);
+        for idle in self.canvases.lock().unwrap().values_mut() {
+            idle.truncate(max);
+        }
+    }
+
+    fn len(&self) -> usize {
+        self.canvases.lock().unwrap().values().map(Vec::len).sum()
+    }
+}
+
+// hands out same-size canvases, reusing the ones that were dropped rather than creating a new one each time
+#[derive(Clone)]
+pub struct CanvasPool {
+    generator: CanvasGenerator,
+    data: ContextData2D,
+    idle: Arc<Idle>,
+}
+
+impl CanvasPool {
+    pub fn new(
+        generator: CanvasGenerator,
+        data: ContextData2D,
+        width: u32,
+        height: u32,
+    ) -> CanvasPool {
+        CanvasPool {
+            generator,
+            data,
+            idle: Arc::new(Idle::new(width, height, 16)),
+        }
+    }
+
+    // the most idle canvases kept for each browser, 16 by default. any more are destroyed when dropped
+    pub fn with_max_idle_per_browser(self, max: usize) -> CanvasPool {
+        self.idle.set_max_per_browser(max);
+        self
+    }
+
+    pub fn get_idle(&self) -> usize {
+        self.idle.len()
+    }
+
+    // a cleared canvas with its drawing state and transform reset and strict mode off, created only if none are idle
+    pub async fn acquire(&self) -> Result<PooledCanvas> {
+        while let Some(mut canvas) = self.idle.take(&self.generator.browsers) {
+            // strict mode isn't part of the drawing state, so it's left in a single round trip with the reset
+            let reset = if canvas.is_strict_mode() {
+                canvas
+                    .send_batch(&[
+                        WSMessageData::Reset {},
+                        WSMessageData::SetStrictMode { strict: false },
+                    ])
+                    .await
+            } else {
+                canvas.reset().await
+            };
+            if reset.is_ok() {
+                return Ok(self.pooled(canvas));
+            }
+        }
+        let canvas = self
+            .generator
+            .make_2d_canvas(self.data.clone(), self.idle.width, self.idle.height)
+            .await?;
+        Ok(self.pooled(canvas))
+    }
+
+    fn pooled(&self, canvas: Canvas2D) -> PooledCanvas {
+        PooledCanvas {
+            canvas: Some(canvas),
+            pool: Arc::downgrade(&self.idle),
+        }
+    }
+}
+
+// a canvas from a CanvasPool, which goes back to the pool when dropped instead of being destroyed
+pub struct PooledCanvas {
+    canvas: Option<Canvas2D>,
+    pool: Weak<Idle>,
+}
+
+impl PooledCanvas {
+    // takes the canvas out of the pool for good, it's destroyed as usual once dropped
+    pub fn detach(mut self) -> Canvas2D {
+        self.canvas.take().unwrap()
+    }
+}
+
+impl Deref for PooledCanvas {
+    type Target = Canvas2D;
+
+    fn deref(&self) -> &Canvas2D {
+        self.canvas.as_ref().unwrap()
+    }
+}
+
+impl DerefMut for PooledCanvas {
+    fn deref_mut(&mut self) -> &mut Canvas2D {
+        self.canvas.as_mut().unwrap()
+    }
+}
+
+impl Drop for PooledCanvas {
+    fn drop(&mut self) {
+        if let (Some(canvas), Some(pool)) = (self.canvas.take(), self.pool.upgrade()) {
+            pool.put(canvas);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::websocket::tests::fake_browser;
+    use std::time::Duration;
+
+    #[test]
+    fn keeps_idle_canvases_up_to_the_limit() {
+        let idle = Idle::new(100, 50, 2);
+        for _ in 0..3 {
+            idle.put(Canvas2D::new_recorder(100, 50).unwrap());
+        }
+        idle.put(Canvas2D::new_recorder(50, 100).unwrap());
+        assert_eq!(idle.len(), 2);
+
+        // recorders aren't on a connected browser, so none of them are handed out again
+        assert!(idle.take(&Browsers::default()).is_none());
+        assert_eq!(idle.len(), 0);
+    }
+
+    #[actix_rt::test]
+    async fn reuses_dropped_canvases() {
+        let generator = crate::start_server("127.0.0.1:7094", "127.0.0.1:7095").unwrap();
+        let ops = fake_browser("127.0.0.1:7095");
+        generator
+            .wait_for_browsers(1, Duration::from_secs(5))
+            .await
+            .unwrap();
+        let pool = CanvasPool::new(generator, Default::default(), 10, 10);
+        let mut canvas = pool.acquire().await.unwrap();
+        canvas.set_strict_mode(true).await.unwrap();
+        // outstanding canvases still go back to the pool after changing its cap
+        let pool = pool.with_max_idle_per_browser(1);
+        drop(canvas);
+        assert_eq!(pool.get_idle(), 1);
+
+        let canvas = pool.acquire().await.unwrap();
+        assert_eq!(pool.get_idle(), 0);
+        assert!(!canvas.is_strict_mode());
+        drop(canvas);
+        drop(pool);
+        let ops: Vec<String> = ops.try_iter().collect();
+        assert_eq!(ops.iter().filter(|op| *op == "Init2DCanvas").count(), 1);
+        assert!(ops.contains(&"Batch".to_string()));
+    }
+}
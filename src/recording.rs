@@ -0,0 +1,147 @@
+use crate::canvas::*;
+use crate::result::*;
+use crate::websocket::*;
+use serde::{Deserialize, Serialize};
+
+// bumped whenever an op changes in a way older recordings can't be read with
+const RECORDING_VERSION: u32 = 1;
+
+// ops captured from a recorder canvas (Canvas2D::new_recorder) that can be replayed onto any canvas
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Recording {
+    version: u32,
+    width: u32,
+    height: u32,
+    ops: Vec<WSMessageData>,
+}
+
+impl Recording {
+    pub(crate) fn new(width: u32, height: u32, ops: Vec<WSMessageData>) -> Recording {
+        Recording {
+            version: RECORDING_VERSION,
+            width,
+            height,
+            ops,
+        }
+    }
+
+    // the size of the recorder it was captured from
+    pub fn get_width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn get_height(&self) -> u32 {
+        self.height
+    }
+
+    pub(crate) fn ops(&self) -> &[WSMessageData] {
+        &self.ops
+    }
+
+    pub fn len(&self) -> usize {
+        self.ops.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ops.is_empty()
+    }
+
+    // appends the ops of another recording after this one's
+    pub fn append(&mut self, other: &Recording) {
+        self.ops.extend(other.ops.iter().cloned());
+    }
+
+    pub fn to_json(&self) -> Result<String> {
+        Ok(serde_json::to_string(self)?)
+    }
+
+    pub fn from_json(json: &str) -> Result<Recording> {
+        Recording::check_version(serde_json::from_str(json)?)
+    }
+
+    // CBOR encoded, which is considerably smaller for recordings holding image data
+    pub fn to_bytes(&self) -> Result<Vec<u8>> {
+        Ok(serde_cbor::to_vec(self)?)
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Recording> {
+        Recording::check_version(serde_cbor::from_slice(bytes)?)
+    }
+
+    fn check_version(recording: Recording) -> Result<Recording> {
+        if recording.version != RECORDING_VERSION {
+            return Err(canvas_error!(
+                "unsupported recording version {}, expected {}",
+                recording.version,
+                RECORDING_VERSION
+            ));
+        }
+        Ok(recording)
+    }
+
+    // draws the recording onto `canvas` in a single message. if an op fails, the ones before it have
+    // still been drawn but the canvas' mirrored state isn't updated
+    pub async fn replay(&self, canvas: &mut Canvas2D) -> Result<()> {
+        if self.ops.is_empty() {
+            return Ok(());
+        }
+        // a recording that was loaded rather than recorded may hold anything
+        if let Some(op) = find_unrecordable(&self.ops) {
+            return Err(canvas_error!("{} can't be replayed", op.name()));
+        }
+        canvas.send_batch(&self.ops).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn records_and_serializes() {
+        let mut recorder = Canvas2D::new_recorder(100, 50).unwrap();
+        recorder.set_fill_style("#ff0000").await.unwrap();
+        recorder.translate(10.0, 0.0).await.unwrap();
+        recorder.fill_rect(0.0, 0.0, 10.0, 10.0).await.unwrap();
+        assert!(recorder.measure_text("text".to_string()).await.is_err());
+        assert_eq!(recorder.get_fill_style(), "#ff0000");
+
+        let recording = recorder.take_recording().unwrap();
+        assert_eq!(recording.len(), 3);
+        assert!(recorder.take_recording().unwrap().is_empty());
+
+        let json = recording.to_json().unwrap();
+        assert_eq!(
+            Recording::from_json(&json).unwrap().to_json().unwrap(),
+            json
+        );
+        let bytes = recording.to_bytes().unwrap();
+        assert_eq!(
+            Recording::from_bytes(&bytes).unwrap().to_json().unwrap(),
+            json
+        );
+        assert!(Recording::from_json(&json.replace("\"version\":1", "\"version\":2")).is_err());
+        // ops as they were serialized when the version was last bumped still load
+        let gradient = r#"{"version":1,"width":10,"height":10,"ops":[{"CreateConicGradient":{"start_angle":0.0,"x":0.0,"y":0.0,"stops":[]}}]}"#;
+        assert_eq!(Recording::from_json(gradient).unwrap().len(), 1);
+
+        // replaying onto another recorder nests it as a batch, and mirrors its state changes
+        let mut other = Canvas2D::new_recorder(100, 50).unwrap();
+        recording.replay(&mut other).await.unwrap();
+        assert_eq!(other.get_fill_style(), "#ff0000");
+        assert_eq!(other.get_transform().e, 10.0);
+        assert_eq!(other.take_recording().unwrap().len(), 1);
+
+        let export = json.replace(
+            "{\"FillRect\"",
+            "{\"Batch\":{\"ops\":[{\"Export\":{\"format\":\"image/png\",\"quality\":null}}]}},{\"FillRect\"",
+        );
+        assert_ne!(export, json);
+        let error = Recording::from_json(&export)
+            .unwrap()
+            .replay(&mut other)
+            .await
+            .unwrap_err();
+        assert!(error.to_string().contains("Export can't be replayed"));
+    }
+}
@@ -1,4 +1,6 @@
 use std::fmt;
+use std::time::Duration;
+use uuid::Uuid;
 
 pub type Error = Box<dyn std::error::Error + Send + Sync + 'static>;
 pub type Result<T> = std::result::Result<T, Error>;
@@ -22,6 +24,60 @@ impl fmt::Display for CanvasError {
 
 impl std::error::Error for CanvasError {}
 
+// no browser took and initialized a canvas, or enough browsers didn't connect, within the timeout
+#[derive(Debug)]
+pub struct NoBrowserAvailable {
+    pub waited: Duration,
+}
+
+impl fmt::Display for NoBrowserAvailable {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "no browser available after waiting {:?}", self.waited)
+    }
+}
+
+impl std::error::Error for NoBrowserAvailable {}
+
+// a canvas creation was refused because `limit` creations were already waiting
+#[derive(Debug)]
+pub struct CreationQueueFull {
+    pub limit: usize,
+}
+
+impl fmt::Display for CreationQueueFull {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} canvas creations are already pending", self.limit)
+    }
+}
+
+impl std::error::Error for CreationQueueFull {}
+
+// a canvas creation that no browser could ever take, however long it waited
+#[derive(Debug)]
+pub enum CanvasRejected {
+    // larger than a browser's max_pixels_per_browser on its own
+    TooLarge { width: u32, height: u32 },
+    // the browser it was to be made near has disconnected
+    BrowserGone { browser: Uuid },
+}
+
+impl fmt::Display for CanvasRejected {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CanvasRejected::TooLarge { width, height } => write!(
+                f,
+                "canvas of {}x{} is larger than a browser's limit",
+                width, height
+            ),
+            CanvasRejected::BrowserGone { browser } => {
+                write!(f, "browser {} is no longer connected", browser)
+            }
+        }
+    }
+}
+
+impl std::error::Error for CanvasRejected {}
+
 #[macro_export]
 macro_rules! canvas_error {
     ($($arg:tt)*) => { Box::new(CanvasError::new(format!($($arg)*))) }
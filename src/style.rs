@@ -0,0 +1,732 @@
+use crate::result::*;
+use std::fmt;
+use std::str::FromStr;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Color {
+    pub r: u8,
+    pub g: u8,
+    pub b: u8,
+    // 0.0 to 1.0
+    pub a: f64,
+}
+
+const NAMED_COLORS: &[(&str, u32)] = &[
+    ("aliceblue", 0xf0f8ff),
+    ("antiquewhite", 0xfaebd7),
+    ("aqua", 0x00ffff),
+    ("aquamarine", 0x7fffd4),
+    ("azure", 0xf0ffff),
+    ("beige", 0xf5f5dc),
+    ("bisque", 0xffe4c4),
+    ("black", 0x000000),
+    ("blanchedalmond", 0xffebcd),
+    ("blue", 0x0000ff),
+    ("blueviolet", 0x8a2be2),
+    ("brown", 0xa52a2a),
+    ("burlywood", 0xdeb887),
+    ("cadetblue", 0x5f9ea0),
+    ("chartreuse", 0x7fff00),
+    ("chocolate", 0xd2691e),
+    ("coral", 0xff7f50),
+    ("cornflowerblue", 0x6495ed),
+    ("cornsilk", 0xfff8dc),
+    ("crimson", 0xdc143c),
+    ("cyan", 0x00ffff),
+    ("darkblue", 0x00008b),
+    ("darkcyan", 0x008b8b),
+    ("darkgoldenrod", 0xb8860b),
+    ("darkgray", 0xa9a9a9),
+    ("darkgreen", 0x006400),
+    ("darkgrey", 0xa9a9a9),
+    ("darkkhaki", 0xbdb76b),
+    ("darkmagenta", 0x8b008b),
+    ("darkolivegreen", 0x556b2f),
+    ("darkorange", 0xff8c00),
+    ("darkorchid", 0x9932cc),
+    ("darkred", 0x8b0000),
+    ("darksalmon", 0xe9967a),
+    ("darkseagreen", 0x8fbc8f),
+    ("darkslateblue", 0x483d8b),
+    ("darkslategray", 0x2f4f4f),
+    ("darkslategrey", 0x2f4f4f),
+    ("darkturquoise", 0x00ced1),
+    ("darkviolet", 0x9400d3),
+    ("deeppink", 0xff1493),
+    ("deepskyblue", 0x00bfff),
+    ("dimgray", 0x696969),
+    ("dimgrey", 0x696969),
+    ("dodgerblue", 0x1e90ff),
+    ("firebrick", 0xb22222),
+    ("floralwhite", 0xfffaf0),
+    ("forestgreen", 0x228b22),
+    ("fuchsia", 0xff00ff),
+    ("gainsboro", 0xdcdcdc),
+    ("ghostwhite", 0xf8f8ff),
+    ("gold", 0xffd700),
+    ("goldenrod", 0xdaa520),
+    ("gray", 0x808080),
+    ("green", 0x008000),
+    ("greenyellow", 0xadff2f),
+    ("grey", 0x808080),
+    ("honeydew", 0xf0fff0),
+    ("hotpink", 0xff69b4),
+    ("indianred", 0xcd5c5c),
+    ("indigo", 0x4b0082),
+    ("ivory", 0xfffff0),
+    ("khaki", 0xf0e68c),
+    ("lavender", 0xe6e6fa),
+    ("lavenderblush", 0xfff0f5),
+    ("lawngreen", 0x7cfc00),
+    ("lemonchiffon", 0xfffacd),
+    ("lightblue", 0xadd8e6),
+    ("lightcoral", 0xf08080),
+    ("lightcyan", 0xe0ffff),
+    ("lightgoldenrodyellow", 0xfafad2),
+    ("lightgray", 0xd3d3d3),
+    ("lightgreen", 0x90ee90),
+    ("lightgrey", 0xd3d3d3),
+    ("lightpink", 0xffb6c1),
+    ("lightsalmon", 0xffa07a),
+    ("lightseagreen", 0x20b2aa),
+    ("lightskyblue", 0x87cefa),
+    ("lightslategray", 0x778899),
+    ("lightslategrey", 0x778899),
+    ("lightsteelblue", 0xb0c4de),
+    ("lightyellow", 0xffffe0),
+    ("lime", 0x00ff00),
+    ("limegreen", 0x32cd32),
+    ("linen", 0xfaf0e6),
+    ("magenta", 0xff00ff),
+    ("maroon", 0x800000),
+    ("mediumaquamarine", 0x66cdaa),
+    ("mediumblue", 0x0000cd),
+    ("mediumorchid", 0xba55d3),
+    ("mediumpurple", 0x9370db),
+    ("mediumseagreen", 0x3cb371),
+    ("mediumslateblue", 0x7b68ee),
+    ("mediumspringgreen", 0x00fa9a),
+    ("mediumturquoise", 0x48d1cc),
+    ("mediumvioletred", 0xc71585),
+    ("midnightblue", 0x191970),
+    ("mintcream", 0xf5fffa),
+    ("mistyrose", 0xffe4e1),
+    ("moccasin", 0xffe4b5),
+    ("navajowhite", 0xffdead),
+    ("navy", 0x000080),
+    ("oldlace", 0xfdf5e6),
+    ("olive", 0x808000),
+    ("olivedrab", 0x6b8e23),
+    ("orange", 0xffa500),
+    ("orangered", 0xff4500),
+    ("orchid", 0xda70d6),
+    ("palegoldenrod", 0xeee8aa),
+    ("palegreen", 0x98fb98),
+    ("paleturquoise", 0xafeeee),
+    ("palevioletred", 0xdb7093),
+    ("papayawhip", 0xffefd5),
+    ("peachpuff", 0xffdab9),
+    ("peru", 0xcd853f),
+    ("pink", 0xffc0cb),
+    ("plum", 0xdda0dd),
+    ("powderblue", 0xb0e0e6),
+    ("purple", 0x800080),
+    ("rebeccapurple", 0x663399),
+    ("red", 0xff0000),
+    ("rosybrown", 0xbc8f8f),
+    ("royalblue", 0x4169e1),
+    ("saddlebrown", 0x8b4513),
+    ("salmon", 0xfa8072),
+    ("sandybrown", 0xf4a460),
+    ("seagreen", 0x2e8b57),
+    ("seashell", 0xfff5ee),
+    ("sienna", 0xa0522d),
+    ("silver", 0xc0c0c0),
+    ("skyblue", 0x87ceeb),
+    ("slateblue", 0x6a5acd),
+    ("slategray", 0x708090),
+    ("slategrey", 0x708090),
+    ("snow", 0xfffafa),
+    ("springgreen", 0x00ff7f),
+    ("steelblue", 0x4682b4),
+    ("tan", 0xd2b48c),
+    ("teal", 0x008080),
+    ("thistle", 0xd8bfd8),
+    ("tomato", 0xff6347),
+    ("turquoise", 0x40e0d0),
+    ("violet", 0xee82ee),
+    ("wheat", 0xf5deb3),
+    ("white", 0xffffff),
+    ("whitesmoke", 0xf5f5f5),
+    ("yellow", 0xffff00),
+    ("yellowgreen", 0x9acd32),
+];
+
+impl Color {
+    pub fn rgb(r: u8, g: u8, b: u8) -> Color {
+        Color { r, g, b, a: 1.0 }
+    }
+
+    pub fn rgba(r: u8, g: u8, b: u8, a: f64) -> Color {
+        Color {
+            r,
+            g,
+            b,
+            a: a.clamp(0.0, 1.0),
+        }
+    }
+
+    // hue in degrees, saturation and lightness from 0.0 to 1.0
+    pub fn hsl(hue: f64, saturation: f64, lightness: f64) -> Color {
+        Color::hsla(hue, saturation, lightness, 1.0)
+    }
+
+    pub fn hsla(hue: f64, saturation: f64, lightness: f64, a: f64) -> Color {
+        let hue = hue.rem_euclid(360.0) / 30.0;
+        let saturation = saturation.clamp(0.0, 1.0);
+        let lightness = lightness.clamp(0.0, 1.0);
+        let amount = saturation * lightness.min(1.0 - lightness);
+        let channel = |n: f64| {
+            let k = (n + hue) % 12.0;
+            let value = lightness - amount * (k - 3.0).min(9.0 - k).clamp(-1.0, 1.0);
+            (value * 255.0).round() as u8
+        };
+        Color::rgba(channel(0.0), channel(8.0), channel(4.0), a)
+    }
+
+    pub fn transparent() -> Color {
+        Color::rgba(0, 0, 0, 0.0)
+    }
+
+    pub fn with_alpha(self, a: f64) -> Color {
+        Color::rgba(self.r, self.g, self.b, a)
+    }
+
+    pub fn named(name: &str) -> Option<Color> {
+        let name = name.to_ascii_lowercase();
+        if name == "transparent" {
+            return Some(Color::transparent());
+        }
+        NAMED_COLORS
+            .iter()
+            .find(|(named, _)| *named == name)
+            .map(|(_, value)| Color::rgb((value >> 16) as u8, (value >> 8) as u8, *value as u8))
+    }
+
+    fn parse_hex(hex: &str) -> Option<Color> {
+        if !hex.chars().all(|o| o.is_ascii_hexdigit()) {
+            return None;
+        }
+        let digits: Vec<u8> = match hex.len() {
+            3 | 4 => hex
+                .chars()
+                .map(|o| u8::from_str_radix(&o.to_string(), 16).unwrap() * 17)
+                .collect(),
+            6 | 8 => (0..hex.len())
+                .step_by(2)
+                .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).unwrap())
+                .collect(),
+            _ => return None,
+        };
+        let a = digits.get(3).map(|o| *o as f64 / 255.0).unwrap_or(1.0);
+        Some(Color::rgba(digits[0], digits[1], digits[2], a))
+    }
+}
+
+// splits the arguments of a css color function, accepting both the comma and the space separated syntax
+fn function_arguments<'a>(value: &'a str, names: &[&str]) -> Option<Vec<&'a str>> {
+    let open = value.find('(')?;
+    if !names.contains(&value[..open].trim()) || !value.ends_with(')') {
+        return None;
+    }
+    Some(
+        value[open + 1..value.len() - 1]
+            .split(|o: char| o == ',' || o == '/' || o.is_whitespace())
+            .filter(|o| !o.is_empty())
+            .collect(),
+    )
+}
+
+// a number, or a percentage of `scale`
+fn parse_number(value: &str, scale: f64) -> Option<f64> {
+    match value.strip_suffix('%') {
+        Some(percentage) => Some(percentage.parse::<f64>().ok()? / 100.0 * scale),
+        None => value.parse::<f64>().ok(),
+    }
+}
+
+fn parse_alpha(arguments: &[&str], index: usize) -> Option<f64> {
+    match arguments.get(index) {
+        Some(alpha) => parse_number(alpha, 1.0),
+        None => Some(1.0),
+    }
+}
+
+fn parse_hue(value: &str) -> Option<f64> {
+    let units: &[(&str, f64)] = &[
+        ("deg", 1.0),
+        ("grad", 0.9),
+        ("rad", 180.0 / std::f64::consts::PI),
+        ("turn", 360.0),
+    ];
+    for (unit, degrees) in units {
+        // "grad" also ends in "rad", so only accept a suffix that leaves a number
+        if let Some(Ok(angle)) = value.strip_suffix(unit).map(|o| o.parse::<f64>()) {
+            return Some(angle * degrees);
+        }
+    }
+    value.parse().ok()
+}
+
+impl FromStr for Color {
+    type Err = Error;
+
+    fn from_str(value: &str) -> Result<Color> {
+        let value = value.trim().to_ascii_lowercase();
+        let color = if let Some(hex) = value.strip_prefix('#') {
+            Color::parse_hex(hex)
+        } else if let Some(arguments) = function_arguments(&value, &["rgb", "rgba"]) {
+            let channel = |i: usize| {
+                arguments
+                    .get(i)
+                    .and_then(|o| parse_number(o, 255.0))
+                    .map(|o| o.round().clamp(0.0, 255.0) as u8)
+            };
+            match (arguments.len(), channel(0), channel(1), channel(2)) {
+                (3..=4, Some(r), Some(g), Some(b)) => {
+                    parse_alpha(&arguments, 3).map(|a| Color::rgba(r, g, b, a))
+                }
+                _ => None,
+            }
+        } else if let Some(arguments) = function_arguments(&value, &["hsl", "hsla"]) {
+            let hue = arguments.first().and_then(|o| parse_hue(o));
+            let saturation = arguments.get(1).and_then(|o| parse_number(o, 1.0));
+            let lightness = arguments.get(2).and_then(|o| parse_number(o, 1.0));
+            match (arguments.len(), hue, saturation, lightness) {
+                (3..=4, Some(h), Some(s), Some(l)) => {
+                    parse_alpha(&arguments, 3).map(|a| Color::hsla(h, s, l, a))
+                }
+                _ => None,
+            }
+        } else {
+            Color::named(&value)
+        };
+        color.ok_or_else(|| canvas_error!("invalid color: {}", value) as Error)
+    }
+}
+
+// formatted the way browsers serialize colors, i.e. "#ff0000" or "rgba(255, 0, 0, 0.5)"
+impl fmt::Display for Color {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.a >= 1.0 {
+            write!(f, "#{:02x}{:02x}{:02x}", self.r, self.g, self.b)
+        } else {
+            write!(f, "rgba({}, {}, {}, {})", self.r, self.g, self.b, self.a)
+        }
+    }
+}
+
+impl From<Color> for String {
+    fn from(color: Color) -> String {
+        color.to_string()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FontStyle {
+    Normal,
+    Italic,
+    Oblique,
+}
+
+// builds a css font shorthand, i.e. "italic bold 16px/1.5 "Open Sans", sans-serif"
+#[derive(Debug, Clone, PartialEq)]
+pub struct Font {
+    pub style: FontStyle,
+    pub small_caps: bool,
+    // 100 to 900, 400 is normal and 700 is bold
+    pub weight: u16,
+    // in px
+    pub size: f64,
+    // multiple of the font size
+    pub line_height: Option<f64>,
+    // in order of preference
+    pub families: Vec<String>,
+}
+
+const GENERIC_FAMILIES: &[&str] = &[
+    "serif",
+    "sans-serif",
+    "monospace",
+    "cursive",
+    "fantasy",
+    "system-ui",
+];
+
+impl Font {
+    pub fn new(size: f64, family: &str) -> Font {
+        Font {
+            style: FontStyle::Normal,
+            small_caps: false,
+            weight: 400,
+            size,
+            line_height: None,
+            families: vec![family.to_string()],
+        }
+    }
+
+    pub fn style(mut self, style: FontStyle) -> Font {
+        self.style = style;
+        self
+    }
+
+    pub fn italic(self) -> Font {
+        self.style(FontStyle::Italic)
+    }
+
+    pub fn small_caps(mut self, small_caps: bool) -> Font {
+        self.small_caps = small_caps;
+        self
+    }
+
+    pub fn weight(mut self, weight: u16) -> Font {
+        self.weight = weight;
+        self
+    }
+
+    pub fn bold(self) -> Font {
+        self.weight(700)
+    }
+
+    pub fn line_height(mut self, line_height: f64) -> Font {
+        self.line_height = Some(line_height);
+        self
+    }
+
+    // a family to use when the ones before it aren't available
+    pub fn fallback(mut self, family: &str) -> Font {
+        self.families.push(family.to_string());
+        self
+    }
+}
+
+impl fmt::Display for Font {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.style {
+            FontStyle::Normal => {}
+            FontStyle::Italic => write!(f, "italic ")?,
+            FontStyle::Oblique => write!(f, "oblique ")?,
+        }
+        if self.small_caps {
+            write!(f, "small-caps ")?;
+        }
+        if self.weight != 400 {
+            write!(f, "{} ", self.weight)?;
+        }
+        write!(f, "{}px", self.size)?;
+        if let Some(line_height) = self.line_height {
+            write!(f, "/{}", line_height)?;
+        }
+        let families: Vec<String> = self
+            .families
+            .iter()
+            .map(|family| {
+                let plain = family
+                    .chars()
+                    .all(|o| o.is_ascii_alphanumeric() || o == '-');
+                if GENERIC_FAMILIES.contains(&&**family)
+                    || (plain && !family.starts_with(|o: char| o.is_ascii_digit()))
+                {
+                    family.clone()
+                } else {
+                    format!("\"{}\"", family.replace('\\', "\\\\").replace('"', "\\\""))
+                }
+            })
+            .collect();
+        write!(f, " {}", families.join(", "))
+    }
+}
+
+impl From<Font> for String {
+    fn from(font: Font) -> String {
+        font.to_string()
+    }
+}
+
+impl From<&Font> for String {
+    fn from(font: &Font) -> String {
+        font.to_string()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum CompositeOperation {
+    SourceOver,
+    SourceIn,
+    SourceOut,
+    SourceAtop,
+    DestinationOver,
+    DestinationIn,
+    DestinationOut,
+    DestinationAtop,
+    Lighter,
+    Copy,
+    Xor,
+    Multiply,
+    Screen,
+    Overlay,
+    Darken,
+    Lighten,
+    ColorDodge,
+    ColorBurn,
+    HardLight,
+    SoftLight,
+    Difference,
+    Exclusion,
+    Hue,
+    Saturation,
+    Color,
+    Luminosity,
+}
+
+const COMPOSITE_OPERATIONS: &[(CompositeOperation, &str)] = &[
+    (CompositeOperation::SourceOver, "source-over"),
+    (CompositeOperation::SourceIn, "source-in"),
+    (CompositeOperation::SourceOut, "source-out"),
+    (CompositeOperation::SourceAtop, "source-atop"),
+    (CompositeOperation::DestinationOver, "destination-over"),
+    (CompositeOperation::DestinationIn, "destination-in"),
+    (CompositeOperation::DestinationOut, "destination-out"),
+    (CompositeOperation::DestinationAtop, "destination-atop"),
+    (CompositeOperation::Lighter, "lighter"),
+    (CompositeOperation::Copy, "copy"),
+    (CompositeOperation::Xor, "xor"),
+    (CompositeOperation::Multiply, "multiply"),
+    (CompositeOperation::Screen, "screen"),
+    (CompositeOperation::Overlay, "overlay"),
+    (CompositeOperation::Darken, "darken"),
+    (CompositeOperation::Lighten, "lighten"),
+    (CompositeOperation::ColorDodge, "color-dodge"),
+    (CompositeOperation::ColorBurn, "color-burn"),
+    (CompositeOperation::HardLight, "hard-light"),
+    (CompositeOperation::SoftLight, "soft-light"),
+    (CompositeOperation::Difference, "difference"),
+    (CompositeOperation::Exclusion, "exclusion"),
+    (CompositeOperation::Hue, "hue"),
+    (CompositeOperation::Saturation, "saturation"),
+    (CompositeOperation::Color, "color"),
+    (CompositeOperation::Luminosity, "luminosity"),
+];
+
+impl CompositeOperation {
+    pub fn as_str(&self) -> &'static str {
+        COMPOSITE_OPERATIONS
+            .iter()
+            .find(|(operation, _)| operation == self)
+            .unwrap()
+            .1
+    }
+}
+
+impl FromStr for CompositeOperation {
+    type Err = Error;
+
+    fn from_str(value: &str) -> Result<CompositeOperation> {
+        COMPOSITE_OPERATIONS
+            .iter()
+            .find(|(_, name)| *name == value)
+            .map(|(operation, _)| *operation)
+            .ok_or_else(|| canvas_error!("invalid composite operation: {}", value) as Error)
+    }
+}
+
+impl fmt::Display for CompositeOperation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+impl From<CompositeOperation> for String {
+    fn from(operation: CompositeOperation) -> String {
+        operation.as_str().to_string()
+    }
+}
+
+// a list of css filter functions, applied in order. an empty filter is "none"
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Filter {
+    functions: Vec<String>,
+}
+
+impl Filter {
+    pub fn new() -> Filter {
+        Filter::default()
+    }
+
+    pub fn none() -> Filter {
+        Filter::default()
+    }
+
+    pub fn is_none(&self) -> bool {
+        self.functions.is_empty()
+    }
+
+    fn function(mut self, function: String) -> Filter {
+        self.functions.push(function);
+        self
+    }
+
+    pub fn url(self, url: &str) -> Filter {
+        self.function(format!("url({})", url))
+    }
+
+    // radius in px
+    pub fn blur(self, radius: f64) -> Filter {
+        self.function(format!("blur({}px)", radius))
+    }
+
+    // amounts are multiples, 1.0 leaves the image unchanged
+    pub fn brightness(self, amount: f64) -> Filter {
+        self.function(format!("brightness({})", amount))
+    }
+
+    pub fn contrast(self, amount: f64) -> Filter {
+        self.function(format!("contrast({})", amount))
+    }
+
+    pub fn drop_shadow(self, offset_x: f64, offset_y: f64, blur: f64, color: Color) -> Filter {
+        self.function(format!(
+            "drop-shadow({}px {}px {}px {})",
+            offset_x, offset_y, blur, color
+        ))
+    }
+
+    // amounts are from 0.0 to 1.0, 0.0 leaves the image unchanged
+    pub fn grayscale(self, amount: f64) -> Filter {
+        self.function(format!("grayscale({})", amount))
+    }
+
+    // in degrees
+    pub fn hue_rotate(self, angle: f64) -> Filter {
+        self.function(format!("hue-rotate({}deg)", angle))
+    }
+
+    pub fn invert(self, amount: f64) -> Filter {
+        self.function(format!("invert({})", amount))
+    }
+
+    pub fn opacity(self, amount: f64) -> Filter {
+        self.function(format!("opacity({})", amount))
+    }
+
+    pub fn saturate(self, amount: f64) -> Filter {
+        self.function(format!("saturate({})", amount))
+    }
+
+    pub fn sepia(self, amount: f64) -> Filter {
+        self.function(format!("sepia({})", amount))
+    }
+}
+
+impl fmt::Display for Filter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.is_none() {
+            write!(f, "none")
+        } else {
+            write!(f, "{}", self.functions.join(" "))
+        }
+    }
+}
+
+// raw css filter strings are passed through as is
+impl From<&str> for Filter {
+    fn from(filter: &str) -> Filter {
+        Filter::from(filter.to_string())
+    }
+}
+
+impl From<String> for Filter {
+    fn from(filter: String) -> Filter {
+        if filter.trim() == "none" {
+            Filter::none()
+        } else {
+            Filter::new().function(filter)
+        }
+    }
+}
+
+impl From<Option<&str>> for Filter {
+    fn from(filter: Option<&str>) -> Filter {
+        filter.map(Filter::from).unwrap_or_default()
+    }
+}
+
+impl From<Filter> for Option<String> {
+    fn from(filter: Filter) -> Option<String> {
+        if filter.is_none() {
+            None
+        } else {
+            Some(filter.to_string())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_colors() {
+        assert_eq!("#f00".parse::<Color>().unwrap(), Color::rgb(255, 0, 0));
+        assert_eq!("#00ff0080".parse::<Color>().unwrap().a, 128.0 / 255.0);
+        assert_eq!(
+            "rgba(10, 20, 30, 0.5)".parse::<Color>().unwrap(),
+            Color::rgba(10, 20, 30, 0.5)
+        );
+        assert_eq!(
+            "rgb(100% 0% 0% / 50%)".parse::<Color>().unwrap(),
+            Color::rgba(255, 0, 0, 0.5)
+        );
+        assert_eq!(
+            "hsl(120deg, 100%, 25%)".parse::<Color>().unwrap(),
+            Color::rgb(0, 128, 0)
+        );
+        assert_eq!(
+            "RebeccaPurple".parse::<Color>().unwrap(),
+            Color::rgb(102, 51, 153)
+        );
+        assert!("rgb(1, 2)".parse::<Color>().is_err());
+        assert!("#12345".parse::<Color>().is_err());
+        assert_eq!(Color::rgb(255, 0, 16).to_string(), "#ff0010");
+        assert_eq!(
+            Color::rgba(1, 2, 3, 0.25).to_string(),
+            "rgba(1, 2, 3, 0.25)"
+        );
+    }
+
+    #[test]
+    fn formats_fonts_and_filters() {
+        let font = Font::new(16.0, "Open Sans")
+            .italic()
+            .bold()
+            .line_height(1.5)
+            .fallback("sans-serif");
+        assert_eq!(
+            font.to_string(),
+            "italic 700 16px/1.5 \"Open Sans\", sans-serif"
+        );
+        assert_eq!(Font::new(10.0, "serif").to_string(), "10px serif");
+        let filter = Filter::new()
+            .blur(2.0)
+            .drop_shadow(1.0, 2.0, 3.0, Color::rgb(0, 0, 0));
+        assert_eq!(
+            filter.to_string(),
+            "blur(2px) drop-shadow(1px 2px 3px #000000)"
+        );
+        assert_eq!(Option::<String>::from(Filter::from(Some("none"))), None);
+        assert_eq!(
+            "color-dodge".parse::<CompositeOperation>().unwrap(),
+            CompositeOperation::ColorDodge
+        );
+    }
+}
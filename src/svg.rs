@@ -0,0 +1,1240 @@
+use crate::canvas::*;
+use crate::matrix::DomMatrix;
+use crate::recording::Recording;
+use crate::style::Color;
+use crate::websocket::*;
+use serde::Serialize;
+use std::collections::{HashMap, HashSet};
+use std::f64::consts::PI;
+use std::fmt::Write;
+use uuid::Uuid;
+
+// an op that couldn't be represented in the svg, and was left out or drawn approximately
+#[derive(Debug, Clone, PartialEq)]
+pub struct UnsupportedOp {
+    // position in the recording, counting the ops inside nested batches
+    pub index: usize,
+    pub op: &'static str,
+    pub reason: String,
+}
+
+#[derive(Debug, Clone)]
+pub struct SvgDocument {
+    pub svg: String,
+    pub unsupported: Vec<UnsupportedOp>,
+}
+
+impl SvgDocument {
+    // true if every op was represented exactly
+    pub fn is_exact(&self) -> bool {
+        self.unsupported.is_empty()
+    }
+}
+
+impl Recording {
+    pub fn to_svg(&self) -> SvgDocument {
+        let mut renderer = SvgRenderer::new(self.get_width(), self.get_height());
+        renderer.render(self.ops());
+        renderer.finish()
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Segment {
+    Move(f64, f64),
+    Line(f64, f64),
+    Cubic(f64, f64, f64, f64, f64, f64),
+    Close,
+}
+
+// points are stored already transformed: by the transform at the time for the context's path, and as is for
+// Path2D objects, which only get transformed when they're drawn
+#[derive(Debug, Clone, Default)]
+struct Path {
+    segments: Vec<Segment>,
+    start: Option<(f64, f64)>,
+    current: Option<(f64, f64)>,
+}
+
+impl Path {
+    fn is_empty(&self) -> bool {
+        self.segments.iter().all(|o| matches!(o, Segment::Move(..)))
+    }
+
+    fn move_to(&mut self, m: &DomMatrix, x: f64, y: f64) {
+        let point = m.transform_point(x, y);
+        self.segments.push(Segment::Move(point.0, point.1));
+        self.start = Some(point);
+        self.current = Some(point);
+    }
+
+    fn line_to(&mut self, m: &DomMatrix, x: f64, y: f64) {
+        if self.current.is_none() {
+            return self.move_to(m, x, y);
+        }
+        let point = m.transform_point(x, y);
+        self.segments.push(Segment::Line(point.0, point.1));
+        self.current = Some(point);
+    }
+
+    fn cubic_to(&mut self, m: &DomMatrix, cp1: (f64, f64), cp2: (f64, f64), end: (f64, f64)) {
+        if self.current.is_none() {
+            self.move_to(m, cp1.0, cp1.1);
+        }
+        let cp1 = m.transform_point(cp1.0, cp1.1);
+        let cp2 = m.transform_point(cp2.0, cp2.1);
+        let end = m.transform_point(end.0, end.1);
+        self.segments
+            .push(Segment::Cubic(cp1.0, cp1.1, cp2.0, cp2.1, end.0, end.1));
+        self.current = Some(end);
+    }
+
+    fn quadratic_to(&mut self, m: &DomMatrix, cp: (f64, f64), end: (f64, f64)) {
+        if self.current.is_none() {
+            self.move_to(m, cp.0, cp.1);
+        }
+        // elevated to a cubic, in path space since that's what the current point is in
+        let start = self.current.unwrap();
+        let cp = m.transform_point(cp.0, cp.1);
+        let end = m.transform_point(end.0, end.1);
+        self.segments.push(Segment::Cubic(
+            start.0 + 2.0 / 3.0 * (cp.0 - start.0),
+            start.1 + 2.0 / 3.0 * (cp.1 - start.1),
+            end.0 + 2.0 / 3.0 * (cp.0 - end.0),
+            end.1 + 2.0 / 3.0 * (cp.1 - end.1),
+            end.0,
+            end.1,
+        ));
+        self.current = Some(end);
+    }
+
+    fn close(&mut self) {
+        if self.current.is_some() {
+            self.segments.push(Segment::Close);
+            self.current = self.start;
+        }
+    }
+
+    // an elliptical arc approximated by cubics of at most a quarter turn each, joined to the current point by a line
+    #[allow(clippy::too_many_arguments)]
+    fn arc(
+        &mut self,
+        m: &DomMatrix,
+        center: (f64, f64),
+        radius_x: f64,
+        radius_y: f64,
+        rotation: f64,
+        start_angle: f64,
+        sweep: f64,
+    ) {
+        let (sin_r, cos_r) = rotation.sin_cos();
+        let point = |angle: f64| {
+            let (sin, cos) = angle.sin_cos();
+            (
+                center.0 + radius_x * cos * cos_r - radius_y * sin * sin_r,
+                center.1 + radius_x * cos * sin_r + radius_y * sin * cos_r,
+            )
+        };
+        let tangent = |angle: f64| {
+            let (sin, cos) = angle.sin_cos();
+            (
+                -radius_x * sin * cos_r - radius_y * cos * sin_r,
+                -radius_x * sin * sin_r + radius_y * cos * cos_r,
+            )
+        };
+        let (x, y) = point(start_angle);
+        self.line_to(m, x, y);
+        if sweep == 0.0 || radius_x == 0.0 || radius_y == 0.0 {
+            return;
+        }
+        let count = (sweep.abs() / (PI / 2.0)).ceil().max(1.0) as usize;
+        let delta = sweep / count as f64;
+        let k = 4.0 / 3.0 * (delta / 4.0).tan();
+        for i in 0..count {
+            let from = start_angle + delta * i as f64;
+            let to = from + delta;
+            let (p0, t0, p1, t1) = (point(from), tangent(from), point(to), tangent(to));
+            self.cubic_to(
+                m,
+                (p0.0 + k * t0.0, p0.1 + k * t0.1),
+                (p1.0 - k * t1.0, p1.1 - k * t1.1),
+                p1,
+            );
+        }
+    }
+
+    fn arc_to(&mut self, m: &DomMatrix, x1: f64, y1: f64, x2: f64, y2: f64, radius: f64) {
+        let current = match self.current {
+            Some(current) => current,
+            None => return self.move_to(m, x1, y1),
+        };
+        let (x0, y0) = match m.invert() {
+            Some(inverse) => inverse.transform_point(current.0, current.1),
+            None => return self.line_to(m, x1, y1),
+        };
+        let (ux, uy) = (x0 - x1, y0 - y1);
+        let (vx, vy) = (x2 - x1, y2 - y1);
+        let (u_length, v_length) = (ux.hypot(uy), vx.hypot(vy));
+        let cross = ux * vy - uy * vx;
+        if radius == 0.0
+            || u_length == 0.0
+            || v_length == 0.0
+            || cross.abs() < 1e-9 * u_length * v_length
+        {
+            return self.line_to(m, x1, y1);
+        }
+        let (ux, uy) = (ux / u_length, uy / u_length);
+        let (vx, vy) = (vx / v_length, vy / v_length);
+        let angle = (ux * vx + uy * vy).clamp(-1.0, 1.0).acos();
+        let tangent_distance = radius / (angle / 2.0).tan();
+        let center_distance = radius / (angle / 2.0).sin();
+        let (bx, by) = (ux + vx, uy + vy);
+        let b_length = bx.hypot(by);
+        let center = (
+            x1 + bx / b_length * center_distance,
+            y1 + by / b_length * center_distance,
+        );
+        let t1 = (x1 + ux * tangent_distance, y1 + uy * tangent_distance);
+        let t2 = (x1 + vx * tangent_distance, y1 + vy * tangent_distance);
+        let start = (t1.1 - center.1).atan2(t1.0 - center.0);
+        let mut sweep = (t2.1 - center.1).atan2(t2.0 - center.0) - start;
+        while sweep > PI {
+            sweep -= 2.0 * PI;
+        }
+        while sweep <= -PI {
+            sweep += 2.0 * PI;
+        }
+        self.arc(m, center, radius, radius, 0.0, start, sweep);
+    }
+
+    fn rect(&mut self, m: &DomMatrix, x: f64, y: f64, width: f64, height: f64) {
+        self.move_to(m, x, y);
+        self.line_to(m, x + width, y);
+        self.line_to(m, x + width, y + height);
+        self.line_to(m, x, y + height);
+        self.close();
+        self.move_to(m, x, y);
+    }
+
+    fn round_rect(
+        &mut self,
+        m: &DomMatrix,
+        x: f64,
+        y: f64,
+        width: f64,
+        height: f64,
+        radii: &CornerRadii,
+    ) {
+        let (x, width) = if width < 0.0 {
+            (x + width, -width)
+        } else {
+            (x, width)
+        };
+        let (y, height) = if height < 0.0 {
+            (y + height, -height)
+        } else {
+            (y, height)
+        };
+        // radii that don't fit are all scaled down together
+        let scale = [
+            width / (radii.top_left + radii.top_right),
+            width / (radii.bottom_left + radii.bottom_right),
+            height / (radii.top_left + radii.bottom_left),
+            height / (radii.top_right + radii.bottom_right),
+        ]
+        .iter()
+        .filter(|o| o.is_finite())
+        .fold(1.0f64, |scale, o| scale.min(*o));
+        let (tl, tr) = (radii.top_left * scale, radii.top_right * scale);
+        let (br, bl) = (radii.bottom_right * scale, radii.bottom_left * scale);
+        let quarter = PI / 2.0;
+        self.move_to(m, x + tl, y);
+        self.arc(m, (x + width - tr, y + tr), tr, tr, 0.0, -quarter, quarter);
+        self.arc(
+            m,
+            (x + width - br, y + height - br),
+            br,
+            br,
+            0.0,
+            0.0,
+            quarter,
+        );
+        self.arc(m, (x + bl, y + height - bl), bl, bl, 0.0, quarter, quarter);
+        self.arc(m, (x + tl, y + tl), tl, tl, 0.0, PI, quarter);
+        self.close();
+        self.move_to(m, x, y);
+    }
+
+    // the path data with every point mapped through `m`
+    fn data(&self, m: &DomMatrix) -> String {
+        let point = |x: f64, y: f64| {
+            let (x, y) = m.transform_point(x, y);
+            format!("{} {}", number(x), number(y))
+        };
+        let mut data = vec![];
+        for segment in &self.segments {
+            data.push(match *segment {
+                Segment::Move(x, y) => format!("M{}", point(x, y)),
+                Segment::Line(x, y) => format!("L{}", point(x, y)),
+                Segment::Cubic(x1, y1, x2, y2, x, y) => {
+                    format!("C{} {} {}", point(x1, y1), point(x2, y2), point(x, y))
+                }
+                Segment::Close => "Z".to_string(),
+            });
+        }
+        data.join(" ")
+    }
+}
+
+// the direction and size of the arc drawn by arc() and ellipse(), the same way the browser works it out
+fn arc_sweep(start_angle: f64, end_angle: f64, anticlockwise: bool) -> f64 {
+    if !anticlockwise && end_angle - start_angle >= 2.0 * PI {
+        2.0 * PI
+    } else if anticlockwise && start_angle - end_angle >= 2.0 * PI {
+        -2.0 * PI
+    } else if !anticlockwise {
+        (end_angle - start_angle).rem_euclid(2.0 * PI)
+    } else {
+        -(start_angle - end_angle).rem_euclid(2.0 * PI)
+    }
+}
+
+fn number(value: f64) -> String {
+    let rounded = (value * 1000.0).round() / 1000.0;
+    // avoids printing "-0"
+    format!("{}", rounded + 0.0)
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&apos;")
+}
+
+// the css keyword an enum is serialized as
+fn keyword<T: Serialize>(value: &T) -> String {
+    serde_json::to_value(value)
+        .ok()
+        .and_then(|o| o.as_str().map(|o| o.to_lowercase()))
+        .unwrap_or_default()
+}
+
+fn transform_attribute(matrix: &DomMatrix) -> String {
+    if matrix.is_identity() {
+        return String::new();
+    }
+    format!(
+        r#" transform="matrix({} {} {} {} {} {})""#,
+        number(matrix.a),
+        number(matrix.b),
+        number(matrix.c),
+        number(matrix.d),
+        number(matrix.e),
+        number(matrix.f)
+    )
+}
+
+const BLEND_MODES: &[&str] = &[
+    "multiply",
+    "screen",
+    "overlay",
+    "darken",
+    "lighten",
+    "color-dodge",
+    "color-burn",
+    "hard-light",
+    "soft-light",
+    "difference",
+    "exclusion",
+    "hue",
+    "saturation",
+    "color",
+    "luminosity",
+];
+
+const GRADIENT_PREFIX: &str = "url(#gradient-";
+
+enum Gradient {
+    Linear(f64, f64, f64, f64),
+    Radial(f64, f64, f64, f64, f64, f64),
+    Conic,
+}
+
+struct SvgRenderer {
+    width: u32,
+    height: u32,
+    state: DrawState,
+    // id of the clip path currently in effect, which includes every clip before it
+    clip: Option<usize>,
+    stack: Vec<(DrawState, Option<usize>)>,
+    path: Path,
+    paths: HashMap<Uuid, Path>,
+    gradients: HashMap<Uuid, (Gradient, Vec<(f64, String)>)>,
+    written_gradients: HashSet<Uuid>,
+    shadows: HashMap<String, usize>,
+    clip_count: usize,
+    defs: Vec<String>,
+    elements: Vec<String>,
+    index: usize,
+    op: &'static str,
+    unsupported: Vec<UnsupportedOp>,
+}
+
+impl SvgRenderer {
+    fn new(width: u32, height: u32) -> SvgRenderer {
+        SvgRenderer {
+            width,
+            height,
+            state: DrawState::default(),
+            clip: None,
+            stack: vec![],
+            path: Path::default(),
+            paths: HashMap::new(),
+            gradients: HashMap::new(),
+            written_gradients: HashSet::new(),
+            shadows: HashMap::new(),
+            clip_count: 0,
+            defs: vec![],
+            elements: vec![],
+            index: 0,
+            op: "",
+            unsupported: vec![],
+        }
+    }
+
+    fn report(&mut self, reason: &str) {
+        self.unsupported.push(UnsupportedOp {
+            index: self.index,
+            op: self.op,
+            reason: reason.to_string(),
+        });
+    }
+
+    fn reset(&mut self) {
+        self.state = DrawState::default();
+        self.clip = None;
+        self.stack.clear();
+        self.path = Path::default();
+    }
+
+    fn render(&mut self, ops: &[WSMessageData]) {
+        for op in ops {
+            self.op = op.name();
+            self.render_op(op);
+            if let WSMessageData::Batch { .. } = op {
+                continue;
+            }
+            self.index += 1;
+        }
+    }
+
+    fn render_op(&mut self, op: &WSMessageData) {
+        let transform = self.state.transform;
+        match op {
+            WSMessageData::Batch { ops } => self.render(ops),
+            WSMessageData::Save {} => self.stack.push((self.state.clone(), self.clip)),
+            WSMessageData::Restore {} => {
+                if let Some((state, clip)) = self.stack.pop() {
+                    self.state = state;
+                    self.clip = clip;
+                }
+            }
+            WSMessageData::Reset {} => {
+                self.reset();
+                self.elements.clear();
+            }
+            WSMessageData::SetWidth { width } => {
+                self.width = *width as u32;
+                self.reset();
+                self.elements.clear();
+            }
+            WSMessageData::SetHeight { height } => {
+                self.height = *height as u32;
+                self.reset();
+                self.elements.clear();
+            }
+            WSMessageData::Resize {
+                width,
+                height,
+                preserve_content,
+            } => {
+                self.width = *width;
+                self.height = *height;
+                self.reset();
+                if !preserve_content {
+                    self.elements.clear();
+                }
+            }
+            WSMessageData::CreateLinearGradient {
+                gradient_uuid,
+                x0,
+                y0,
+                x1,
+                y1,
+                stops,
+            } => {
+                let gradient = Gradient::Linear(*x0, *y0, *x1, *y1);
+                self.gradients
+                    .insert(*gradient_uuid, (gradient, stops.clone()));
+            }
+            WSMessageData::CreateRadialGradient {
+                gradient_uuid,
+                x0,
+                y0,
+                r0,
+                x1,
+                y1,
+                r1,
+                stops,
+            } => {
+                let gradient = Gradient::Radial(*x0, *y0, *r0, *x1, *y1, *r1);
+                self.gradients
+                    .insert(*gradient_uuid, (gradient, stops.clone()));
+            }
+            WSMessageData::CreateConicGradient {
+                gradient_uuid,
+                stops,
+                ..
+            } => {
+                self.gradients
+                    .insert(*gradient_uuid, (Gradient::Conic, stops.clone()));
+            }
+            WSMessageData::BeginPath { path_uuid } => match path_uuid {
+                Some(path_uuid) => {
+                    self.paths.insert(*path_uuid, Path::default());
+                }
+                None => self.path = Path::default(),
+            },
+            WSMessageData::ClosePath { path_uuid } => {
+                if let Some((path, _)) = self.target(path_uuid) {
+                    path.close();
+                }
+            }
+            WSMessageData::MoveTo { path_uuid, x, y } => {
+                if let Some((path, m)) = self.target(path_uuid) {
+                    path.move_to(&m, *x, *y);
+                }
+            }
+            WSMessageData::LineTo { path_uuid, x, y } => {
+                if let Some((path, m)) = self.target(path_uuid) {
+                    path.line_to(&m, *x, *y);
+                }
+            }
+            WSMessageData::BezierCurveTo {
+                path_uuid,
+                cp1x,
+                cp1y,
+                cp2x,
+                cp2y,
+                x,
+                y,
+            } => {
+                if let Some((path, m)) = self.target(path_uuid) {
+                    path.cubic_to(&m, (*cp1x, *cp1y), (*cp2x, *cp2y), (*x, *y));
+                }
+            }
+            WSMessageData::QuadraticCurveTo {
+                path_uuid,
+                cpx,
+                cpy,
+                x,
+                y,
+            } => {
+                if let Some((path, m)) = self.target(path_uuid) {
+                    path.quadratic_to(&m, (*cpx, *cpy), (*x, *y));
+                }
+            }
+            WSMessageData::Arc {
+                path_uuid,
+                x,
+                y,
+                radius,
+                start_angle,
+                end_angle,
+                anticlockwise,
+            } => {
+                let sweep = arc_sweep(*start_angle, *end_angle, *anticlockwise);
+                if let Some((path, m)) = self.target(path_uuid) {
+                    path.arc(&m, (*x, *y), *radius, *radius, 0.0, *start_angle, sweep);
+                }
+            }
+            WSMessageData::ArcTo {
+                path_uuid,
+                x1,
+                y1,
+                x2,
+                y2,
+                radius,
+            } => {
+                if let Some((path, m)) = self.target(path_uuid) {
+                    path.arc_to(&m, *x1, *y1, *x2, *y2, *radius);
+                }
+            }
+            WSMessageData::Ellipse {
+                path_uuid,
+                x,
+                y,
+                radius_x,
+                radius_y,
+                rotation,
+                start_angle,
+                end_angle,
+                anticlockwise,
+            } => {
+                let sweep = arc_sweep(*start_angle, *end_angle, *anticlockwise);
+                if let Some((path, m)) = self.target(path_uuid) {
+                    path.arc(
+                        &m,
+                        (*x, *y),
+                        *radius_x,
+                        *radius_y,
+                        *rotation,
+                        *start_angle,
+                        sweep,
+                    );
+                }
+            }
+            WSMessageData::Rect {
+                path_uuid,
+                x,
+                y,
+                width,
+                height,
+            } => {
+                if let Some((path, m)) = self.target(path_uuid) {
+                    path.rect(&m, *x, *y, *width, *height);
+                }
+            }
+            WSMessageData::RoundRect {
+                path_uuid,
+                x,
+                y,
+                width,
+                height,
+                radii,
+            } => {
+                if let Some((path, m)) = self.target(path_uuid) {
+                    path.round_rect(&m, *x, *y, *width, *height, radii);
+                }
+            }
+            WSMessageData::Fill {
+                path_uuid,
+                is_even_odd,
+            } => {
+                if let Some(shape) = self.shape(path_uuid) {
+                    let fill = self.fill_attributes(*is_even_odd);
+                    self.push_element(format!("<path{}{}/>", shape, fill));
+                }
+            }
+            WSMessageData::Stroke { path_uuid } => {
+                if let Some(shape) = self.shape(path_uuid) {
+                    let stroke = self.stroke_attributes();
+                    self.push_element(format!("<path{}{}/>", shape, stroke));
+                }
+            }
+            WSMessageData::Clip {
+                path_uuid,
+                is_even_odd,
+            } => {
+                if let Some(shape) = self.shape(path_uuid) {
+                    let rule = if *is_even_odd { "evenodd" } else { "nonzero" };
+                    let path = format!(r#"<path{} clip-rule="{}"/>"#, shape, rule);
+                    self.clip = Some(self.add_clip(path));
+                }
+            }
+            WSMessageData::FillRect {
+                x,
+                y,
+                width,
+                height,
+            } => {
+                let fill = self.fill_attributes(false);
+                let rect = rect_element(*x, *y, *width, *height, &transform);
+                self.push_element(format!("{}{}/>", rect, fill));
+            }
+            WSMessageData::StrokeRect {
+                x,
+                y,
+                width,
+                height,
+            } => {
+                let stroke = self.stroke_attributes();
+                let rect = rect_element(*x, *y, *width, *height, &transform);
+                self.push_element(format!("{}{}/>", rect, stroke));
+            }
+            WSMessageData::ClearRect {
+                x,
+                y,
+                width,
+                height,
+            } => {
+                // clearing part of the canvas would need masking everything drawn so far, so only clearing the
+                // whole canvas is supported
+                let (x, y, width, height) = transform.transform_rect(*x, *y, *width, *height);
+                let axis_aligned = transform.b == 0.0 && transform.c == 0.0;
+                if axis_aligned
+                    && self.clip.is_none()
+                    && x <= 0.0
+                    && y <= 0.0
+                    && x + width >= self.width as f64
+                    && y + height >= self.height as f64
+                {
+                    self.elements.clear();
+                } else {
+                    self.report("only clearing the whole canvas can be represented");
+                }
+            }
+            WSMessageData::FillText {
+                text,
+                x,
+                y,
+                max_width,
+            } => self.text(text, *x, *y, *max_width, false),
+            WSMessageData::StrokeText {
+                text,
+                x,
+                y,
+                max_width,
+            } => self.text(text, *x, *y, *max_width, true),
+            WSMessageData::FillTextLines {
+                font,
+                lines,
+                clip,
+                letter_spacing,
+                word_spacing,
+            } => {
+                let saved = (self.state.clone(), self.clip);
+                if let Some((x, y, width, height)) = clip {
+                    let rect = rect_element(*x, *y, *width, *height, &transform);
+                    self.clip = Some(self.add_clip(format!("{}/>", rect)));
+                }
+                self.state.font = font.clone();
+                if let Some(letter_spacing) = letter_spacing {
+                    self.state.letter_spacing = letter_spacing.clone();
+                }
+                if let Some(word_spacing) = word_spacing {
+                    self.state.word_spacing = word_spacing.clone();
+                }
+                self.state.text_align = TextAlign::Left;
+                self.state.text_baseline = TextBaseline::Alphabetic;
+                for (text, x, y) in lines {
+                    self.text(text, *x, *y, None, false);
+                }
+                self.state = saved.0;
+                self.clip = saved.1;
+            }
+            WSMessageData::DrawImage {
+                url,
+                source,
+                dx,
+                dy,
+                d_dims,
+            } => {
+                let image = match (source, d_dims) {
+                    (None, None) => format!(
+                        r#"<image xlink:href="{}" x="{}" y="{}"{}/>"#,
+                        escape(url),
+                        number(*dx),
+                        number(*dy),
+                        transform_attribute(&transform)
+                    ),
+                    (None, Some((width, height))) => format!(
+                        r#"<image xlink:href="{}" x="{}" y="{}" width="{}" height="{}" preserveAspectRatio="none"{}/>"#,
+                        escape(url),
+                        number(*dx),
+                        number(*dy),
+                        number(*width),
+                        number(*height),
+                        transform_attribute(&transform)
+                    ),
+                    (Some((sx, sy, s_width, s_height)), d_dims) => {
+                        let (width, height) = d_dims.unwrap_or((*s_width, *s_height));
+                        // a nested viewport crops the image down to the source rect
+                        format!(
+                            r#"<g{}><svg x="{}" y="{}" width="{}" height="{}" viewBox="{} {} {} {}" preserveAspectRatio="none"><image xlink:href="{}"/></svg></g>"#,
+                            transform_attribute(&transform),
+                            number(*dx),
+                            number(*dy),
+                            number(width),
+                            number(height),
+                            number(*sx),
+                            number(*sy),
+                            number(*s_width),
+                            number(*s_height),
+                            escape(url)
+                        )
+                    }
+                };
+                self.push_element(image);
+            }
+            WSMessageData::DrawImageData { .. }
+            | WSMessageData::SetImageData { .. }
+            | WSMessageData::DrawCanvas { .. } => {
+                self.report("raster content can't be represented");
+            }
+            WSMessageData::MeasureText { .. }
+            | WSMessageData::MeasureTexts { .. }
+            | WSMessageData::IsPointInPath { .. }
+            | WSMessageData::IsPointInStroke { .. }
+            | WSMessageData::GetImageData { .. }
+            | WSMessageData::GetContextAttributes {}
+            | WSMessageData::IsContextLost {}
+            | WSMessageData::ToDataUrl { .. }
+            | WSMessageData::Export { .. }
+            | WSMessageData::Init2DCanvas { .. }
+            | WSMessageData::Destroy2DCanvas { .. }
+            | WSMessageData::RegisterFont { .. } => {
+                self.report("needs a browser");
+            }
+            // patterns can't be set as a style, and the rest only affect the browser
+            WSMessageData::CreatePattern { .. }
+            | WSMessageData::PreloadImages { .. }
+            | WSMessageData::EvictImages { .. }
+            | WSMessageData::SetStrictMode { .. } => {}
+            _ => self.state.update(op),
+        }
+    }
+
+    // the path an op builds on, and the transform to apply to its points
+    fn target(&mut self, path_uuid: &Option<Uuid>) -> Option<(&mut Path, DomMatrix)> {
+        let transform = self.state.transform;
+        match path_uuid {
+            None => Some((&mut self.path, transform)),
+            Some(path_uuid) => {
+                if !self.paths.contains_key(path_uuid) {
+                    self.report("unknown path");
+                    return None;
+                }
+                Some((
+                    self.paths.get_mut(path_uuid).unwrap(),
+                    DomMatrix::identity(),
+                ))
+            }
+        }
+    }
+
+    // the d and transform attributes for drawing a path in the current user space
+    fn shape(&mut self, path_uuid: &Option<Uuid>) -> Option<String> {
+        let transform = self.state.transform;
+        let (data, transform) = match path_uuid {
+            None => {
+                if self.path.is_empty() {
+                    return None;
+                }
+                match transform.invert() {
+                    Some(inverse) => (self.path.data(&inverse), transform),
+                    None => (
+                        self.path.data(&DomMatrix::identity()),
+                        DomMatrix::identity(),
+                    ),
+                }
+            }
+            Some(path_uuid) => match self.paths.get(path_uuid) {
+                Some(path) if path.is_empty() => return None,
+                Some(path) => (path.data(&DomMatrix::identity()), transform),
+                None => {
+                    self.report("unknown path");
+                    return None;
+                }
+            },
+        };
+        Some(format!(
+            r#" d="{}"{}"#,
+            data,
+            transform_attribute(&transform)
+        ))
+    }
+
+    // a fill or stroke style as an svg paint, writing out the gradient it references
+    fn paint(&mut self, style: &str) -> String {
+        if !style.starts_with(GRADIENT_PREFIX) || !style.ends_with(')') {
+            return escape(style);
+        }
+        let uuid = &style[GRADIENT_PREFIX.len()..style.len() - 1];
+        let uuid = match Uuid::parse_str(uuid) {
+            Ok(uuid) if self.gradients.contains_key(&uuid) => uuid,
+            _ => {
+                self.report("unknown gradient");
+                return "none".to_string();
+            }
+        };
+        let (gradient, stops) = &self.gradients[&uuid];
+        let stops: String = stops
+            .iter()
+            .map(|(offset, color)| {
+                format!(
+                    r#"<stop offset="{}" stop-color="{}"/>"#,
+                    number(*offset),
+                    escape(color)
+                )
+            })
+            .collect();
+        let element = match gradient {
+            Gradient::Linear(x0, y0, x1, y1) => format!(
+                r#"<linearGradient id="gradient-{}" gradientUnits="userSpaceOnUse" x1="{}" y1="{}" x2="{}" y2="{}">{}</linearGradient>"#,
+                uuid,
+                number(*x0),
+                number(*y0),
+                number(*x1),
+                number(*y1),
+                stops
+            ),
+            Gradient::Radial(x0, y0, r0, x1, y1, r1) => format!(
+                r#"<radialGradient id="gradient-{}" gradientUnits="userSpaceOnUse" fx="{}" fy="{}" fr="{}" cx="{}" cy="{}" r="{}">{}</radialGradient>"#,
+                uuid,
+                number(*x0),
+                number(*y0),
+                number(*r0),
+                number(*x1),
+                number(*y1),
+                number(*r1),
+                stops
+            ),
+            Gradient::Conic => {
+                // drawn with its first color instead
+                let first = self.gradients[&uuid].1.first().map(|o| escape(&o.1));
+                self.report("conic gradients can't be represented");
+                return first.unwrap_or_else(|| "none".to_string());
+            }
+        };
+        if self.written_gradients.insert(uuid) {
+            self.defs.push(element);
+        }
+        escape(style)
+    }
+
+    fn fill_attributes(&mut self, is_even_odd: bool) -> String {
+        let fill_style = self.state.fill_style.clone();
+        let mut attributes = format!(r#" fill="{}""#, self.paint(&fill_style));
+        if is_even_odd {
+            attributes.push_str(r#" fill-rule="evenodd""#);
+        }
+        attributes
+    }
+
+    fn stroke_attributes(&mut self) -> String {
+        let stroke_style = self.state.stroke_style.clone();
+        let state = &self.state;
+        let mut attributes = format!(
+            r#" fill="none" stroke-width="{}" stroke-miterlimit="{}""#,
+            number(state.line_width),
+            number(state.miter_limit)
+        );
+        if state.line_cap != LineCap::Butt {
+            write!(
+                attributes,
+                r#" stroke-linecap="{}""#,
+                keyword(&state.line_cap)
+            )
+            .unwrap();
+        }
+        if state.line_join != LineJoin::Miter {
+            write!(
+                attributes,
+                r#" stroke-linejoin="{}""#,
+                keyword(&state.line_join)
+            )
+            .unwrap();
+        }
+        if !state.line_dash.is_empty() {
+            let dashes: Vec<String> = state.line_dash.iter().map(|o| number(*o)).collect();
+            write!(
+                attributes,
+                r#" stroke-dasharray="{}" stroke-dashoffset="{}""#,
+                dashes.join(" "),
+                number(state.line_dash_offset)
+            )
+            .unwrap();
+        }
+        format!(r#" stroke="{}"{}"#, self.paint(&stroke_style), attributes)
+    }
+
+    // adds a clip path, intersected with the current clip, and returns its id
+    fn add_clip(&mut self, element: String) -> usize {
+        self.clip_count += 1;
+        let parent = match self.clip {
+            Some(clip) => format!(r#" clip-path="url(#clip-{})""#, clip),
+            None => String::new(),
+        };
+        self.defs.push(format!(
+            r#"<clipPath id="clip-{}" clipPathUnits="userSpaceOnUse"{}>{}</clipPath>"#,
+            self.clip_count, parent, element
+        ));
+        self.clip_count
+    }
+
+    fn shadow(&mut self) -> Option<usize> {
+        let state = &self.state;
+        if state.shadow_blur == 0.0 && state.shadow_offset_x == 0.0 && state.shadow_offset_y == 0.0
+        {
+            return None;
+        }
+        let color = match state.shadow_color.parse::<Color>() {
+            Ok(color) if color.a == 0.0 => return None,
+            Ok(color) => color,
+            Err(_) => {
+                self.report("shadow color can't be represented");
+                return None;
+            }
+        };
+        // shadow offsets aren't affected by the transform, so the filter works in canvas pixels
+        let filter = format!(
+            r##"x="0" y="0" width="{}" height="{}" filterUnits="userSpaceOnUse"><feDropShadow dx="{}" dy="{}" stdDeviation="{}" flood-color="#{:02x}{:02x}{:02x}" flood-opacity="{}"/>"##,
+            self.width,
+            self.height,
+            number(state.shadow_offset_x),
+            number(state.shadow_offset_y),
+            number(state.shadow_blur / 2.0),
+            color.r,
+            color.g,
+            color.b,
+            number(color.a)
+        );
+        let count = self.shadows.len();
+        let id = *self.shadows.entry(filter.clone()).or_insert(count + 1);
+        if id == count + 1 {
+            self.defs
+                .push(format!(r#"<filter id="shadow-{}" {}</filter>"#, id, filter));
+        }
+        Some(id)
+    }
+
+    // wraps a drawn element in whatever applies the current clip, alpha, shadow, filter and compositing
+    fn push_element(&mut self, element: String) {
+        let mut attributes = String::new();
+        let mut styles = vec![];
+        if let Some(clip) = self.clip {
+            write!(attributes, r#" clip-path="url(#clip-{})""#, clip).unwrap();
+        }
+        if self.state.global_alpha < 1.0 {
+            write!(
+                attributes,
+                r#" opacity="{}""#,
+                number(self.state.global_alpha)
+            )
+            .unwrap();
+        }
+        if let Some(shadow) = self.shadow() {
+            write!(attributes, r#" filter="url(#shadow-{})""#, shadow).unwrap();
+        }
+        let operation = self.state.global_composite_operation.clone();
+        if BLEND_MODES.contains(&&*operation) {
+            styles.push(format!("mix-blend-mode: {}", operation));
+        } else if operation != "source-over" {
+            self.report("only blend mode composite operations can be represented");
+        }
+        // css filters apply before the shadow, so they need their own group when there is one
+        let mut element = element;
+        if let Some(filter) = &self.state.filter {
+            if attributes.contains("filter=") {
+                element = format!(r#"<g style="filter: {}">{}</g>"#, escape(filter), element);
+            } else {
+                styles.push(format!("filter: {}", escape(filter)));
+            }
+        }
+        if !styles.is_empty() {
+            write!(attributes, r#" style="{}""#, styles.join("; ")).unwrap();
+        }
+        if attributes.is_empty() {
+            self.elements.push(element);
+        } else {
+            self.elements
+                .push(format!("<g{}>{}</g>", attributes, element));
+        }
+    }
+
+    fn text(&mut self, text: &str, x: f64, y: f64, max_width: Option<f64>, stroke: bool) {
+        if max_width.is_some() {
+            self.report("max_width is ignored");
+        }
+        let paint = if stroke {
+            self.stroke_attributes()
+        } else {
+            self.fill_attributes(false)
+        };
+        let state = &self.state;
+        let rtl = state.text_direction == TextDirection::Rtl;
+        let anchor = match state.text_align {
+            TextAlign::Start => "start",
+            TextAlign::End => "end",
+            TextAlign::Center => "middle",
+            TextAlign::Left if rtl => "end",
+            TextAlign::Left => "start",
+            TextAlign::Right if rtl => "start",
+            TextAlign::Right => "end",
+        };
+        let mut attributes = String::new();
+        if anchor != "start" {
+            write!(attributes, r#" text-anchor="{}""#, anchor).unwrap();
+        }
+        let baseline = match state.text_baseline {
+            TextBaseline::Top => Some("text-before-edge"),
+            TextBaseline::Hanging => Some("hanging"),
+            TextBaseline::Middle => Some("central"),
+            TextBaseline::Alphabetic => None,
+            TextBaseline::Ideographic => Some("ideographic"),
+            TextBaseline::Bottom => Some("text-after-edge"),
+        };
+        if let Some(baseline) = baseline {
+            write!(attributes, r#" dominant-baseline="{}""#, baseline).unwrap();
+        }
+        if rtl {
+            attributes.push_str(r#" direction="rtl""#);
+        }
+        if state.text_rendering != TextRendering::Auto {
+            write!(
+                attributes,
+                r#" text-rendering="{}""#,
+                serde_json::to_value(state.text_rendering)
+                    .ok()
+                    .and_then(|o| o.as_str().map(|o| o.to_string()))
+                    .unwrap_or_default()
+            )
+            .unwrap();
+        }
+        let mut style = vec![format!("font: {}", state.font)];
+        if state.letter_spacing != "0px" {
+            style.push(format!("letter-spacing: {}", state.letter_spacing));
+        }
+        if state.word_spacing != "0px" {
+            style.push(format!("word-spacing: {}", state.word_spacing));
+        }
+        if state.font_kerning != FontKerning::Auto {
+            style.push(format!("font-kerning: {}", keyword(&state.font_kerning)));
+        }
+        if state.font_stretch != FontStretch::Normal {
+            style.push(format!("font-stretch: {}", keyword(&state.font_stretch)));
+        }
+        if state.font_variant_caps != FontVariantCaps::Normal {
+            style.push(format!(
+                "font-variant-caps: {}",
+                keyword(&state.font_variant_caps)
+            ));
+        }
+        let element = format!(
+            r#"<text xml:space="preserve" x="{}" y="{}"{}{} style="{}"{}>{}</text>"#,
+            number(x),
+            number(y),
+            transform_attribute(&state.transform),
+            attributes,
+            escape(&style.join("; ")),
+            paint,
+            escape(text)
+        );
+        self.push_element(element);
+    }
+
+    fn finish(self) -> SvgDocument {
+        let mut svg = format!(
+            r#"<svg xmlns="http://www.w3.org/2000/svg" xmlns:xlink="http://www.w3.org/1999/xlink" width="{0}" height="{1}" viewBox="0 0 {0} {1}">"#,
+            self.width, self.height
+        );
+        if !self.defs.is_empty() {
+            svg.push_str("<defs>");
+            svg.push_str(&self.defs.concat());
+            svg.push_str("</defs>");
+        }
+        for element in &self.elements {
+            svg.push_str(element);
+        }
+        svg.push_str("</svg>");
+        SvgDocument {
+            svg,
+            unsupported: self.unsupported,
+        }
+    }
+}
+
+// an unclosed <rect> with its x, y, width, height and transform attributes
+fn rect_element(x: f64, y: f64, width: f64, height: f64, transform: &DomMatrix) -> String {
+    let (x, width) = if width < 0.0 {
+        (x + width, -width)
+    } else {
+        (x, width)
+    };
+    let (y, height) = if height < 0.0 {
+        (y + height, -height)
+    } else {
+        (y, height)
+    };
+    format!(
+        r#"<rect x="{}" y="{}" width="{}" height="{}"{}"#,
+        number(x),
+        number(y),
+        number(width),
+        number(height),
+        transform_attribute(transform)
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn arcs_become_cubics() {
+        let mut path = Path::default();
+        let identity = DomMatrix::identity();
+        path.arc(
+            &identity,
+            (0.0, 0.0),
+            10.0,
+            10.0,
+            0.0,
+            0.0,
+            arc_sweep(0.0, PI, false),
+        );
+        assert_eq!(path.segments.len(), 3);
+        assert_eq!(
+            path.data(&identity),
+            "M10 0 C10 5.523 5.523 10 0 10 C-5.523 10 -10 5.523 -10 0"
+        );
+        assert_eq!(arc_sweep(0.0, PI / 2.0, true), -1.5 * PI);
+        assert_eq!(arc_sweep(0.0, 3.0 * PI, false), 2.0 * PI);
+    }
+
+    #[tokio::test]
+    async fn renders_a_recording() {
+        let mut recorder = Canvas2D::new_recorder(100, 100).unwrap();
+        let gradient = recorder
+            .create_linear_gradient(
+                0.0,
+                0.0,
+                100.0,
+                0.0,
+                vec![(0.0, "red".to_string()), (1.0, "blue".to_string())],
+            )
+            .await
+            .unwrap();
+        recorder.set_fill_style(&gradient).await.unwrap();
+        recorder.translate(10.0, 20.0).await.unwrap();
+        recorder.begin_path(None).await.unwrap();
+        recorder.rect(None, 0.0, 0.0, 5.0, 5.0).await.unwrap();
+        recorder.fill(None, false).await.unwrap();
+        recorder.set_global_alpha(0.5).await.unwrap();
+        recorder.fill_text("a < b", 1.0, 2.0, None).await.unwrap();
+        recorder
+            .set_global_composite_operation("xor")
+            .await
+            .unwrap();
+        recorder.fill_rect(0.0, 0.0, 1.0, 1.0).await.unwrap();
+        let document = recorder.take_recording().unwrap().to_svg();
+
+        assert!(document.svg.contains(&format!(
+            r#"<linearGradient id="gradient-{}" gradientUnits="userSpaceOnUse" x1="0" y1="0" x2="100" y2="0">"#,
+            gradient.get_uuid()
+        )));
+        assert!(document.svg.contains(&format!(
+            r#"<path d="M0 0 L5 0 L5 5 L0 5 Z M0 0" transform="matrix(1 0 0 1 10 20)" fill="url(#gradient-{})"/>"#,
+            gradient.get_uuid()
+        )));
+        assert!(document
+            .svg
+            .contains(r#"<g opacity="0.5"><text xml:space="preserve" x="1" y="2""#));
+        assert!(document.svg.contains(">a &lt; b</text>"));
+        assert_eq!(document.unsupported.len(), 1);
+        assert_eq!(document.unsupported[0].op, "FillRect");
+    }
+}
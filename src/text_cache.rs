@@ -0,0 +1,68 @@
+use crate::canvas::*;
+use std::collections::{HashMap, VecDeque};
+use std::sync::Mutex;
+use uuid::Uuid;
+
+// everything that can change the result of measureText for the same string
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub(crate) struct TextMetricsKey {
+    pub browser: Uuid,
+    pub font: String,
+    pub direction: TextDirection,
+    pub letter_spacing: String,
+    pub word_spacing: String,
+    pub font_kerning: FontKerning,
+    pub font_stretch: FontStretch,
+    pub font_variant_caps: FontVariantCaps,
+    pub text_rendering: TextRendering,
+    pub text: String,
+}
+
+struct CacheEntries {
+    metrics: HashMap<TextMetricsKey, TextMetrics>,
+    // insertion order, oldest first
+    order: VecDeque<TextMetricsKey>,
+}
+
+pub(crate) struct TextMetricsCache {
+    capacity: usize,
+    entries: Mutex<CacheEntries>,
+}
+
+impl TextMetricsCache {
+    pub(crate) fn new(capacity: usize) -> TextMetricsCache {
+        TextMetricsCache {
+            capacity,
+            entries: Mutex::new(CacheEntries {
+                metrics: HashMap::new(),
+                order: VecDeque::new(),
+            }),
+        }
+    }
+
+    pub(crate) fn get(&self, key: &TextMetricsKey) -> Option<TextMetrics> {
+        self.entries.lock().unwrap().metrics.get(key).cloned()
+    }
+
+    pub(crate) fn insert(&self, key: TextMetricsKey, metrics: TextMetrics) {
+        if self.capacity == 0 {
+            return;
+        }
+        let mut entries = self.entries.lock().unwrap();
+        if entries.metrics.insert(key.clone(), metrics).is_some() {
+            return;
+        }
+        entries.order.push_back(key);
+        while entries.order.len() > self.capacity {
+            if let Some(oldest) = entries.order.pop_front() {
+                entries.metrics.remove(&oldest);
+            }
+        }
+    }
+
+    pub(crate) fn clear(&self) {
+        let mut entries = self.entries.lock().unwrap();
+        entries.metrics.clear();
+        entries.order.clear();
+    }
+}
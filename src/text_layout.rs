@@ -0,0 +1,464 @@
+use crate::canvas::*;
+use crate::result::*;
+use crate::websocket::*;
+use std::collections::{HashMap, HashSet};
+
+const ELLIPSIS: &str = "\u{2026}";
+// measured for the height of its ascender and descender
+const TALL_TEXT: &str = "Hg";
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TextOverflow {
+    // lines past the bottom of the box are still drawn
+    Wrap,
+    // the last line that fits is cut short and ends in an ellipsis
+    Ellipsis,
+    // lines are drawn clipped to the box
+    Clip,
+    // the font is scaled down until every line fits in the box
+    ShrinkToFit,
+}
+
+#[derive(Debug, Clone)]
+pub struct TextLayout {
+    pub x: f64,
+    pub y: f64,
+    pub width: f64,
+    pub height: f64,
+    pub font: String,
+    pub align: TextAlign,
+    // multiple of the font's height
+    pub line_height: f64,
+    pub overflow: TextOverflow,
+}
+
+impl TextLayout {
+    pub fn new(x: f64, y: f64, width: f64, height: f64, font: &str) -> TextLayout {
+        TextLayout {
+            x,
+            y,
+            width,
+            height,
+            font: font.to_string(),
+            align: TextAlign::Start,
+            line_height: 1.2,
+            overflow: TextOverflow::Wrap,
+        }
+    }
+
+    pub fn align(mut self, align: TextAlign) -> TextLayout {
+        self.align = align;
+        self
+    }
+
+    pub fn line_height(mut self, line_height: f64) -> TextLayout {
+        self.line_height = line_height;
+        self
+    }
+
+    pub fn overflow(mut self, overflow: TextOverflow) -> TextLayout {
+        self.overflow = overflow;
+        self
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct LineBox {
+    pub text: String,
+    pub x: f64,
+    pub y: f64,
+    pub width: f64,
+    pub height: f64,
+    // y of the alphabetic baseline the line is drawn on
+    pub baseline: f64,
+}
+
+struct Measurements {
+    widths: HashMap<String, f64>,
+    space: f64,
+    ellipsis: f64,
+    ascent: f64,
+    descent: f64,
+}
+
+#[derive(Default)]
+struct Line<'a> {
+    words: Vec<&'a str>,
+    width: f64,
+}
+
+fn break_lines<'a>(
+    paragraphs: &[Vec<&'a str>],
+    measurements: &Measurements,
+    max_width: f64,
+) -> Vec<Line<'a>> {
+    let mut lines = vec![];
+    for paragraph in paragraphs {
+        let mut line = Line::default();
+        for word in paragraph {
+            let width = measurements.widths[*word];
+            if line.words.is_empty() {
+                line.width = width;
+            } else if line.width + measurements.space + width <= max_width {
+                line.width += measurements.space + width;
+            } else {
+                lines.push(std::mem::replace(
+                    &mut line,
+                    Line {
+                        words: vec![],
+                        width,
+                    },
+                ));
+            }
+            line.words.push(word);
+        }
+        lines.push(line);
+    }
+    lines
+}
+
+fn fits(lines: &[Line], advance: f64, layout: &TextLayout, scale: f64) -> bool {
+    lines.len() as f64 * advance * scale <= layout.height
+        && lines.iter().all(|line| line.width * scale <= layout.width)
+}
+
+// returns the laid out lines, and how much the font had to be scaled by
+fn arrange(
+    text: &str,
+    layout: &TextLayout,
+    measurements: &Measurements,
+    rtl: bool,
+) -> (Vec<LineBox>, f64) {
+    let paragraphs: Vec<Vec<&str>> = text
+        .split('\n')
+        .map(|paragraph| paragraph.split_whitespace().collect())
+        .collect();
+    let font_height = measurements.ascent + measurements.descent;
+    let advance = font_height * layout.line_height;
+
+    let mut scale = 1.0;
+    let mut lines = break_lines(&paragraphs, measurements, layout.width);
+    if layout.overflow == TextOverflow::ShrinkToFit && !fits(&lines, advance, layout, 1.0) {
+        let (mut low, mut high) = (0.0, 1.0);
+        for _ in 0..24 {
+            let middle = (low + high) / 2.0;
+            let candidate = break_lines(&paragraphs, measurements, layout.width / middle);
+            if fits(&candidate, advance, layout, middle) {
+                low = middle;
+            } else {
+                high = middle;
+            }
+        }
+        scale = if low > 0.0 { low } else { high };
+        lines = break_lines(&paragraphs, measurements, layout.width / scale);
+    }
+
+    let mut texts: Vec<(String, f64)> = lines
+        .iter()
+        .map(|line| (line.words.join(" "), line.width))
+        .collect();
+    if layout.overflow == TextOverflow::Ellipsis {
+        let max_lines = ((layout.height / advance) + 1e-9).floor().max(1.0) as usize;
+        if lines.len() > max_lines {
+            texts.truncate(max_lines - 1);
+            let mut text = String::new();
+            let mut width = 0.0;
+            for word in &lines[max_lines - 1].words {
+                let word_width = measurements.widths[*word];
+                let added = if text.is_empty() {
+                    word_width
+                } else {
+                    measurements.space + word_width
+                };
+                if width + added + measurements.ellipsis > layout.width {
+                    break;
+                }
+                if !text.is_empty() {
+                    text.push(' ');
+                }
+                text.push_str(word);
+                width += added;
+            }
+            text.push_str(ELLIPSIS);
+            texts.push((text, width + measurements.ellipsis));
+        }
+    }
+
+    // lines are drawn from their left edge, so start and end follow the direction like they would in the browser
+    let align = match (layout.align, rtl) {
+        (TextAlign::Start, false) | (TextAlign::End, true) => TextAlign::Left,
+        (TextAlign::Start, true) | (TextAlign::End, false) => TextAlign::Right,
+        (align, _) => align,
+    };
+    let line_boxes = texts
+        .into_iter()
+        .enumerate()
+        .map(|(i, (text, width))| {
+            let width = width * scale;
+            let height = advance * scale;
+            let y = layout.y + i as f64 * height;
+            let x = match align {
+                TextAlign::Start | TextAlign::Left => layout.x,
+                TextAlign::End | TextAlign::Right => layout.x + layout.width - width,
+                TextAlign::Center => layout.x + (layout.width - width) / 2.0,
+            };
+            LineBox {
+                text,
+                x,
+                y,
+                width,
+                height,
+                baseline: y + ((advance - font_height) / 2.0 + measurements.ascent) * scale,
+            }
+        })
+        .collect();
+    (line_boxes, scale)
+}
+
+// the ascent and descent lines are spaced by. browsers without fontBoundingBox* report 0 for both, there the
+// extent of tall glyphs stands in for the font's
+fn font_extent(space: &TextMetrics, tall: &TextMetrics) -> Option<(f64, f64)> {
+    if space.font_bounding_box_ascent + space.font_bounding_box_descent > 0.0 {
+        Some((
+            space.font_bounding_box_ascent,
+            space.font_bounding_box_descent,
+        ))
+    } else if tall.actual_bounding_box_ascent + tall.actual_bounding_box_descent > 0.0 {
+        Some((
+            tall.actual_bounding_box_ascent,
+            tall.actual_bounding_box_descent,
+        ))
+    } else {
+        None
+    }
+}
+
+// scales the font size in a css font shorthand, i.e. "bold 20px/1.5 sans-serif"
+fn scale_font(font: &str, scale: f64) -> Result<String> {
+    let mut tokens: Vec<String> = font.split_whitespace().map(|o| o.to_string()).collect();
+    for token in tokens.iter_mut() {
+        let (size, line_height) = match token.find('/') {
+            Some(i) => token.split_at(i),
+            None => (&token[..], ""),
+        };
+        for unit in &["px", "pt", "rem", "em", "%"] {
+            if !size.ends_with(unit) {
+                continue;
+            }
+            if let Ok(value) = size[..size.len() - unit.len()].parse::<f64>() {
+                *token = format!("{}{}{}", value * scale, unit, line_height);
+                return Ok(tokens.join(" "));
+            }
+        }
+    }
+    Err(canvas_error!("no font size found in font: {}", font))
+}
+
+// scales a css length used for letter or word spacing, except for those relative to the font's size which follow it
+fn scale_spacing(spacing: &str, scale: f64) -> String {
+    let split = spacing
+        .find(|c: char| !(c.is_ascii_digit() || c == '.' || c == '-' || c == '+'))
+        .unwrap_or(spacing.len());
+    let (value, unit) = spacing.split_at(split);
+    if ["em", "ex", "ch", "cap", "ic", "lh"].contains(&unit) {
+        return spacing.to_string();
+    }
+    match value.parse::<f64>() {
+        Ok(value) => format!("{}{}", value * scale, unit),
+        Err(_) => spacing.to_string(),
+    }
+}
+
+impl Canvas2D {
+    async fn arrange_text(
+        &mut self,
+        text: &str,
+        layout: &TextLayout,
+    ) -> Result<(Vec<LineBox>, f64)> {
+        let words: HashSet<&str> = text.split_whitespace().collect();
+        let mut texts: Vec<String> = words.iter().map(|word| word.to_string()).collect();
+        texts.push(" ".to_string());
+        texts.push(ELLIPSIS.to_string());
+        texts.push(TALL_TEXT.to_string());
+        let mut metrics = self
+            .measure_batch(texts.clone(), Some(layout.font.clone()))
+            .await?;
+        let tall = metrics.pop().unwrap();
+        let ellipsis = metrics.pop().unwrap();
+        let space = metrics.pop().unwrap();
+        texts.truncate(metrics.len());
+        let (ascent, descent) = font_extent(&space, &tall).ok_or_else(|| {
+            canvas_error!("the browser reported no height for font {}", layout.font) as Error
+        })?;
+        let measurements = Measurements {
+            widths: texts
+                .into_iter()
+                .zip(metrics.iter().map(|metrics| metrics.width))
+                .collect(),
+            space: space.width,
+            ellipsis: ellipsis.width,
+            ascent,
+            descent,
+        };
+        let rtl = self.get_text_direction() == TextDirection::Rtl;
+        Ok(arrange(text, layout, &measurements, rtl))
+    }
+
+    // computes where each line of `text` goes in the layout's box without drawing anything
+    pub async fn layout_text(&mut self, text: &str, layout: &TextLayout) -> Result<Vec<LineBox>> {
+        Ok(self.arrange_text(text, layout).await?.0)
+    }
+
+    // lays out and fills `text` with the current fill style, drawing every line in a single message
+    pub async fn fill_text_layout(
+        &mut self,
+        text: &str,
+        layout: &TextLayout,
+    ) -> Result<Vec<LineBox>> {
+        let (lines, scale) = self.arrange_text(text, layout).await?;
+        // the widths were measured at full size, so the spacing has to shrink along with the font to match them
+        let (font, letter_spacing, word_spacing) = if scale < 1.0 {
+            (
+                scale_font(&layout.font, scale)?,
+                Some(scale_spacing(self.get_letter_spacing(), scale)),
+                Some(scale_spacing(self.get_word_spacing(), scale)),
+            )
+        } else {
+            (layout.font.clone(), None, None)
+        };
+        let clip = if layout.overflow == TextOverflow::Clip {
+            Some((layout.x, layout.y, layout.width, layout.height))
+        } else {
+            None
+        };
+        map_js_error(
+            self.send(WSMessageData::FillTextLines {
+                font,
+                lines: lines
+                    .iter()
+                    .map(|line| (line.text.clone(), line.x, line.baseline))
+                    .collect(),
+                clip,
+                letter_spacing,
+                word_spacing,
+            })
+            .await,
+        )?;
+        Ok(lines)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // every character is 10 wide, and lines are 10 high
+    fn measure(text: &str) -> Measurements {
+        Measurements {
+            widths: text
+                .split_whitespace()
+                .map(|word| (word.to_string(), word.chars().count() as f64 * 10.0))
+                .collect(),
+            space: 10.0,
+            ellipsis: 10.0,
+            ascent: 8.0,
+            descent: 2.0,
+        }
+    }
+
+    fn text_metrics(font: (f64, f64), actual: (f64, f64)) -> TextMetrics {
+        TextMetrics {
+            width: 10.0,
+            actual_bounding_box_left: 0.0,
+            actual_bounding_box_right: 10.0,
+            font_bounding_box_ascent: font.0,
+            font_bounding_box_descent: font.1,
+            actual_bounding_box_ascent: actual.0,
+            actual_bounding_box_descent: actual.1,
+            em_height_ascent: 0.0,
+            em_height_descent: 0.0,
+            hanging_baseline: 0.0,
+            alphabetic_baseline: 0.0,
+            ideographic_baseline: 0.0,
+        }
+    }
+
+    fn texts(lines: &[LineBox]) -> Vec<&str> {
+        lines.iter().map(|line| &*line.text).collect()
+    }
+
+    #[test]
+    fn wraps_words() {
+        let text = "aa bb cc\ndd";
+        let layout = TextLayout::new(0.0, 0.0, 50.0, 100.0, "10px sans-serif").line_height(1.0);
+        let (lines, scale) = arrange(text, &layout, &measure(text), false);
+        assert_eq!(texts(&lines), vec!["aa bb", "cc", "dd"]);
+        assert_eq!(scale, 1.0);
+        assert_eq!(lines[1].y, 10.0);
+        assert_eq!(lines[1].baseline, 18.0);
+    }
+
+    #[test]
+    fn aligns_lines() {
+        let text = "aa";
+        let layout =
+            TextLayout::new(0.0, 0.0, 50.0, 100.0, "10px sans-serif").align(TextAlign::Center);
+        let (lines, _) = arrange(text, &layout, &measure(text), false);
+        assert_eq!(lines[0].x, 15.0);
+
+        let layout = TextLayout::new(0.0, 0.0, 50.0, 100.0, "10px sans-serif");
+        let (lines, _) = arrange(text, &layout, &measure(text), false);
+        assert_eq!(lines[0].x, 0.0);
+        // start is the right edge for right to left text, and end the left
+        let (lines, _) = arrange(text, &layout, &measure(text), true);
+        assert_eq!(lines[0].x, 30.0);
+        let layout = layout.align(TextAlign::End);
+        let (lines, _) = arrange(text, &layout, &measure(text), true);
+        assert_eq!(lines[0].x, 0.0);
+        let layout = layout.align(TextAlign::Left);
+        let (lines, _) = arrange(text, &layout, &measure(text), true);
+        assert_eq!(lines[0].x, 0.0);
+    }
+
+    #[test]
+    fn truncates_with_ellipsis() {
+        let text = "aa bb cc dd ee";
+        let layout = TextLayout::new(0.0, 0.0, 50.0, 20.0, "10px sans-serif")
+            .line_height(1.0)
+            .overflow(TextOverflow::Ellipsis);
+        let (lines, _) = arrange(text, &layout, &measure(text), false);
+        assert_eq!(texts(&lines), vec!["aa bb", "cc\u{2026}"]);
+    }
+
+    #[test]
+    fn shrinks_to_fit() {
+        let text = "aa bb cc dd";
+        let layout = TextLayout::new(0.0, 0.0, 50.0, 10.0, "10px sans-serif")
+            .line_height(1.0)
+            .overflow(TextOverflow::ShrinkToFit);
+        let (lines, scale) = arrange(text, &layout, &measure(text), false);
+        assert!(scale < 1.0);
+        assert!(lines.len() as f64 * lines[0].height <= 10.0);
+        assert!(lines.iter().all(|line| line.width <= 50.0));
+        assert_eq!(
+            scale_font("bold 20px/1.5 sans-serif", 0.5).unwrap(),
+            "bold 10px/1.5 sans-serif"
+        );
+        assert_eq!(scale_spacing("4px", 0.5), "2px");
+        assert_eq!(scale_spacing("-1.5pt", 0.5), "-0.75pt");
+        assert_eq!(scale_spacing("0.1em", 0.5), "0.1em");
+        assert_eq!(scale_spacing("normal", 0.5), "normal");
+    }
+
+    #[test]
+    fn falls_back_to_glyph_extent() {
+        let space = text_metrics((8.0, 2.0), (0.0, 0.0));
+        let tall = text_metrics((8.0, 2.0), (7.0, 2.0));
+        assert_eq!(font_extent(&space, &tall), Some((8.0, 2.0)));
+        // fontBoundingBox* unsupported
+        let space = text_metrics((0.0, 0.0), (0.0, 0.0));
+        let tall = text_metrics((0.0, 0.0), (7.0, 2.0));
+        assert_eq!(font_extent(&space, &tall), Some((7.0, 2.0)));
+        assert_eq!(font_extent(&space, &space), None);
+    }
+}
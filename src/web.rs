@@ -1,5 +1,7 @@
+use crate::metrics::Metrics;
 use actix_web::{http, web, App, HttpRequest, HttpResponse, HttpServer, Responder};
-use log::*;
+use std::sync::Arc;
+use tracing::info;
 
 async fn serve(req: HttpRequest) -> impl Responder {
     let websocket_addr: String = req.app_data::<String>().unwrap().clone();
@@ -15,14 +17,31 @@ async fn serve_script(_req: HttpRequest) -> impl Responder {
         .body(include_str!("./client.js"))
 }
 
-pub(crate) async fn start_server(web_addr: String, websocket_addr: String) {
+async fn serve_metrics(req: HttpRequest) -> impl Responder {
+    let metrics = req.app_data::<Arc<Metrics>>().unwrap();
+    HttpResponse::Ok()
+        .content_type("text/plain; version=0.0.4")
+        .body(metrics.render())
+}
+
+pub(crate) async fn start_server(
+    web_addr: String,
+    websocket_addr: String,
+    metrics_path: Option<String>,
+    metrics: Arc<Metrics>,
+) {
     info!("web server started on {}", web_addr);
     let future = {
         HttpServer::new(move || {
-            App::new()
+            let app = App::new()
                 .app_data(websocket_addr.clone())
+                .app_data(metrics.clone())
                 .route("/client.js", web::get().to(serve_script))
-                .route("/", web::get().to(serve))
+                .route("/", web::get().to(serve));
+            match &metrics_path {
+                Some(path) => app.route(path, web::get().to(serve_metrics)),
+                None => app,
+            }
         })
         .bind(web_addr)
         .unwrap()
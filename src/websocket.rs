@@ -1,33 +1,45 @@
+use crate::browser::{Browsers, RegisteredFont};
 use crate::canvas::*;
+use crate::config::BrowserLimits;
+use crate::matrix::DomMatrix;
+use crate::metrics::Metrics;
 use crate::result::*;
 use async_std::sync;
-use futures::future::FutureExt;
+use futures::future::{self, Either, Fuse, FutureExt};
 use futures::select;
-use futures::stream::SelectAll;
+use futures::stream::{self, BoxStream, SelectAll};
 use futures::SinkExt;
 use futures::StreamExt;
-use log::*;
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet, VecDeque};
 use std::net::SocketAddr;
-use std::time::Duration;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
 use tokio::net::{TcpListener, TcpStream};
-use tokio::sync::mpsc;
+use tokio::sync::{mpsc, oneshot, Notify};
 use tokio::time::delay_for;
+use tracing::{debug, debug_span, error, field, info, info_span, warn, Instrument, Span};
 use tungstenite::protocol::Message;
 use uuid::Uuid;
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub(crate) enum WSMessageData {
     Init2DCanvas {
         data: ContextData2D,
         width: u32,
         height: u32,
     },
-    Destroy2DCanvas {},
+    // only answered when `confirm` is set
+    Destroy2DCanvas {
+        confirm: bool,
+    },
     MeasureText {
         text: String,
     },
+    MeasureTexts {
+        texts: Vec<String>,
+        font: Option<String>, // measured with the current font if not set
+    },
     SetLineWidth {
         line_width: f64,
     },
@@ -47,6 +59,12 @@ pub(crate) enum WSMessageData {
     SetLineDashOffset {
         line_dash_offset: f64,
     },
+    ApplyState {
+        state: DrawStateDelta,
+    },
+    SetStrictMode {
+        strict: bool,
+    },
     SetFont {
         font: String,
     },
@@ -59,6 +77,24 @@ pub(crate) enum WSMessageData {
     SetTextDirection {
         text_direction: TextDirection,
     },
+    SetLetterSpacing {
+        letter_spacing: String,
+    },
+    SetWordSpacing {
+        word_spacing: String,
+    },
+    SetFontKerning {
+        font_kerning: FontKerning,
+    },
+    SetFontStretch {
+        font_stretch: FontStretch,
+    },
+    SetFontVariantCaps {
+        font_variant_caps: FontVariantCaps,
+    },
+    SetTextRendering {
+        text_rendering: TextRendering,
+    },
     SetFillStyle {
         fill_style: String,
     },
@@ -98,6 +134,11 @@ pub(crate) enum WSMessageData {
     SetHeight {
         height: i32,
     },
+    Resize {
+        width: u32,
+        height: u32,
+        preserve_content: bool,
+    },
     ClearRect {
         x: f64,
         y: f64,
@@ -128,7 +169,20 @@ pub(crate) enum WSMessageData {
         y: f64,
         max_width: Option<f64>,
     },
+    FillTextLines {
+        font: String,
+        lines: Vec<(String, f64, f64)>, // text, x, alphabetic baseline y
+        clip: Option<(f64, f64, f64, f64)>,
+        // set when the font was shrunk to fit, missing from older recordings
+        #[serde(default)]
+        letter_spacing: Option<String>,
+        #[serde(default)]
+        word_spacing: Option<String>,
+    },
     CreateLinearGradient {
+        // missing from recordings made before gradients could be used as styles
+        #[serde(default = "Uuid::nil")]
+        gradient_uuid: Uuid,
         x0: f64,
         y0: f64,
         x1: f64,
@@ -136,6 +190,8 @@ pub(crate) enum WSMessageData {
         stops: Vec<(f64, String)>,
     },
     CreateRadialGradient {
+        #[serde(default = "Uuid::nil")]
+        gradient_uuid: Uuid,
         x0: f64,
         y0: f64,
         r0: f64,
@@ -144,6 +200,14 @@ pub(crate) enum WSMessageData {
         r1: f64,
         stops: Vec<(f64, String)>,
     },
+    CreateConicGradient {
+        #[serde(default = "Uuid::nil")]
+        gradient_uuid: Uuid,
+        start_angle: f64,
+        x: f64,
+        y: f64,
+        stops: Vec<(f64, String)>,
+    },
     CreatePattern {
         url: String,
         repitition: PatternRepitition,
@@ -215,6 +279,14 @@ pub(crate) enum WSMessageData {
         width: f64,
         height: f64,
     },
+    RoundRect {
+        path_uuid: Option<Uuid>,
+        x: f64,
+        y: f64,
+        width: f64,
+        height: f64,
+        radii: CornerRadii,
+    },
     Fill {
         path_uuid: Option<Uuid>,
         is_even_odd: bool,
@@ -237,7 +309,6 @@ pub(crate) enum WSMessageData {
         x: f64,
         y: f64,
     },
-    GetTransform {},
     Rotate {
         angle: f64,
     },
@@ -262,6 +333,26 @@ pub(crate) enum WSMessageData {
         dy: f64,
         d_dims: Option<(f64, f64)>, // d_width, d_height
     },
+    DrawCanvas {
+        source_context: Uuid,
+        source: Option<(f64, f64, f64, f64)>, // sx, sy, s_width, s_height
+        dest: (f64, f64, f64, f64),           // dx, dy, d_width, d_height
+    },
+    DrawImageData {
+        image_data: ImageData,
+        dest: (f64, f64, f64, f64), // dx, dy, d_width, d_height
+    },
+    RegisterFont {
+        family: String,
+        source: FontSource,
+        descriptors: FontDescriptors,
+    },
+    PreloadImages {
+        urls: Vec<String>,
+    },
+    EvictImages {
+        urls: Option<Vec<String>>,
+    },
     GetImageData {
         sx: f64,
         sy: f64,
@@ -275,12 +366,114 @@ pub(crate) enum WSMessageData {
         dirty_pos: Option<(f64, f64)>,
         dirty_dims: Option<(f64, f64)>,
     },
+    // run in order with a single response, stopping at the first error
+    Batch {
+        ops: Vec<WSMessageData>,
+    },
     Save {},
     Restore {},
+    Reset {},
+    GetContextAttributes {},
+    IsContextLost {},
     ToDataUrl {
-        mime_type: String,
+        format: ImageFormat,
         quality: Option<f64>,
     },
+    Export {
+        format: ImageFormat,
+        quality: Option<f64>,
+    },
+}
+
+impl WSMessageData {
+    pub(crate) fn name(&self) -> &'static str {
+        match self {
+            WSMessageData::Init2DCanvas { .. } => "Init2DCanvas",
+            WSMessageData::Destroy2DCanvas { .. } => "Destroy2DCanvas",
+            WSMessageData::MeasureText { .. } => "MeasureText",
+            WSMessageData::MeasureTexts { .. } => "MeasureTexts",
+            WSMessageData::SetLineWidth { .. } => "SetLineWidth",
+            WSMessageData::SetLineCap { .. } => "SetLineCap",
+            WSMessageData::SetLineJoin { .. } => "SetLineJoin",
+            WSMessageData::SetMiterLimit { .. } => "SetMiterLimit",
+            WSMessageData::GetLineDash { .. } => "GetLineDash",
+            WSMessageData::SetLineDash { .. } => "SetLineDash",
+            WSMessageData::SetLineDashOffset { .. } => "SetLineDashOffset",
+            WSMessageData::ApplyState { .. } => "ApplyState",
+            WSMessageData::SetStrictMode { .. } => "SetStrictMode",
+            WSMessageData::SetFont { .. } => "SetFont",
+            WSMessageData::SetTextAlign { .. } => "SetTextAlign",
+            WSMessageData::SetTextBaseline { .. } => "SetTextBaseline",
+            WSMessageData::SetTextDirection { .. } => "SetTextDirection",
+            WSMessageData::SetLetterSpacing { .. } => "SetLetterSpacing",
+            WSMessageData::SetWordSpacing { .. } => "SetWordSpacing",
+            WSMessageData::SetFontKerning { .. } => "SetFontKerning",
+            WSMessageData::SetFontStretch { .. } => "SetFontStretch",
+            WSMessageData::SetFontVariantCaps { .. } => "SetFontVariantCaps",
+            WSMessageData::SetTextRendering { .. } => "SetTextRendering",
+            WSMessageData::SetFillStyle { .. } => "SetFillStyle",
+            WSMessageData::SetStrokeStyle { .. } => "SetStrokeStyle",
+            WSMessageData::SetShadowBlur { .. } => "SetShadowBlur",
+            WSMessageData::SetShadowColor { .. } => "SetShadowColor",
+            WSMessageData::SetShadowOffsetX { .. } => "SetShadowOffsetX",
+            WSMessageData::SetShadowOffsetY { .. } => "SetShadowOffsetY",
+            WSMessageData::SetGlobalAlpha { .. } => "SetGlobalAlpha",
+            WSMessageData::SetGlobalCompositeOperation { .. } => "SetGlobalCompositeOperation",
+            WSMessageData::SetImageSmoothingEnabled { .. } => "SetImageSmoothingEnabled",
+            WSMessageData::SetImageSmoothingQuality { .. } => "SetImageSmoothingQuality",
+            WSMessageData::SetFilter { .. } => "SetFilter",
+            WSMessageData::SetWidth { .. } => "SetWidth",
+            WSMessageData::SetHeight { .. } => "SetHeight",
+            WSMessageData::Resize { .. } => "Resize",
+            WSMessageData::ClearRect { .. } => "ClearRect",
+            WSMessageData::FillRect { .. } => "FillRect",
+            WSMessageData::StrokeRect { .. } => "StrokeRect",
+            WSMessageData::FillText { .. } => "FillText",
+            WSMessageData::StrokeText { .. } => "StrokeText",
+            WSMessageData::FillTextLines { .. } => "FillTextLines",
+            WSMessageData::CreateLinearGradient { .. } => "CreateLinearGradient",
+            WSMessageData::CreateRadialGradient { .. } => "CreateRadialGradient",
+            WSMessageData::CreateConicGradient { .. } => "CreateConicGradient",
+            WSMessageData::CreatePattern { .. } => "CreatePattern",
+            WSMessageData::BeginPath { .. } => "BeginPath",
+            WSMessageData::ClosePath { .. } => "ClosePath",
+            WSMessageData::MoveTo { .. } => "MoveTo",
+            WSMessageData::LineTo { .. } => "LineTo",
+            WSMessageData::BezierCurveTo { .. } => "BezierCurveTo",
+            WSMessageData::QuadraticCurveTo { .. } => "QuadraticCurveTo",
+            WSMessageData::Arc { .. } => "Arc",
+            WSMessageData::ArcTo { .. } => "ArcTo",
+            WSMessageData::Ellipse { .. } => "Ellipse",
+            WSMessageData::Rect { .. } => "Rect",
+            WSMessageData::RoundRect { .. } => "RoundRect",
+            WSMessageData::Fill { .. } => "Fill",
+            WSMessageData::Stroke { .. } => "Stroke",
+            WSMessageData::Clip { .. } => "Clip",
+            WSMessageData::IsPointInPath { .. } => "IsPointInPath",
+            WSMessageData::IsPointInStroke { .. } => "IsPointInStroke",
+            WSMessageData::Rotate { .. } => "Rotate",
+            WSMessageData::Scale { .. } => "Scale",
+            WSMessageData::Translate { .. } => "Translate",
+            WSMessageData::Transform { .. } => "Transform",
+            WSMessageData::SetTransform { .. } => "SetTransform",
+            WSMessageData::DrawImage { .. } => "DrawImage",
+            WSMessageData::DrawCanvas { .. } => "DrawCanvas",
+            WSMessageData::DrawImageData { .. } => "DrawImageData",
+            WSMessageData::RegisterFont { .. } => "RegisterFont",
+            WSMessageData::PreloadImages { .. } => "PreloadImages",
+            WSMessageData::EvictImages { .. } => "EvictImages",
+            WSMessageData::GetImageData { .. } => "GetImageData",
+            WSMessageData::SetImageData { .. } => "SetImageData",
+            WSMessageData::Batch { .. } => "Batch",
+            WSMessageData::Save { .. } => "Save",
+            WSMessageData::Restore { .. } => "Restore",
+            WSMessageData::Reset { .. } => "Reset",
+            WSMessageData::GetContextAttributes { .. } => "GetContextAttributes",
+            WSMessageData::IsContextLost { .. } => "IsContextLost",
+            WSMessageData::ToDataUrl { .. } => "ToDataUrl",
+            WSMessageData::Export { .. } => "Export",
+        }
+    }
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -288,11 +481,19 @@ pub(crate) enum WsMessageResponse {
     JsError { message: String },
     Success {},
     MeasureText { text_metrics: TextMetrics },
+    MeasureTexts { text_metrics: Vec<TextMetrics> },
     LineDash { line_dash: Vec<f64> },
     IsPointIn { is_in: bool },
-    Transform { matrix: DomMatrix },
+    // the browser normalized value of a property, in strict mode
+    Property { value: String },
+    // keyed by DrawState field name, in strict mode
+    Properties { values: HashMap<String, String> },
+    ContextAttributes { attributes: ContextData2D },
+    ContextLost { is_lost: bool },
     ImageData { image_data: ImageData },
     DataUrl { data_url: String },
+    // received as a binary frame rather than json
+    Blob { data: Vec<u8> },
 }
 
 #[derive(Debug)]
@@ -300,6 +501,202 @@ pub(crate) struct WSMessage {
     pub data: WSMessageData,
     pub context: Uuid,
     pub response: Option<sync::Sender<WsMessageResponse>>,
+    // the op's span, under its canvas', which gets the txn and timings once it's sent and answered
+    pub span: Span,
+    pub queued: Instant,
+}
+
+impl WSMessage {
+    pub(crate) fn new(
+        context: Uuid,
+        data: WSMessageData,
+        response: Option<sync::Sender<WsMessageResponse>>,
+        parent: &Span,
+    ) -> WSMessage {
+        let span = debug_span!(
+            parent: parent,
+            "send",
+            op = data.name(),
+            txn = field::Empty,
+            queue_ms = field::Empty,
+            browser_ms = field::Empty,
+        );
+        WSMessage {
+            data,
+            context,
+            response,
+            span,
+            queued: Instant::now(),
+        }
+    }
+}
+
+// a transaction sent to the browser that hasn't been answered yet
+struct LivingJob {
+    response: sync::Sender<WsMessageResponse>,
+    op: &'static str,
+    sent: Instant,
+    span: Span,
+}
+
+// the browser a context was placed on, or why it never will be
+pub(crate) type Assignment = std::result::Result<Uuid, CanvasRejected>;
+
+// a new context waiting to be picked up by a browser, which reports back its id
+pub(crate) struct ContextRequest {
+    pub receiver: mpsc::Receiver<WSMessage>,
+    pub browser: oneshot::Sender<Assignment>,
+    // counted against the limits of the browser it's placed on. None for contexts that aren't a canvas
+    pub canvas: Option<CanvasPlacement>,
+}
+
+pub(crate) struct CanvasPlacement {
+    pub uuid: Uuid,
+    pub width: u32,
+    pub height: u32,
+    // the only browser the canvas may be placed on
+    pub near: Option<Uuid>,
+}
+
+// the size of every canvas on a browser
+type Canvases = HashMap<Uuid, (u32, u32)>;
+
+// the canvases every connected browser has been given, checked against the limits before one is given more
+pub(crate) struct Loads {
+    limits: BrowserLimits,
+    browsers: Mutex<HashMap<Uuid, Canvases>>,
+    // signalled when a browser connects or has room freed up on it
+    changed: Notify,
+}
+
+impl Loads {
+    pub(crate) fn new(limits: BrowserLimits) -> Loads {
+        Loads {
+            limits,
+            browsers: Mutex::new(HashMap::new()),
+            changed: Notify::new(),
+        }
+    }
+
+    fn connect(&self, browser: Uuid) {
+        self.browsers
+            .lock()
+            .unwrap()
+            .insert(browser, HashMap::new());
+        self.changed.notify();
+    }
+
+    fn disconnect(&self, browser: &Uuid) {
+        self.browsers.lock().unwrap().remove(browser);
+        self.changed.notify();
+    }
+
+    fn fits(&self, canvases: &Canvases, width: u32, height: u32) -> bool {
+        let pixels: u64 = canvases
+            .values()
+            .map(|(width, height)| *width as u64 * *height as u64)
+            .sum();
+        under(canvases.len(), self.limits.max_contexts)
+            && !over(
+                pixels + width as u64 * height as u64,
+                self.limits.max_pixels,
+            )
+    }
+
+    // a canvas that wouldn't fit even on an otherwise empty browser
+    fn too_large(&self, width: u32, height: u32) -> bool {
+        over(width as u64 * height as u64, self.limits.max_pixels)
+    }
+
+    fn can_send(&self, in_flight: usize) -> bool {
+        under(in_flight, self.limits.max_in_flight)
+    }
+
+    // picks the browser with the fewest canvases out of those with room for this one, other than those in `skip`,
+    // and counts it against it
+    fn place(&self, canvas: Option<&CanvasPlacement>, skip: &HashSet<Uuid>) -> Option<Uuid> {
+        let mut browsers = self.browsers.lock().unwrap();
+        let browser = browsers
+            .iter()
+            .filter(|(browser, _)| !skip.contains(browser))
+            .filter(|(browser, canvases)| match canvas {
+                Some(canvas) => {
+                    (canvas.near.is_none() || canvas.near == Some(**browser))
+                        && self.fits(canvases, canvas.width, canvas.height)
+                }
+                None => true,
+            })
+            .min_by_key(|(_, canvases)| canvases.len())
+            .map(|(browser, _)| *browser)?;
+        if let Some(canvas) = canvas {
+            browsers
+                .get_mut(&browser)
+                .unwrap()
+                .insert(canvas.uuid, (canvas.width, canvas.height));
+        }
+        Some(browser)
+    }
+
+    fn is_connected(&self, browser: &Uuid) -> bool {
+        self.browsers.lock().unwrap().contains_key(browser)
+    }
+
+    fn release(&self, browser: &Uuid, context: &Uuid) {
+        self.unreserve(browser, context);
+        self.changed.notify();
+    }
+
+    // takes back a placement that was never sent, which leaves nothing new to place
+    fn unreserve(&self, browser: &Uuid, context: &Uuid) {
+        if let Some(canvases) = self.browsers.lock().unwrap().get_mut(browser) {
+            canvases.remove(context);
+        }
+    }
+
+    // follows canvases being resized and destroyed
+    fn track(&self, browser: &Uuid, context: &Uuid, op: &WSMessageData) {
+        match op {
+            WSMessageData::Destroy2DCanvas { .. }
+            | WSMessageData::SetWidth { .. }
+            | WSMessageData::SetHeight { .. }
+            | WSMessageData::Resize { .. }
+            | WSMessageData::Batch { .. } => (),
+            _ => return,
+        }
+        if let Some(canvases) = self.browsers.lock().unwrap().get_mut(browser) {
+            follow(canvases, context, op);
+        }
+        self.changed.notify();
+    }
+}
+
+fn follow(canvases: &mut Canvases, context: &Uuid, op: &WSMessageData) {
+    match op {
+        WSMessageData::Destroy2DCanvas { .. } => {
+            canvases.remove(context);
+        }
+        WSMessageData::SetWidth { width } => {
+            if let Some(size) = canvases.get_mut(context) {
+                size.0 = (*width).max(0) as u32;
+            }
+        }
+        WSMessageData::SetHeight { height } => {
+            if let Some(size) = canvases.get_mut(context) {
+                size.1 = (*height).max(0) as u32;
+            }
+        }
+        WSMessageData::Resize { width, height, .. } => {
+            if let Some(size) = canvases.get_mut(context) {
+                *size = (*width, *height);
+            }
+        }
+        WSMessageData::Batch { ops } => {
+            for op in ops {
+                follow(canvases, context, op);
+            }
+        }
+        _ => (),
+    }
 }
 
 #[derive(Serialize, Deserialize)]
@@ -318,47 +715,115 @@ struct WSNetResponse {
 async fn handle_connection(
     raw_stream: TcpStream,
     address: SocketAddr,
-    mut receiver: sync::Receiver<mpsc::Receiver<WSMessage>>,
+    browser: Uuid,
+    mut receiver: mpsc::Receiver<ContextRequest>,
+    fonts: Vec<RegisteredFont>,
+    metrics: &Metrics,
+    loads: &Loads,
 ) -> Result<()> {
     debug!("Incoming WebSocket connection from: {}", address);
 
     let mut ws_stream = tokio_tungstenite::accept_async(raw_stream).await?;
     debug!("WebSocket connection established: {}", address);
-    let mut living_jobs: HashMap<Uuid, sync::Sender<WsMessageResponse>> = HashMap::new();
+    let mut living_jobs: HashMap<Uuid, LivingJob> = HashMap::new();
+    // fonts registered before this browser connected, no context is admitted until they've all loaded
+    let mut loading_fonts = HashSet::new();
+    for font in fonts {
+        let txn = Uuid::new_v4();
+        loading_fonts.insert(txn);
+        let data = font.to_message();
+        let op = data.name();
+        let net_msg = WSNetMessage {
+            data,
+            txn_uuid: txn,
+            context: Uuid::nil(),
+        };
+        let text = serde_json::to_string(&net_msg).unwrap();
+        metrics.op_sent(&browser, op, text.len(), true);
+        ws_stream.send(Message::Text(text)).await?;
+        let (sender, receiver) = sync::channel::<WsMessageResponse>(1);
+        living_jobs.insert(
+            txn,
+            LivingJob {
+                response: sender,
+                op,
+                sent: Instant::now(),
+                span: Span::current(),
+            },
+        );
+        let family = font.family;
+        tokio::spawn(async move {
+            if let Some(WsMessageResponse::JsError { message }) = receiver.recv().await {
+                warn!("failed to load font {} in {}: {}", family, address, message);
+            }
+        });
+    }
     let mut contexts = SelectAll::new();
     loop {
+        let sending = loads.can_send(living_jobs.len());
+        let admitting = loading_fonts.is_empty();
         select! {
-            ctx = receiver.next().fuse() => {
+            ctx = if admitting { Either::Left(receiver.next().fuse()) } else { Either::Right(Fuse::<future::Pending<_>>::terminated()) } => {
                 if let Some(ctx) = ctx {
-                    contexts.push(ctx);
-                } else {
-                    break;
+                    admit(ctx, browser, metrics, loads, &mut contexts);
                 }
             },
-            job = contexts.next() => {
-                if let Some(job) = job {
+            event = if sending { Either::Left(contexts.next()) } else { Either::Right(Fuse::<future::Pending<_>>::terminated()) } => {
+                // a canvas is gone once nothing can send to it, whether or not it was destroyed
+                if let Some(ContextEvent::Closed(Some(canvas))) = event {
+                    metrics.canvas_destroyed(&browser);
+                    loads.release(&browser, &canvas);
+                }
+                if let Some(ContextEvent::Message(job)) = event {
+                    loads.track(&browser, &job.context, &job.data);
                     let txn = Uuid::new_v4();
+                    let op = job.data.name();
+                    job.span.record("txn", field::display(txn));
+                    job.span.record("queue_ms", millis(job.queued.elapsed()));
                     let net_msg = WSNetMessage { data: job.data, txn_uuid: txn, context: job.context };
-                    ws_stream.send(Message::Text(serde_json::to_string(&net_msg).unwrap())).await?;
+                    let text = serde_json::to_string(&net_msg).unwrap();
+                    metrics.op_sent(&browser, op, text.len(), job.response.is_some());
+                    ws_stream.send(Message::Text(text)).await?;
                     if let Some(response) = job.response {
-                        living_jobs.insert(txn, response);
+                        living_jobs.insert(txn, LivingJob { response, op, sent: Instant::now(), span: job.span });
                     }
                 }
             },
             msg = ws_stream.next().fuse() => {
-                if let Some(Ok(Message::Text(msg))) = msg {
-                    let msg: WSNetResponse = serde_json::from_str(&msg)?;
-                    let job = living_jobs.remove(&msg.txn_uuid);
-                    match job {
-                        Some(job) => {
-                            job.send(msg.data).await;
-                        },
-                        None => {
-                            return Err(canvas_error!("invalid txn id in response: {:?}", &msg));
+                let msg: WSNetResponse = match msg {
+                    Some(Ok(Message::Text(msg))) => {
+                        metrics.response_received(msg.len());
+                        serde_json::from_str(&msg)?
+                    },
+                    // binary frames are a 16 byte txn uuid followed by the payload
+                    Some(Ok(Message::Binary(mut msg))) if msg.len() >= 16 => {
+                        metrics.response_received(msg.len());
+                        let data = msg.split_off(16);
+                        let txn_uuid = Uuid::from_bytes(&msg)
+                            .map_err(|e| canvas_error!("invalid txn id in binary response: {:?}", e))?;
+                        WSNetResponse {
+                            data: WsMessageResponse::Blob { data },
+                            txn_uuid,
+                        }
+                    },
+                    _ => break,
+                };
+                loading_fonts.remove(&msg.txn_uuid);
+                let job = living_jobs.remove(&msg.txn_uuid);
+                match job {
+                    Some(job) => {
+                        let is_js_error = matches!(msg.data, WsMessageResponse::JsError { .. });
+                        let elapsed = job.sent.elapsed();
+                        metrics.op_answered(&browser, job.op, elapsed, is_js_error);
+                        job.span.record("browser_ms", millis(elapsed));
+                        if let WsMessageResponse::JsError { message } = &msg.data {
+                            debug!(parent: &job.span, "javascript error: {}", message);
                         }
+                        job.response.send(msg.data).await;
+                    },
+                    None => {
+                        return Err(canvas_error!("invalid txn id in response: {:?}", &msg));
                     }
-                } else {
-                    break;
                 }
             },
         }
@@ -368,13 +833,162 @@ async fn handle_connection(
     Ok(())
 }
 
+fn under<T: PartialOrd>(value: T, limit: Option<T>) -> bool {
+    match limit {
+        Some(limit) => value < limit,
+        None => true,
+    }
+}
+
+fn over<T: PartialOrd>(value: T, limit: Option<T>) -> bool {
+    match limit {
+        Some(limit) => value > limit,
+        None => false,
+    }
+}
+
+// what a context's channel yields to its browser's connection, its messages and then that it's closed. messages
+// aren't boxed as there's only one Closed for every context
+#[allow(clippy::large_enum_variant)]
+enum ContextEvent {
+    Message(WSMessage),
+    // with the canvas' uuid, None for contexts that aren't a canvas
+    Closed(Option<Uuid>),
+}
+
+fn admit(
+    ctx: ContextRequest,
+    browser: Uuid,
+    metrics: &Metrics,
+    loads: &Loads,
+    contexts: &mut SelectAll<BoxStream<'static, ContextEvent>>,
+) {
+    // there's room in this browser's queue again for contexts that found it full
+    loads.changed.notify();
+    let canvas = ctx.canvas.map(|canvas| canvas.uuid);
+    // the creator may have given up waiting, in which case nothing will use or destroy the canvas
+    if ctx.browser.send(Ok(browser)).is_err() {
+        if let Some(canvas) = canvas {
+            loads.release(&browser, &canvas);
+        }
+        return;
+    }
+    if canvas.is_some() {
+        metrics.canvas_created(&browser);
+    }
+    let closed = stream::once(future::ready(ContextEvent::Closed(canvas)));
+    contexts.push(
+        ctx.receiver
+            .map(ContextEvent::Message)
+            .chain(closed)
+            .boxed(),
+    );
+}
+
+// places each new canvas on a browser with room for it, keeping it until one has room
+async fn dispatch(
+    mut receiver: sync::Receiver<ContextRequest>,
+    browsers: Arc<Browsers>,
+    loads: Arc<Loads>,
+) {
+    let mut waiting: VecDeque<ContextRequest> = VecDeque::new();
+    loop {
+        // browsers whose queue was full or closed, they're skipped until the next pass
+        let mut full = HashSet::new();
+        for ctx in std::mem::take(&mut waiting) {
+            if let Some(ctx) = place(ctx, &browsers, &loads, &mut full) {
+                waiting.push_back(ctx);
+            }
+        }
+        let changed = loads.changed.notified();
+        futures::pin_mut!(changed);
+        select! {
+            ctx = receiver.next().fuse() => match ctx {
+                Some(ctx) => waiting.push_back(ctx),
+                None => break,
+            },
+            _ = changed.fuse() => (),
+        }
+    }
+}
+
+// sends the context to a browser without waiting on it, or hands it back if no browser has room for it yet
+fn place(
+    ctx: ContextRequest,
+    browsers: &Browsers,
+    loads: &Loads,
+    full: &mut HashSet<Uuid>,
+) -> Option<ContextRequest> {
+    // its creator gave up waiting
+    if ctx.browser.is_closed() {
+        return None;
+    }
+    if let Some(canvas) = &ctx.canvas {
+        let rejection = if loads.too_large(canvas.width, canvas.height) {
+            Some(CanvasRejected::TooLarge {
+                width: canvas.width,
+                height: canvas.height,
+            })
+        } else {
+            match canvas.near {
+                Some(near) if !loads.is_connected(&near) => {
+                    Some(CanvasRejected::BrowserGone { browser: near })
+                }
+                _ => None,
+            }
+        };
+        if let Some(rejection) = rejection {
+            warn!("rejected canvas {}: {}", canvas.uuid, rejection);
+            ctx.browser.send(Err(rejection)).unwrap_or(());
+            return None;
+        }
+    }
+    let mut ctx = ctx;
+    loop {
+        let browser = match loads.place(ctx.canvas.as_ref(), full) {
+            Some(browser) => browser,
+            None => return Some(ctx),
+        };
+        ctx = match browsers.get(&browser) {
+            Some(mut sender) => match sender.try_send(ctx) {
+                Ok(()) => return None,
+                Err(mpsc::error::TrySendError::Full(ctx)) => ctx,
+                // disconnecting
+                Err(mpsc::error::TrySendError::Closed(ctx)) => ctx,
+            },
+            None => ctx,
+        };
+        full.insert(browser);
+        if let Some(canvas) = &ctx.canvas {
+            loads.unreserve(&browser, &canvas.uuid);
+        }
+    }
+}
+
+fn millis(duration: Duration) -> f64 {
+    duration.as_secs_f64() * 1000.0
+}
+
 // deals with non-Send Errors
 async fn handle_connection_wrapper(
     raw_stream: TcpStream,
     address: SocketAddr,
-    receiver: sync::Receiver<mpsc::Receiver<WSMessage>>,
+    browsers: Arc<Browsers>,
+    metrics: Arc<Metrics>,
+    loads: Arc<Loads>,
 ) -> Result<()> {
-    let result = handle_connection(raw_stream, address, receiver).await;
+    let (browser, receiver) = browsers.register();
+    let fonts = browsers.fonts();
+    metrics.browser_connected(browser);
+    loads.connect(browser);
+    let result = handle_connection(
+        raw_stream, address, browser, receiver, fonts, &metrics, &loads,
+    )
+    .instrument(info_span!("browser", browser = %browser, address = %address))
+    .await;
+    loads.disconnect(&browser);
+    browsers.unregister(&browser);
+    metrics.browser_disconnected(&browser);
     match result {
         Err(e) => {
             warn!("Error in websocket connection: {:?}", e);
@@ -386,10 +1000,15 @@ async fn handle_connection_wrapper(
 
 pub(crate) async fn start_server(
     address: String,
-    receiver: sync::Receiver<mpsc::Receiver<WSMessage>>,
+    receiver: sync::Receiver<ContextRequest>,
+    browsers: Arc<Browsers>,
+    metrics: Arc<Metrics>,
+    limits: BrowserLimits,
 ) -> Result<()> {
+    let loads = Arc::new(Loads::new(limits));
     let mut listener = TcpListener::bind(&address).await?;
     info!("websocket server listening on: {}", address);
+    tokio::spawn(dispatch(receiver, browsers.clone(), loads.clone()));
 
     loop {
         let result = listener.accept().await;
@@ -402,6 +1021,361 @@ pub(crate) async fn start_server(
             continue;
         }
         let (stream, addr) = result.unwrap();
-        tokio::spawn(handle_connection_wrapper(stream, addr, receiver.clone()));
+        tokio::spawn(handle_connection_wrapper(
+            stream,
+            addr,
+            browsers.clone(),
+            metrics.clone(),
+            loads.clone(),
+        ));
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    // how a fake browser answers an op
+    pub(crate) enum FakeAnswer {
+        Data(serde_json::Value),
+        // sent as a binary frame, after the txn uuid
+        Binary(Vec<u8>),
+        Later(Duration, Box<FakeAnswer>),
+        Nothing,
+    }
+
+    #[derive(Debug, PartialEq)]
+    pub(crate) enum FakeEvent {
+        Received(String),
+        Answered(String),
+    }
+
+    // answers every op with success like a browser would, except for unconfirmed destroys
+    pub(crate) fn succeed(op: &str, packet: &serde_json::Value) -> FakeAnswer {
+        if op == "Destroy2DCanvas" && packet["data"][op]["confirm"] == false {
+            return FakeAnswer::Nothing;
+        }
+        FakeAnswer::Data(serde_json::json!({ "Success": {} }))
+    }
+
+    pub(crate) fn measured(width: f64) -> FakeAnswer {
+        let mut text_metrics = serde_json::json!({ "width": width });
+        for metric in &[
+            "actualBoundingBoxLeft",
+            "actualBoundingBoxRight",
+            "fontBoundingBoxAscent",
+            "fontBoundingBoxDescent",
+            "actualBoundingBoxAscent",
+            "actualBoundingBoxDescent",
+            "emHeightAscent",
+            "emHeightDescent",
+            "hangingBaseline",
+            "alphabeticBaseline",
+            "ideographicBaseline",
+        ] {
+            text_metrics[*metric] = serde_json::json!(0.0);
+        }
+        FakeAnswer::Data(serde_json::json!({ "MeasureText": { "text_metrics": text_metrics } }))
+    }
+
+    // passes on the name of each op the fake browser was sent
+    pub(crate) fn fake_browser(address: &'static str) -> std::sync::mpsc::Receiver<String> {
+        let (sender, receiver) = std::sync::mpsc::channel();
+        serve_fake_browser(address, succeed, move |event| {
+            if let FakeEvent::Received(op) = event {
+                sender.send(op).unwrap_or(());
+            }
+        });
+        receiver
+    }
+
+    // passes on each op the fake browser was sent and each answer as it's sent back
+    pub(crate) fn fake_browser_with<F>(
+        address: &'static str,
+        respond: F,
+    ) -> std::sync::mpsc::Receiver<FakeEvent>
+    where
+        F: Fn(&str, &serde_json::Value) -> FakeAnswer + Send + 'static,
+    {
+        let (sender, receiver) = std::sync::mpsc::channel();
+        serve_fake_browser(address, respond, move |event| {
+            sender.send(event).unwrap_or(());
+        });
+        receiver
+    }
+
+    fn serve_fake_browser<F, R>(address: &'static str, respond: F, report: R)
+    where
+        F: Fn(&str, &serde_json::Value) -> FakeAnswer + Send + 'static,
+        R: Fn(FakeEvent) + Clone + Send + 'static,
+    {
+        std::thread::spawn(move || {
+            let mut runtime = tokio::runtime::Runtime::new().unwrap();
+            runtime.block_on(async move {
+                // the server may not be listening yet
+                let stream = loop {
+                    match tokio::net::TcpStream::connect(address).await {
+                        Ok(stream) => break stream,
+                        Err(_) => delay_for(Duration::from_millis(10)).await,
+                    }
+                };
+                let url = format!("ws://{}/", address);
+                let (websocket, _) = tokio_tungstenite::client_async(&*url, stream)
+                    .await
+                    .unwrap();
+                let (mut sink, mut stream) = websocket.split();
+                // answers may be sent out of order, like a browser's awaiting ones
+                let (answers, mut answered) = futures::channel::mpsc::unbounded::<Message>();
+                tokio::spawn(async move {
+                    while let Some(answer) = answered.next().await {
+                        if sink.send(answer).await.is_err() {
+                            break;
+                        }
+                    }
+                });
+                while let Some(Ok(Message::Text(text))) = stream.next().await {
+                    let packet: serde_json::Value = serde_json::from_str(&text).unwrap();
+                    let op = packet["data"].as_object().unwrap().keys().next().unwrap();
+                    let op = op.clone();
+                    report(FakeEvent::Received(op.clone()));
+                    let mut answer = respond(&op, &packet);
+                    let txn = packet["txn_uuid"].clone();
+                    let answers = answers.clone();
+                    let report = report.clone();
+                    tokio::spawn(async move {
+                        while let FakeAnswer::Later(delay, later) = answer {
+                            delay_for(delay).await;
+                            answer = *later;
+                        }
+                        let message = match answer {
+                            FakeAnswer::Data(data) => {
+                                let response = serde_json::json!({ "data": data, "txn_uuid": txn });
+                                Message::Text(response.to_string())
+                            }
+                            FakeAnswer::Binary(data) => {
+                                let txn = Uuid::parse_str(txn.as_str().unwrap()).unwrap();
+                                let mut frame = txn.as_bytes().to_vec();
+                                frame.extend(data);
+                                Message::Binary(frame)
+                            }
+                            _ => return,
+                        };
+                        report(FakeEvent::Answered(op));
+                        answers.unbounded_send(message).unwrap_or(());
+                    });
+                }
+            });
+        });
+    }
+
+    fn canvas(width: u32, height: u32) -> CanvasPlacement {
+        CanvasPlacement {
+            uuid: Uuid::new_v4(),
+            width,
+            height,
+            near: None,
+        }
+    }
+
+    #[test]
+    fn loads_follow_limits() {
+        let loads = Loads::new(BrowserLimits {
+            max_contexts: Some(2),
+            max_pixels: Some(100 * 100),
+            max_in_flight: Some(4),
+        });
+        let browser = Uuid::new_v4();
+        let none = HashSet::new();
+        assert!(loads.place(Some(&canvas(10, 10)), &none).is_none());
+        loads.connect(browser);
+        assert!(loads.too_large(101, 100));
+
+        let first = canvas(60, 100);
+        assert_eq!(loads.place(Some(&first), &none), Some(browser));
+        assert!(loads.place(Some(&canvas(50, 100)), &none).is_none());
+        loads.track(
+            &browser,
+            &first.uuid,
+            &WSMessageData::Batch {
+                ops: vec![WSMessageData::SetWidth { width: 50 }],
+            },
+        );
+        assert_eq!(loads.place(Some(&canvas(50, 100)), &none), Some(browser));
+        // at the most contexts, even though there are pixels to spare
+        loads.track(
+            &browser,
+            &first.uuid,
+            &WSMessageData::Resize {
+                width: 10,
+                height: 10,
+                preserve_content: false,
+            },
+        );
+        assert!(loads.place(Some(&canvas(10, 10)), &none).is_none());
+        loads.track(
+            &browser,
+            &first.uuid,
+            &WSMessageData::Destroy2DCanvas { confirm: false },
+        );
+        assert_eq!(loads.place(Some(&canvas(10, 10)), &none), Some(browser));
+
+        assert!(loads.can_send(3));
+        assert!(!loads.can_send(4));
+    }
+
+    fn request(canvas: Option<CanvasPlacement>) -> (ContextRequest, oneshot::Receiver<Assignment>) {
+        let (_, receiver) = mpsc::channel::<WSMessage>(1);
+        let (browser, assignment) = oneshot::channel::<Assignment>();
+        let ctx = ContextRequest {
+            receiver,
+            browser,
+            canvas,
+        };
+        (ctx, assignment)
+    }
+
+    async fn placed(browser: &mut mpsc::Receiver<ContextRequest>) -> Option<Uuid> {
+        tokio::time::timeout(Duration::from_millis(100), browser.recv())
+            .await
+            .ok()
+            .flatten()
+            .map(|ctx| ctx.canvas.unwrap().uuid)
+    }
+
+    // the assignment has to be kept, as nothing is placed for a creator that has given up
+    async fn send(
+        sender: &sync::Sender<ContextRequest>,
+        placement: CanvasPlacement,
+    ) -> oneshot::Receiver<Assignment> {
+        let (ctx, assignment) = request(Some(placement));
+        sender.send(ctx).await;
+        assignment
+    }
+
+    #[tokio::test]
+    async fn routes_canvases_to_browsers_with_room() {
+        let browsers = Arc::new(Browsers::default());
+        let loads = Arc::new(Loads::new(BrowserLimits {
+            max_pixels: Some(100 * 100),
+            ..BrowserLimits::default()
+        }));
+        let (sender, receiver) = sync::channel::<ContextRequest>(8);
+        tokio::spawn(dispatch(receiver, browsers.clone(), loads.clone()));
+
+        let (first, mut first_receiver) = browsers.register();
+        loads.connect(first);
+        let large = canvas(60, 100);
+        let large_uuid = large.uuid;
+        let _large = send(&sender, large).await;
+        assert_eq!(placed(&mut first_receiver).await, Some(large_uuid));
+
+        // doesn't fit next to the first canvas, so it waits for a browser with room
+        let small = canvas(50, 100);
+        let small_uuid = small.uuid;
+        let _small = send(&sender, small).await;
+        assert_eq!(placed(&mut first_receiver).await, None);
+        let (second, mut second_receiver) = browsers.register();
+        loads.connect(second);
+        assert_eq!(placed(&mut second_receiver).await, Some(small_uuid));
+
+        let waiting = canvas(60, 100);
+        let waiting_uuid = waiting.uuid;
+        let _waiting = send(&sender, waiting).await;
+        let too_large = send(&sender, canvas(101, 100)).await;
+        assert_eq!(placed(&mut first_receiver).await, None);
+        assert_eq!(placed(&mut second_receiver).await, None);
+        loads.track(
+            &first,
+            &large_uuid,
+            &WSMessageData::Destroy2DCanvas { confirm: false },
+        );
+        assert_eq!(placed(&mut first_receiver).await, Some(waiting_uuid));
+
+        // the one too large for any browser was rejected
+        assert!(matches!(
+            too_large.await,
+            Ok(Err(CanvasRejected::TooLarge {
+                width: 101,
+                height: 100
+            }))
+        ));
+        let mut near = canvas(10, 10);
+        near.near = Some(Uuid::new_v4());
+        assert!(matches!(
+            send(&sender, near).await.await,
+            Ok(Err(CanvasRejected::BrowserGone { .. }))
+        ));
+    }
+
+    #[tokio::test]
+    async fn skips_browsers_with_full_queues() {
+        let browsers = Arc::new(Browsers::default());
+        let loads = Arc::new(Loads::new(BrowserLimits::default()));
+        let (sender, receiver) = sync::channel::<ContextRequest>(8);
+        tokio::spawn(dispatch(receiver, browsers.clone(), loads.clone()));
+        let (first, mut first_receiver) = browsers.register();
+        loads.connect(first);
+        let mut queue = browsers.get(&first).unwrap();
+        let mut queued = 0;
+        while queue.try_send(request(None).0).is_ok() {
+            queued += 1;
+        }
+
+        // a full browser doesn't hold up the others
+        let (second, mut second_receiver) = browsers.register();
+        loads.connect(second);
+        let placement = canvas(10, 10);
+        let uuid = placement.uuid;
+        let (ctx, _assignment) = request(Some(placement));
+        sender.send(ctx).await;
+        assert_eq!(placed(&mut second_receiver).await, Some(uuid));
+
+        // nor does it stop canvases from being placed on it once it catches up
+        loads.disconnect(&second);
+        let placement = canvas(10, 10);
+        let uuid = placement.uuid;
+        let (ctx, _assignment) = request(Some(placement));
+        sender.send(ctx).await;
+        let ctx = first_receiver.recv().await.unwrap();
+        admit(
+            ctx,
+            first,
+            &Metrics::default(),
+            &loads,
+            &mut SelectAll::new(),
+        );
+        for _ in 1..queued {
+            first_receiver.recv().await.unwrap();
+        }
+        assert_eq!(placed(&mut first_receiver).await, Some(uuid));
+    }
+
+    #[actix_rt::test]
+    async fn loads_fonts_before_any_context() {
+        let generator = crate::start_server("127.0.0.1:7096", "127.0.0.1:7097").unwrap();
+        generator
+            .register_font("Test", vec![0u8], Default::default())
+            .await
+            .unwrap();
+        let events = fake_browser_with("127.0.0.1:7097", |op, packet| match op {
+            "RegisterFont" => {
+                FakeAnswer::Later(Duration::from_millis(200), Box::new(succeed(op, packet)))
+            }
+            _ => succeed(op, packet),
+        });
+        generator
+            .wait_for_browsers(1, Duration::from_secs(5))
+            .await
+            .unwrap();
+        let _canvas = generator
+            .make_2d_canvas(Default::default(), 10, 10)
+            .await
+            .unwrap();
+        let events: Vec<FakeEvent> = events.try_iter().collect();
+        let position = |event: FakeEvent| events.iter().position(|e| *e == event).unwrap();
+        assert!(
+            position(FakeEvent::Answered("RegisterFont".to_string()))
+                < position(FakeEvent::Received("Init2DCanvas".to_string()))
+        );
     }
 }